@@ -0,0 +1,219 @@
+// Copyright 2024 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Counterfactual `AADemo` accounts of the `AADemoFactory` contract.
+//!
+//! The factory deploys each account as an ERC-1167 clone of its
+//! implementation with CREATE2, salted by the claim ID of the owner, and sets
+//! the owner in the same transaction. The address of an account is thus
+//! known before it is deployed, and funds sent to it are kept until the
+//! account is deployed on first use. Each call of an account commits to its
+//! nonce, read with [AccountFactory::account_nonce].
+
+use anyhow::{ensure, Context, Result};
+use ethers::{prelude::*, types::transaction::eip2718::TypedTransaction};
+
+use crate::{claim_id_of, ChainClient, SignerClient};
+
+abigen!(AaDemoFactory, "./src/AADemoFactory.json");
+
+abigen!(
+    AaAccount,
+    r#"[
+        function nonce() external view returns (uint256)
+    ]"#
+);
+
+// Init code of an ERC-1167 clone, around the address of the implementation.
+const CLONE_PREFIX: [u8; 20] = [
+    0x3d, 0x60, 0x2d, 0x80, 0x60, 0x0a, 0x3d, 0x39, 0x81, 0xf3, 0x36, 0x3d, 0x3d, 0x37, 0x3d, 0x3d,
+    0x3d, 0x36, 0x3d, 0x73,
+];
+const CLONE_SUFFIX: [u8; 15] = [
+    0x5a, 0xf4, 0x3d, 0x82, 0x80, 0x3e, 0x90, 0x3d, 0x91, 0x60, 0x2b, 0x57, 0xfd, 0x5b, 0xf3,
+];
+
+/// Returns the address of the account of `claim_id` created by `factory`
+/// from `implementation`, as computed by `AADemoFactory.getAddress`.
+pub fn aa_account_address(
+    factory: Address,
+    implementation: Address,
+    claim_id: [u8; 32],
+) -> Address {
+    let mut init_code = Vec::with_capacity(CLONE_PREFIX.len() + 20 + CLONE_SUFFIX.len());
+    init_code.extend_from_slice(&CLONE_PREFIX);
+    init_code.extend_from_slice(implementation.as_bytes());
+    init_code.extend_from_slice(&CLONE_SUFFIX);
+    ethers::utils::get_create2_address(factory, claim_id, init_code)
+}
+
+/// Client of a deployed `AADemoFactory` contract.
+#[derive(Clone)]
+pub struct AccountFactory {
+    contract: AaDemoFactory<SignerClient>,
+    implementation: Address,
+}
+
+impl AccountFactory {
+    /// Creates a client of the factory at `address`, cloning
+    /// `implementation`, without reading the chain.
+    pub fn new(chain: &ChainClient, address: Address, implementation: Address) -> Self {
+        Self {
+            contract: AaDemoFactory::new(address, chain.client()),
+            implementation,
+        }
+    }
+
+    /// Creates a client of the factory at `address`, reading its
+    /// implementation and checking that the contract derives the same
+    /// account addresses.
+    pub async fn connect(chain: &ChainClient, address: Address) -> Result<Self> {
+        let contract = AaDemoFactory::new(address, chain.client());
+        let implementation = contract
+            .implementation()
+            .call()
+            .await
+            .context("Read factory implementation")?;
+        let factory = Self {
+            contract,
+            implementation,
+        };
+
+        let claim_id = [0u8; 32];
+        let expected = factory
+            .contract
+            .get_address(claim_id)
+            .call()
+            .await
+            .context("Read account address")?;
+        ensure!(
+            factory.account_address(claim_id) == expected,
+            "Factory {address:?} does not derive account addresses as expected"
+        );
+        Ok(factory)
+    }
+
+    pub fn address(&self) -> Address {
+        self.contract.address()
+    }
+
+    /// The `AADemo` contract cloned by the factory.
+    pub fn implementation(&self) -> Address {
+        self.implementation
+    }
+
+    /// The generated bindings, for calls not covered by this client.
+    pub fn contract(&self) -> &AaDemoFactory<SignerClient> {
+        &self.contract
+    }
+
+    /// Returns the address of the account of `claim_id`, deployed or not.
+    pub fn account_address(&self, claim_id: [u8; 32]) -> Address {
+        aa_account_address(self.address(), self.implementation, claim_id)
+    }
+
+    /// Returns the address of the account owned by `email`.
+    pub fn account_of(&self, email: &str) -> Address {
+        self.account_address(claim_id_of(email))
+    }
+
+    /// Returns whether the account of `claim_id` is deployed.
+    pub async fn is_deployed(&self, claim_id: [u8; 32]) -> Result<bool> {
+        let code = self
+            .contract
+            .client()
+            .get_code(self.account_address(claim_id), None)
+            .await
+            .context("Read account code")?;
+        Ok(!code.is_empty())
+    }
+
+    /// Returns the nonce the proof of the next call of `account` commits to,
+    /// zero while the account is not deployed.
+    pub async fn account_nonce(&self, account: Address) -> Result<U256> {
+        let client = self.contract.client();
+        let code = client
+            .get_code(account, None)
+            .await
+            .context("Read account code")?;
+        if code.is_empty() {
+            return Ok(U256::zero());
+        }
+        AaAccount::new(account, client)
+            .nonce()
+            .call()
+            .await
+            .context("Read account nonce")
+    }
+
+    /// Returns the transaction deploying the account of `claim_id`. Anyone
+    /// may send it, and it does nothing once the account is deployed.
+    pub fn create_account_transaction(&self, claim_id: [u8; 32]) -> TypedTransaction {
+        self.contract.create_account(claim_id).tx
+    }
+
+    /// Returns the `initCode` of an ERC-4337 user operation deploying the
+    /// account of `claim_id`: the factory address followed by the calldata
+    /// of `createAccount`.
+    pub fn init_code(&self, claim_id: [u8; 32]) -> Bytes {
+        let calldata = self
+            .contract
+            .create_account(claim_id)
+            .calldata()
+            .expect("createAccount has calldata");
+        [self.address().as_bytes(), calldata.as_ref()]
+            .concat()
+            .into()
+    }
+
+    /// Returns the transaction deploying the account of `claim_id`, or
+    /// `None` if it is already deployed, to be sent before its first use.
+    pub async fn deploy_transaction(&self, claim_id: [u8; 32]) -> Result<Option<TypedTransaction>> {
+        if self.is_deployed(claim_id).await? {
+            return Ok(None);
+        }
+        Ok(Some(self.create_account_transaction(claim_id)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ethers::prelude::*;
+
+    use super::aa_account_address;
+    use crate::claim_id_of;
+
+    #[test]
+    fn test_account_address() {
+        let factory = Address::repeat_byte(0x11);
+        let implementation = Address::repeat_byte(0x22);
+        let claim_id = claim_id_of("bob@email.com");
+
+        // `Clones.predictDeterministicAddress(implementation, claimId, factory)`.
+        assert_eq!(
+            aa_account_address(factory, implementation, claim_id),
+            "0x56956f53142720704c519727a683a85f9c2ebd5e"
+                .parse::<Address>()
+                .unwrap()
+        );
+        assert_ne!(
+            aa_account_address(factory, implementation, claim_id),
+            aa_account_address(factory, implementation, claim_id_of("carol@email.com"))
+        );
+        assert_ne!(
+            aa_account_address(factory, implementation, claim_id),
+            aa_account_address(Address::repeat_byte(0x33), implementation, claim_id)
+        );
+    }
+}
@@ -13,44 +13,37 @@
 // limitations under the License.
 
 
-use alloy_primitives::{FixedBytes, U256,Address, utils as AlloyUtils};
-use ethers::core::types::U256 as EthersU256;
+use alloy_primitives::{U256,Address};
+use anyhow::{anyhow, bail, ensure, Context};
+use ethers::core::types::{Address as EthersAddress, U256 as EthersU256};
 use ethers::{utils};
-use alloy_sol_types::{sol, SolInterface, SolValue};
-use anyhow::Context;
-use apps::{BonsaiProver, TxSender,AaDeployer,AaContract};
-use risc0_zkvm::sha::rust_crypto::{Digest as _, Sha256};
-use clap::Parser;
+use alloy_sol_types::{sol, SolInterface};
+use apps::{
+    claim_id_of, verify_bundle, AccountFactory, Assignment, BonsaiProver, Bundler, CachedProver,
+    Call, ChainClient, ConfirmationPolicy, ContractError, DevModeProver, EventStore, IndexedEvent,
+    Indexer, IndexerConfig, LocalProver, PollConfig, ProofBundle, ProofCache, Prover, ProverError,
+    RebalancePolicy, RpcPool, SignerSource, SnarkBackend, Token, TokenRegistry, TxQueue,
+    VerifyError, WalletPool, DEFAULT_DERIVATION_PATH,
+};
+use clap::{ArgGroup, Parser, ValueEnum};
 use log::info;
 use std::str::FromStr;
-use std::{collections::HashMap};
-use methods::JWT_VALIDATOR_ELF;
-use tokio::sync::oneshot;
-use warp::{ Filter,Rejection, Reply};
+use std::{collections::HashMap, path::PathBuf, time::Duration};
+use methods::{JWT_VALIDATOR_ELF, JWT_VALIDATOR_ID};
+use oidc_validator::{input::GuestInput, IdentityProvider, OidcErr};
+use serde::{Deserialize, Serialize};
+use tokio_util::sync::CancellationToken;
+use warp::{http::StatusCode, Filter, Rejection, Reply};
 use std::sync::{Arc, Mutex};
 use google_oauth::AsyncClient;
 
 
 
 sol! {
-    interface IBonsaiPay {
-        function claim(address payable to, bytes32 claim_id, bytes32 post_state_digest, bytes calldata seal);
-        function executeCall(address payable _to, bytes32 claim_id, bytes32 post_state_digest, bytes calldata seal);
-    }
     interface IAaDemo {
         function execute(address dest, uint256 value, bytes calldata func,  bytes32 claim_id, bytes32 post_state_digest, bytes calldata seal);
-        function setOwner(bytes32 _owner);
-    }
-
-    struct Input {
-        uint256 identity_provider;
-        string jwt;
     }
 
-    struct ClaimsData {
-        address msg_sender;
-        bytes32 claim_id;
-    }
     struct Reciever{
         address to;
     }
@@ -59,339 +52,831 @@ sol! {
 /// Arguments of the publisher CLI.
 #[derive(Parser, Debug)]
 #[clap(author, version, about, long_about = None)]
+#[clap(group(ArgGroup::new("signer").required(true)))]
 struct Args {
     /// Ethereum chain ID
     #[clap(long)]
     chain_id: u64,
 
-    /// Ethereum Node endpoint.
+    /// Private key of the relayer wallet
+    #[clap(long, env, group = "signer")]
+    eth_wallet_private_key: Option<String>,
+
+    /// Encrypted JSON keystore of the relayer wallet
+    #[clap(long, env, group = "signer", requires = "keystore_password")]
+    keystore: Option<PathBuf>,
+
+    /// Password of the keystore
     #[clap(long, env)]
-    eth_wallet_private_key: String,
+    keystore_password: Option<String>,
+
+    /// BIP-39 mnemonic of the relayer wallet
+    #[clap(long, env, group = "signer")]
+    mnemonic: Option<String>,
 
-    /// Ethereum Node endpoint.
+    /// Derivation path of the relayer account in the mnemonic
+    #[clap(long, default_value = DEFAULT_DERIVATION_PATH)]
+    derivation_path: String,
+
+    /// URL of a remote signer holding the relayer key
+    #[clap(long, env, group = "signer")]
+    remote_signer: Option<String>,
+
+    /// Ethereum Node endpoint, over HTTP, WebSocket (ws://) or IPC (ipc:// or a path).
+    /// Repeat it or separate URLs by commas to fail over to the next endpoints.
+    #[clap(long, required = true, value_delimiter = ',')]
+    rpc_url: Vec<String>,
+
+    /// Number of endpoints that must agree on balances, nonces and other account reads
     #[clap(long)]
-    rpc_url: String,
+    rpc_quorum: Option<usize>,
 
     /// Application's contract address on Ethereum
     #[clap(long)]
     contract: String,
-}
-type UserState = Arc<Mutex<HashMap<String, Option<Address>>>>;
-const HEADER_XAUTH: &str = "X-Auth-Token";
-const HEADER_DEST: &str ="X-DEST";
 
-async fn handle_jwt_authentication(token: String, user_state: UserState) -> Result<(String,String), warp::Rejection> {
-    if token.is_empty() {
-        return Err(warp::reject::reject());
-    }
-    let client_id = "280372739368-qv4brva0eiq0v1oo1jtsqdaph6mv7omo.apps.googleusercontent.com";
+    /// Address of the AADemoFactory deploying the AA accounts of the users
+    #[clap(long, env)]
+    aa_factory: String,
 
-    info!("Token received: {}", token);
-    let client = AsyncClient::new(client_id);
-    let payload = client.validate_id_token(token.clone()).await.expect("Could not validate token"); // In production, remember to handle this error.
+    /// URL of an ERC-4337 bundler. When set, calls of the AA accounts are sent to it as user
+    /// operations paid by the accounts, instead of transactions of the relayer
+    #[clap(long, env)]
+    bundler_url: Option<String>,
 
+    /// EntryPoint receiving the user operations, instead of the EntryPoint v0.6
+    #[clap(long, requires = "bundler_url")]
+    entry_point: Option<String>,
 
-    let args = Args::parse();
-    let (tx, rx) = oneshot::channel();
-    let email = payload.email.unwrap();
-    let email_clone = email.clone();
-    
+    /// Maximum time in seconds to wait for a user operation to be included
+    #[clap(long, default_value_t = 300)]
+    user_op_timeout: u64,
 
-    // Spawn a new thread for the Bonsai Prover computation
-    std::thread::spawn(move || {
-        deploy_AA(args,email_clone , user_state, tx);
+    /// Prover used to generate the proofs
+    #[clap(long, value_enum, default_value_t = ProverKind::Bonsai)]
+    prover: ProverKind,
 
-    });
+    /// Groth16 prover binary used by the local prover, instead of Docker
+    #[clap(long, conflicts_with = "groth16_image")]
+    groth16_binary: Option<PathBuf>,
 
-    match rx.await {
-        Ok(_result) => Ok((email,token)),
-        Err(_) => Err(warp::reject::reject()),
-    }
+    /// Groth16 prover image used by the local prover, instead of the default one
+    #[clap(long)]
+    groth16_image: Option<String>,
+
+    /// Container runtime running the Groth16 prover image
+    #[clap(long, default_value = "docker")]
+    container_runtime: String,
+
+    /// Initial delay in seconds between two status checks of a Bonsai session
+    #[clap(long, default_value_t = 5)]
+    poll_interval: u64,
+
+    /// Maximum time in seconds to wait for a Bonsai proof, 0 to wait forever
+    #[clap(long, default_value_t = 1800)]
+    proof_timeout: u64,
+
+    /// Directory remembering the images uploaded to Bonsai across restarts
+    #[clap(long, env)]
+    image_cache_dir: Option<PathBuf>,
+
+    /// Directory caching the generated proofs, so identical requests are only proven once
+    #[clap(long, env)]
+    proof_cache_dir: Option<PathBuf>,
+
+    /// Maximum number of cached proofs
+    #[clap(long, default_value_t = 1000)]
+    proof_cache_size: usize,
+
+    /// Time in seconds after which a cached proof expires
+    #[clap(long, default_value_t = 24 * 60 * 60)]
+    proof_cache_ttl: u64,
+
+    /// Number of blocks, including its own, after which a transaction is confirmed
+    #[clap(long, default_value_t = 1)]
+    confirmations: u64,
+
+    /// Time in seconds after which a pending transaction is replaced with higher fees
+    #[clap(long, default_value_t = 180)]
+    replace_after: u64,
+
+    /// Fee increase of a replacement transaction, in percent
+    #[clap(long, default_value_t = 15)]
+    fee_bump: u64,
+
+    /// Private keys of the relayer wallets sending the transactions, separated by commas.
+    /// The main signer then only tops them up
+    #[clap(long, env, value_delimiter = ',')]
+    relayer_private_keys: Vec<String>,
+
+    /// Encrypted JSON keystores of more relayer wallets, separated by commas
+    #[clap(long, env, value_delimiter = ',', requires = "relayer_keystore_password")]
+    relayer_keystores: Vec<PathBuf>,
+
+    /// Password of the relayer keystores
+    #[clap(long, env)]
+    relayer_keystore_password: Option<String>,
+
+    /// BIP-39 mnemonic of more relayer wallets, the first --relayer-count accounts of
+    /// m/44'/60'/0'/0
+    #[clap(long, env, requires = "relayer_count")]
+    relayer_mnemonic: Option<String>,
+
+    /// Number of relayer wallets derived from --relayer-mnemonic
+    #[clap(long)]
+    relayer_count: Option<u32>,
+
+    /// URLs of remote signers holding the keys of more relayer wallets, separated by commas
+    #[clap(long, env, value_delimiter = ',')]
+    relayer_remote_signers: Vec<String>,
+
+    /// How transactions are assigned to the relayer wallets
+    #[clap(long, value_enum, default_value_t = AssignmentKind::RoundRobin)]
+    relayer_assignment: AssignmentKind,
+
+    /// Balance in ether under which a relayer wallet is topped up
+    #[clap(long, default_value = "0.05")]
+    relayer_min_balance: String,
+
+    /// Amount in ether sent to a relayer wallet running low
+    #[clap(long, default_value = "0.1")]
+    relayer_top_up: String,
+
+    /// Delay in seconds between two checks of the relayer balances
+    #[clap(long, default_value_t = 60)]
+    rebalance_interval: u64,
+
+    /// Tokens accepted by the execute requests, in the layout of ui/src/assets/tokens.json.
+    /// Defaults to that file
+    #[clap(long, env)]
+    token_registry: Option<PathBuf>,
+
+    /// Directory of the database indexing the contract events, served under /events.
+    /// Indexing is disabled without it
+    #[clap(long, env)]
+    index_db: Option<PathBuf>,
+
+    /// Contracts whose events are indexed, separated by commas. Defaults to --contract
+    #[clap(long, value_delimiter = ',')]
+    index_contracts: Vec<String>,
+
+    /// First block indexed
+    #[clap(long, default_value_t = 0)]
+    index_from_block: u64,
+
+    /// Number of blocks, including its own, after which a block is indexed
+    #[clap(long, default_value_t = 12)]
+    index_confirmations: u64,
 }
-async fn handle_execute(token: String, email: String, user_state: UserState, dest: String) ->Result<impl warp::Reply, warp::Rejection> {
-    if token.is_empty() {
-        return Err(warp::reject::reject());
+
+impl Args {
+    /// Returns the source of the relayer key, exactly one being set.
+    fn signer_source(&self) -> SignerSource {
+        if let Some(key) = &self.eth_wallet_private_key {
+            SignerSource::PrivateKey(key.clone())
+        } else if let Some(path) = &self.keystore {
+            SignerSource::Keystore {
+                path: path.clone(),
+                password: self.keystore_password.clone().unwrap_or_default(),
+            }
+        } else if let Some(phrase) = &self.mnemonic {
+            SignerSource::Mnemonic {
+                phrase: phrase.clone(),
+                derivation_path: self.derivation_path.clone(),
+            }
+        } else {
+            SignerSource::Remote(self.remote_signer.clone().expect("no signer configured"))
+        }
     }
-   
-    info!("Token received: {}", token);
 
+    /// Returns a client sending transactions from the relayer wallet.
+    async fn chain_client(&self) -> ChainClient {
+        let signer = self
+            .signer_source()
+            .signer(self.chain_id)
+            .await
+            .expect("failed to load relayer signer");
+        let mut pool = RpcPool::connect(&self.rpc_url)
+            .await
+            .expect("failed to connect to the RPC endpoints");
+        if let Some(quorum) = self.rpc_quorum {
+            pool = pool.with_quorum(quorum).expect("invalid --rpc-quorum");
+        }
+        ChainClient::new(self.chain_id, pool, signer)
+    }
 
-    let args = Args::parse();
-    let (tx, rx) = oneshot::channel();
-    
+    fn confirmation_policy(&self) -> ConfirmationPolicy {
+        ConfirmationPolicy {
+            confirmations: self.confirmations,
+            replace_after: Duration::from_secs(self.replace_after),
+            fee_bump: self.fee_bump,
+            ..Default::default()
+        }
+    }
 
-    // Spawn a new thread for the Bonsai Prover computation
-    std::thread::spawn(move || {
-        prove_and_execute_transaction(args, token, email,user_state,dest, tx);
-    });
+    /// Returns the sources of the keys of the relayer wallets, from private
+    /// keys, keystores, a mnemonic and remote signers, in that order.
+    fn relayer_sources(&self) -> Vec<SignerSource> {
+        let keys = self
+            .relayer_private_keys
+            .iter()
+            .map(|key| SignerSource::PrivateKey(key.clone()));
+        let keystores = self.relayer_keystores.iter().map(|path| SignerSource::Keystore {
+            path: path.clone(),
+            password: self.relayer_keystore_password.clone().unwrap_or_default(),
+        });
+        let mnemonic = self.relayer_mnemonic.iter().flat_map(|phrase| {
+            (0..self.relayer_count.unwrap_or_default()).map(move |index| SignerSource::Mnemonic {
+                phrase: phrase.clone(),
+                derivation_path: format!("m/44'/60'/0'/0/{index}"),
+            })
+        });
+        let remotes = self
+            .relayer_remote_signers
+            .iter()
+            .map(|url| SignerSource::Remote(url.clone()));
+        keys.chain(keystores).chain(mnemonic).chain(remotes).collect()
+    }
 
-    match rx.await {
-        Ok(_result) => Ok(warp::reply()),
-        Err(_) => Err(warp::reject::reject()),
+    /// Returns the pool of relayer wallets sending the transactions, spawned
+    /// on the current runtime. With relayer wallets, the main signer is the
+    /// treasury topping them up, otherwise the only relayer.
+    async fn relayers(&self) -> WalletPool {
+        let chain = self.chain_client().await;
+        let policy = self.confirmation_policy();
+        let sources = self.relayer_sources();
+        if sources.is_empty() {
+            let queue = TxQueue::spawn(chain).with_confirmation_policy(policy);
+            return WalletPool::new(vec![queue]).expect("no relayer");
+        }
+
+        let mut queues = Vec::with_capacity(sources.len());
+        for source in &sources {
+            let signer = source
+                .signer(self.chain_id)
+                .await
+                .expect("failed to load relayer key");
+            queues.push(
+                TxQueue::spawn(chain.with_signer(signer)).with_confirmation_policy(policy.clone()),
+            );
+        }
+        let relayers = WalletPool::new(queues)
+            .expect("no relayer")
+            .with_assignment(self.relayer_assignment.into());
+        info!("Sending transactions from relayers {:?}", relayers.addresses());
+
+        let rebalance = RebalancePolicy {
+            min_balance: utils::parse_ether(&self.relayer_min_balance)
+                .expect("invalid relayer minimum balance"),
+            top_up: utils::parse_ether(&self.relayer_top_up).expect("invalid relayer top up"),
+            interval: Duration::from_secs(self.rebalance_interval),
+        };
+        let treasury = TxQueue::spawn(chain).with_confirmation_policy(policy);
+        relayers.spawn_rebalancer(treasury, rebalance);
+        relayers
     }
-}
 
-fn prove_and_send_transaction(
-    args: Args,
-    token: String,
-    tx: oneshot::Sender<(Vec<u8>, FixedBytes<32>, Vec<u8>)>,
-) {
-    let input = Input {
-        identity_provider: U256::ZERO, // Google as the identity provider
-        jwt: token,
-    };
+    /// Returns the store of the indexed events, with `--index-db`, after
+    /// spawning the indexer filling it on the current runtime.
+    async fn event_store(&self) -> Option<EventStore> {
+        let path = self.index_db.as_ref()?;
+        let store = EventStore::open(path).expect("failed to open the event database");
+        let contracts = if self.index_contracts.is_empty() {
+            std::slice::from_ref(&self.contract)
+        } else {
+            self.index_contracts.as_slice()
+        };
+        let config = IndexerConfig {
+            contracts: contracts
+                .iter()
+                .map(|contract| contract.parse().expect("invalid indexed contract address"))
+                .collect(),
+            start_block: self.index_from_block,
+            confirmations: self.index_confirmations,
+            ..Default::default()
+        };
+        Indexer::new(self.chain_client().await, store.clone(), config).spawn();
+        Some(store)
+    }
 
-    let (journal, post_state_digest, seal) =
-        BonsaiProver::prove(JWT_VALIDATOR_ELF, &input.abi_encode())
-            .expect("failed to prove on bonsai");
+    fn token_registry(&self) -> TokenRegistry {
+        match &self.token_registry {
+            Some(path) => TokenRegistry::load(path).expect("failed to load token registry"),
+            None => TokenRegistry::bundled(),
+        }
+    }
 
-    let seal_clone = seal.clone();
+    /// Returns the call of an execute request, from its `X-DEST` header, and
+    /// its `X-CALL` header or its `X-TOKEN` and `X-AMOUNT` headers. Without
+    /// them, 10 Twei of Ether are sent to `X-DEST`.
+    fn call(
+        &self,
+        call: Option<&str>,
+        token: Option<&str>,
+        amount: Option<&str>,
+        dest: &str,
+    ) -> anyhow::Result<Call> {
+        let dest: EthersAddress = dest.parse().context("Invalid X-DEST")?;
+        if let Some(call) = call {
+            let request: CallRequest = serde_json::from_str(call).context("Invalid X-CALL")?;
+            let value = match &request.value {
+                Some(value) => EthersU256::from_dec_str(value).context("Invalid call value")?,
+                None => EthersU256::zero(),
+            };
+            return Call::from_signature(dest, value, &request.signature, &request.args);
+        }
+
+        let token = match token {
+            Some(name) => self
+                .token_registry()
+                .find(self.chain_id, name)
+                .cloned()
+                .ok_or_else(|| anyhow!("Unknown token {} on chain {}", name, self.chain_id))?,
+            None => Token::ether(),
+        };
+        let amount = match amount {
+            Some(amount) => token.parse_amount(amount)?,
+            None if token.is_native() => utils::parse_units(10, "twei")?.into(),
+            None => bail!("Missing amount of {}", token.name),
+        };
+        Ok(token.transfer(dest, amount))
+    }
 
-    let tx_sender = TxSender::new(
-        args.chain_id,
-        &args.rpc_url,
-        &args.eth_wallet_private_key,
-        &args.contract,
-    )
-    .expect("failed to create tx sender");
+    fn contract_address(&self) -> EthersAddress {
+        self.contract.parse().expect("invalid contract address")
+    }
 
-    let claims = ClaimsData::abi_decode(&journal, true)
-        .context("decoding journal data")
-        .expect("failed to decode");
+    /// Returns the client of the bundler, with `--bundler-url`.
+    fn bundler(&self) -> Option<Bundler> {
+        let url = self.bundler_url.as_ref()?;
+        let mut bundler = Bundler::new(url).expect("invalid bundler URL");
+        if let Some(entry_point) = &self.entry_point {
+            bundler = bundler.with_entry_point(entry_point.parse().expect("invalid entry point address"));
+        }
+        Some(bundler)
+    }
 
-    info!("Claim ID: {:?}", claims.claim_id);
-    info!("Msg Sender: {:?}", claims.msg_sender);
-    info!("post_state_digest {:?}",post_state_digest);
-    info!("seal: {:?}",seal_clone);
+    /// Returns the client of the factory of the AA accounts.
+    async fn account_factory(&self, chain: &ChainClient) -> anyhow::Result<AccountFactory> {
+        let address = self.aa_factory.parse().context("Invalid AA factory address")?;
+        AccountFactory::connect(chain, address).await
+    }
 
-    let calldata = IBonsaiPay::IBonsaiPayCalls::claim(IBonsaiPay::claimCall {
-        to: claims.msg_sender,
-        claim_id: claims.claim_id,
-        post_state_digest,
-        seal: seal_clone,
-    })
-    .abi_encode();
+    /// Returns the configured [Prover], backed by the proof cache if any.
+    fn prover(&self) -> Box<dyn Prover> {
+        let prover = self.uncached_prover();
+        let Some(dir) = &self.proof_cache_dir else {
+            return prover;
+        };
+
+        let cache = ProofCache::new(dir)
+            .expect("failed to open proof cache")
+            .with_max_entries(self.proof_cache_size)
+            .with_ttl(Duration::from_secs(self.proof_cache_ttl));
+        let kind = self.prover;
+        // Only proofs the relayer would accept are cached.
+        Box::new(
+            CachedProver::new(kind.name(), prover, cache)
+                .with_verifier(move |bundle| kind.verify(bundle)),
+        )
+    }
 
+    /// Checks the proof off-chain before paying for a transaction.
+    fn verify(&self, bundle: &ProofBundle) -> Result<(), VerifyError> {
+        self.prover.verify(bundle)
+    }
 
-    // Send the calldata to Ethereum.
-    let runtime = tokio::runtime::Runtime::new().expect("failed to start new tokio runtime");
-    runtime
-        .block_on(tx_sender.send(calldata))
-        .expect("failed to send tx");
+    fn snark_backend(&self) -> SnarkBackend {
+        match (&self.groth16_binary, &self.groth16_image) {
+            (Some(binary), _) => SnarkBackend::Binary(binary.clone()),
+            (None, Some(image)) => SnarkBackend::Container {
+                runtime: self.container_runtime.clone(),
+                image: image.clone(),
+            },
+            (None, None) => SnarkBackend::Docker,
+        }
+    }
 
-    tx.send((journal, post_state_digest, seal))
-        .expect("failed to send over channel");
+    fn uncached_prover(&self) -> Box<dyn Prover> {
+        match self.prover {
+            ProverKind::Bonsai => {
+                let poll = PollConfig {
+                    interval: Duration::from_secs(self.poll_interval),
+                    timeout: (self.proof_timeout > 0)
+                        .then(|| Duration::from_secs(self.proof_timeout)),
+                    ..Default::default()
+                };
+                let prover = BonsaiProver::default().with_poll_config(poll);
+                match &self.image_cache_dir {
+                    Some(dir) => Box::new(prover.with_image_cache_dir(dir)),
+                    None => Box::new(prover),
+                }
+            }
+            ProverKind::Local => {
+                Box::new(LocalProver::default().with_snark_backend(self.snark_backend()))
+            }
+            ProverKind::Dev => Box::new(DevModeProver {}),
+        }
+    }
 }
 
-fn prove_and_execute_call_transaction(
-    args: Args,
-    token: String,
-    to: Address,
-    tx: oneshot::Sender<(Vec<u8>, FixedBytes<32>, Vec<u8>)>,
-) {
-    let input = Input {
-        identity_provider: U256::ZERO, // Google as the identity provider
-        jwt: token,
-    };
-    
+/// How the execute requests are spread over the relayer wallets.
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum AssignmentKind {
+    /// Each relayer in turn.
+    RoundRobin,
+    /// The relayer with the fewest transactions in flight.
+    LeastLoaded,
+}
 
-    let (journal, post_state_digest, seal) =
-        BonsaiProver::prove(JWT_VALIDATOR_ELF, &input.abi_encode())
-            .expect("failed to prove on bonsai");
+impl From<AssignmentKind> for Assignment {
+    fn from(kind: AssignmentKind) -> Self {
+        match kind {
+            AssignmentKind::RoundRobin => Assignment::RoundRobin,
+            AssignmentKind::LeastLoaded => Assignment::LeastLoaded,
+        }
+    }
+}
 
-    let seal_clone = seal.clone();
+/// The available [Prover] implementations.
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum ProverKind {
+    /// Prove on Bonsai, requires BONSAI_API_KEY and BONSAI_API_URL.
+    Bonsai,
+    /// Prove locally, requires Docker for the Groth16 compression.
+    Local,
+    /// Only execute the guest and return empty seals, for use with a mock verifier.
+    Dev,
+}
 
-    let tx_sender = TxSender::new(
-        args.chain_id,
-        &args.rpc_url,
-        &args.eth_wallet_private_key,
-        &args.contract,
-    )
-    .expect("failed to create tx sender");
+impl ProverKind {
+    /// Name of the prover, keying its proofs in the proof cache.
+    fn name(self) -> &'static str {
+        match self {
+            ProverKind::Bonsai => "bonsai",
+            ProverKind::Local => "local",
+            ProverKind::Dev => "dev",
+        }
+    }
 
-    let claims = ClaimsData::abi_decode(&journal, true)
-        .context("decoding journal data")
-        .expect("failed to decode");
+    /// Checks a proof of this prover. Dev mode proofs have no seal, and are
+    /// only accepted by a mock verifier.
+    fn verify(self, bundle: &ProofBundle) -> Result<(), VerifyError> {
+        match self {
+            ProverKind::Dev => Ok(()),
+            ProverKind::Bonsai | ProverKind::Local => verify_bundle(bundle, JWT_VALIDATOR_ID),
+        }
+    }
+}
 
-    info!("Claim ID: {:?}", claims.claim_id);
-    info!("Msg Sender: {:?}", claims.msg_sender);
-    info!("To Address: {:?}", to);
+type UserState = Arc<Mutex<HashMap<String, Option<Address>>>>;
+/// The prover shared by all requests, so its upload caches outlive a request.
+type SharedProver = Arc<dyn Prover>;
+const HEADER_XAUTH: &str = "X-Auth-Token";
+const HEADER_DEST: &str ="X-DEST";
+const HEADER_TOKEN: &str = "X-TOKEN";
+const HEADER_AMOUNT: &str = "X-AMOUNT";
+const HEADER_CALL: &str = "X-CALL";
+
+// How long a login waits for a concurrent registration of the same account,
+// and how often it checks on it.
+const REGISTRATION_TIMEOUT: Duration = Duration::from_secs(300);
+const REGISTRATION_POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Body of the `X-CALL` header of an execute request.
+#[derive(Debug, Deserialize)]
+struct CallRequest {
+    /// Human-readable signature of the function, e.g. `transfer(address,uint256)`.
+    signature: String,
+    /// Arguments of the function, as JSON values.
+    #[serde(default)]
+    args: Vec<serde_json::Value>,
+    /// Value sent with the call, in wei.
+    value: Option<String>,
+}
 
+/// Reasons an execute request fails.
+#[derive(Debug, thiserror::Error)]
+enum RequestError {
+    #[error(transparent)]
+    Prover(#[from] ProverError),
+    /// The transaction would revert, so it was not sent.
+    #[error("transaction would revert: {0}")]
+    Revert(#[from] ContractError),
+    /// The requested call cannot be made, or is not authorized by the token.
+    #[error("invalid action: {0:#}")]
+    Action(anyhow::Error),
+    #[error(transparent)]
+    Other(#[from] anyhow::Error),
+}
 
-    let calldata = IBonsaiPay::IBonsaiPayCalls::executeCall(IBonsaiPay::executeCallCall {
-        _to: to,
-        claim_id: claims.claim_id,
-        post_state_digest,
-        seal: seal_clone,
-    })
-    .abi_encode();
+/// Body of the reply sent to the UI when the guest rejects a token, or the
+/// contract would reject the transaction.
+#[derive(Serialize)]
+struct ErrorReply {
+    error: String,
+    /// [OidcErr::code] of a rejected token.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    code: Option<u32>,
+}
 
-    // Send the calldata to Ethereum.
-    let runtime = tokio::runtime::Runtime::new().expect("failed to start new tokio runtime");
-    runtime
-        .block_on(tx_sender.send(calldata))
-        .expect("failed to send tx");
+fn guest_error_reply(err: OidcErr) -> warp::reply::Response {
+    let reply = ErrorReply {
+        error: err.to_string(),
+        code: Some(err.code()),
+    };
+    warp::reply::with_status(warp::reply::json(&reply), StatusCode::UNPROCESSABLE_ENTITY)
+        .into_response()
+}
 
-    tx.send((journal, post_state_digest, seal))
-        .expect("failed to send over channel");
+fn bad_request_reply(err: anyhow::Error) -> warp::reply::Response {
+    let reply = ErrorReply {
+        error: format!("{:#}", err),
+        code: None,
+    };
+    warp::reply::with_status(warp::reply::json(&reply), StatusCode::BAD_REQUEST).into_response()
 }
 
+fn server_error_reply(err: anyhow::Error) -> warp::reply::Response {
+    let reply = ErrorReply {
+        error: format!("{:#}", err),
+        code: None,
+    };
+    warp::reply::with_status(warp::reply::json(&reply), StatusCode::INTERNAL_SERVER_ERROR)
+        .into_response()
+}
 
-fn deploy_AA(
-    args: Args,
-    email: String,
-    user_state: UserState,
-    tx: oneshot::Sender<(&str)>,
-) {
-   
-    let mut state = user_state.lock().unwrap();
-   
+fn revert_reply(err: ContractError) -> warp::reply::Response {
+    let reply = ErrorReply {
+        error: err.to_string(),
+        code: None,
+    };
+    warp::reply::with_status(warp::reply::json(&reply), StatusCode::UNPROCESSABLE_ENTITY)
+        .into_response()
+}
 
-    // Send the calldata to Ethereum.
-    if(!state.contains_key(&email)){    
-    let deployer = AaDeployer::new(
-        args.chain_id,
-        &args.rpc_url,
-        &args.eth_wallet_private_key,
-        &"0x2A662A912A1e11c7Cc9cD2a509dF085335Cd2619",
+async fn handle_jwt_authentication(token: String) -> Result<(String,String), warp::Rejection> {
+    if token.is_empty() {
+        return Err(warp::reject::reject());
+    }
+    let client_id = "280372739368-qv4brva0eiq0v1oo1jtsqdaph6mv7omo.apps.googleusercontent.com";
 
-    ).expect("Failed to create Deployer");
-   
-    let email_clone = email.clone();
+    info!("Token received: {}", token);
+    let client = AsyncClient::new(client_id);
+    let payload = client.validate_id_token(token.clone()).await.expect("Could not validate token"); // In production, remember to handle this error.
 
-  
 
+    let email = payload.email.unwrap();
+    Ok((email, token))
+}
+
+/// Replies with the account of `email`, registering it on first sight.
+async fn handle_deploy(email: String, user_state: UserState, relayers: WalletPool) -> Result<warp::reply::Response, warp::Rejection> {
+    let args = Args::parse();
+    if let Err(err) = register_account(&args, &relayers, &email, &user_state).await {
+        info!("Failed to register the account of {}: {:#}", email, err);
+        return Ok(server_error_reply(err));
+    }
+    let account = user_state.lock().unwrap().get(&email).copied().flatten();
+    Ok(warp::reply::json(&account.map(|account| account.to_string())).into_response())
+}
+
+async fn handle_execute(token: String, email: String, user_state: UserState, prover: SharedProver, relayers: WalletPool, dest: String, call: Option<String>, payment_token: Option<String>, payment_amount: Option<String>) ->Result<warp::reply::Response, warp::Rejection> {
+    if token.is_empty() {
+        return Err(warp::reject::reject());
+    }
    
-        let runtime = tokio::runtime::Runtime::new().expect("failed to start new tokio runtime");
-        match
-        runtime
-            .block_on(deployer.deploy()) {
-                Ok((receipt)) => {
-                    info!(" Deploy Transaction receipt: {:?}", receipt);
-                    let addy = receipt.unwrap().contract_address.unwrap();
-                    state.insert(email,Some(Address::from_slice( addy.as_bytes() )));
-                    info!("Contract address: {:?}", addy);
-                }
-                Err(e) => {
-                    info!("Failed to send tx: {:?}", e);
-                }
-            };
-        let claim_id: FixedBytes<32> =   FixedBytes::from_slice(Sha256::digest(email_clone.as_bytes()).as_slice());
+    info!("Token received: {}", token);
 
-        let calldata = IAaDemo::IAaDemoCalls::setOwner(IAaDemo::setOwnerCall {
-              _owner: claim_id
-         })
-        .abi_encode();   
-         let contract_addy = state.get(&email_clone).unwrap().unwrap();
 
+    let args = Args::parse();
+    if let Err(err) = register_account(&args, &relayers, &email, &user_state).await {
+        info!("Failed to register the account of {}: {:#}", email, err);
+        return Ok(server_error_reply(err));
+    }
+    // Check the call before proving, so a bad request costs no proof.
+    let call = match args.call(call.as_deref(), payment_token.as_deref(), payment_amount.as_deref(), &dest) {
+        Ok(call) => call,
+        Err(err) => {
+            info!("Rejected action: {:#}", err);
+            return Ok(bad_request_reply(err));
+        }
+    };
+    let account = match registered_account(&user_state, &email) {
+        Ok(account) => account,
+        Err(err) => {
+            info!("Rejected request: {:#}", err);
+            return Ok(bad_request_reply(err));
+        }
+    };
+    // Stop proving if the client goes away and this future is dropped.
+    let cancel = CancellationToken::new();
+    let _cancel_guard = cancel.clone().drop_guard();
+
+    match prove_and_execute_transaction(&args, prover, relayers, token, claim_id_of(&email), account, call, &cancel).await {
+        Ok(_bundle) => Ok(warp::reply().into_response()),
+        Err(RequestError::Prover(ProverError::Guest(err))) => {
+            info!("Guest rejected the token: {}", err);
+            Ok(guest_error_reply(err))
+        }
+        Err(RequestError::Revert(err)) => {
+            info!("Skipped transaction bound to revert: {}", err);
+            Ok(revert_reply(err))
+        }
+        Err(RequestError::Action(err)) => {
+            info!("Rejected action: {:#}", err);
+            Ok(bad_request_reply(err))
+        }
+        Err(err) => {
+            info!("Failed to execute: {:?}", err);
+            Err(warp::reject::reject())
+        }
+    }
+}
 
-    
-        let init_value: EthersU256 = EthersU256::from(utils::parse_units(1000,"szabo").unwrap());
-
-        match 
-        runtime
-            .block_on(deployer.send(None, &contract_addy.to_string(),Some(init_value))) {
-                Ok((receipt)) => {
-                    log::info!(" Init Funding Transaction receipt: {:?}", receipt);
-                    
-                }
-                Err(e) => {
-                 log::info!("Failed to send tx: {:?}", e);
-                 }
-              };
-        match
-         runtime
-            .block_on(deployer.send(Some(calldata), &contract_addy.to_string(),None)) {
-                Ok((receipt)) => {
-                log::info!(" Set Owner Transaction receipt: {:?}", receipt);
-                         
-                }
-                Err(e) => {
-                    log::info!("Failed to send tx: {:?}", e);
+/// Registers the AA account of `email` at its counterfactual address, and
+/// funds it on first sight. The account is only deployed on its first
+/// execute request. A failed registration is retried on the next request.
+async fn register_account(
+    args: &Args,
+    relayers: &WalletPool,
+    email: &str,
+    user_state: &UserState,
+) -> anyhow::Result<()> {
+    // Reserve the entry, so concurrent logins of a new user fund it once,
+    // while the other logins wait for the registration and retry it if it
+    // fails. The lock is never held across network calls.
+    let started = tokio::time::Instant::now();
+    loop {
+        {
+            let mut state = user_state.lock().unwrap();
+            match state.get(email) {
+                Some(Some(_)) => return Ok(()),
+                Some(None) => {}
+                None => {
+                    state.insert(email.to_string(), None);
+                    break;
                 }
-            };        
-    
+            }
+        }
+        ensure!(
+            started.elapsed() < REGISTRATION_TIMEOUT,
+            "The account of {} is still being registered",
+            email
+        );
+        tokio::time::sleep(REGISTRATION_POLL_INTERVAL).await;
+    }
 
-    };
+    let registered = async {
+        let factory = args.account_factory(relayers.chain()).await?;
+        let contract_addy = factory.account_of(email);
+        info!("Contract address: {:?}", contract_addy);
+
+        // The funds are kept at the counterfactual address until deployment.
+        let init_value: EthersU256 = utils::parse_units(1000, "szabo")?.into();
+        let outcome = relayers
+            .send_call(contract_addy, Vec::new(), init_value)
+            .await
+            .context("Fund the account")?;
+        info!(" Init Funding Transaction: {:?}", outcome);
+        Ok::<_, anyhow::Error>(contract_addy)
+    }
+    .await;
 
-    tx.send("Deployed successfully")
-        .expect("failed to send over channel");
+    let mut state = user_state.lock().unwrap();
+    match registered {
+        Ok(contract_addy) => {
+            state.insert(email.to_string(), Some(Address::from_slice(contract_addy.as_bytes())));
+            Ok(())
+        }
+        Err(err) => {
+            state.remove(email);
+            Err(err)
+        }
+    }
+}
+
+/// Returns the registered account of `email`, copied out of the state so the
+/// lock is released before any network call.
+fn registered_account(user_state: &UserState, email: &str) -> anyhow::Result<EthersAddress> {
+    let state = user_state.lock().unwrap();
+    match state.get(email) {
+        Some(Some(account)) => Ok(EthersAddress::from_slice(account.as_slice())),
+        Some(None) => bail!("The account of {} is being registered", email),
+        None => bail!("No account registered for {}", email),
+    }
 }
 
-fn prove_and_execute_transaction(
-    args: Args,
+async fn prove_and_execute_transaction(
+    args: &Args,
+    prover: SharedProver,
+    relayers: WalletPool,
     token: String,
-    email:String,
-    user_state: UserState,
-    dest: String,
-    tx: oneshot::Sender<(Vec<u8>, FixedBytes<32>, Vec<u8>)>,
-) {
-    let input = Input {
-        identity_provider: U256::ZERO, // Google as the identity provider
-        jwt: token,
+    claim_id: [u8; 32],
+    contract_addy: EthersAddress,
+    call: Call,
+    cancel: &CancellationToken,
+) -> Result<ProofBundle, RequestError> {
+    info!("Call: {:?}", call);
+
+    // The proof commits to the whole call and to the nonce of the account,
+    // and the token must carry their hash as its nonce, which the guest
+    // checks when it validates the token.
+    let factory = args.account_factory(relayers.chain()).await?;
+    let nonce = factory.account_nonce(contract_addy).await?;
+    let mut input = GuestInput::new(IdentityProvider::Google, token).with_call(
+        Address::from_slice(call.to.as_bytes()),
+        U256::from_limbs(call.value.0),
+        call.func_hash().0.into(),
+        U256::from_limbs(nonce.0),
+    );
+    // A token issued for another call would only be rejected by the guest,
+    // so tell the client which nonce to sign in with instead of proving.
+    let token_nonce = oidc_validator::unverified_nonce(&input.jwt).map_err(ProverError::Guest)?;
+    if let Err(err) = input.authorized_sender(&token_nonce) {
+        return Err(RequestError::Action(anyhow!(
+            "{}: sign in with the nonce {} to authorize this call",
+            err,
+            input.call_nonce()
+        )));
+    }
+
+    // With a bundler, the account makes the call in a user operation, which
+    // deploys it on its first use and is paid by the account. The proof is
+    // bound to the hash of the operation, so it is built first.
+    let bundler = args.bundler();
+    let user_op = match &bundler {
+        Some(bundler) => {
+            let op = bundler.user_operation(relayers.chain(), &factory, claim_id, &call).await?;
+            let user_op_hash = op.hash(bundler.entry_point(), relayers.chain().chain_id());
+            input = input.with_user_op_hash(user_op_hash.0.into());
+            Some(op)
+        }
+        None => None,
     };
-    
 
-    let (journal, post_state_digest, seal) =
-        BonsaiProver::prove(JWT_VALIDATOR_ELF, &input.abi_encode())
-            .expect("failed to prove on bonsai");
+    let bundle = prover.prove(JWT_VALIDATOR_ELF, &input.encode_compact(), cancel).await?;
+    args.verify(&bundle).map_err(ProverError::from)?;
 
-    let seal_clone = seal.clone();
-    let mut state = user_state.lock().unwrap();
-   
-    let contract_addy = state.get(&email).unwrap().unwrap();
-    // Send the calldata to Ethereum.
-    let aa_contract = AaContract::new(
-        args.chain_id,
-        &args.rpc_url,
-        &args.eth_wallet_private_key,
-        &contract_addy.to_string(),
-    )
-    .expect("failed to create tx sender");
-
-    let claims = ClaimsData::abi_decode(&journal, true)
-        .context("decoding journal data")
-        .expect("failed to decode");
+    let claims = &bundle.claims;
 
     info!("Claim ID: {:?}", claims.claim_id);
     info!("Msg Sender: {:?}", claims.msg_sender);
-    let test_value: U256 = AlloyUtils::parse_units("10","Twei").unwrap().into();
-    
+    if claims.msg_sender.as_slice() != call.to.as_bytes() {
+        return Err(anyhow!("The token authorizes calls to {:?}, not to {:?}", claims.msg_sender, call.to).into());
+    }
+
+    if let (Some(bundler), Some(op)) = (bundler, user_op) {
+        let op = bundler.sign(op, &bundle, relayers.chain().chain_id())?;
+        let user_op_hash = bundler.send(&op).await?;
+        let receipt = bundler.wait(user_op_hash, Duration::from_secs(args.user_op_timeout)).await?;
+        if !receipt.success {
+            return Err(anyhow!("User operation {:?} reverted", user_op_hash).into());
+        }
+        return Ok(bundle);
+    }
 
     let calldata = IAaDemo::IAaDemoCalls::execute(IAaDemo::executeCall {
-        dest:  claims.msg_sender,
-        value: test_value,
-        func: Vec::new(),
+        dest: Address::from_slice(call.to.as_bytes()),
+        value: U256::from_limbs(call.value.0),
+        func: call.data.to_vec(),
         claim_id: claims.claim_id,
-        post_state_digest,
-        seal: seal_clone,
+        post_state_digest: bundle.post_state_digest,
+        seal: bundle.seal.clone(),
     })
     .abi_encode();
 
-    // Send the calldata to Ethereum.
-    let runtime = tokio::runtime::Runtime::new().expect("failed to start new tokio runtime");
-    runtime
-        .block_on(aa_contract.send(calldata))
-        .expect("failed to send tx");
+    // Deploy the account on its first use, then send the calldata to
+    // Ethereum, unless the simulation reverts.
+    let sent = async {
+        if let Some(deploy_tx) = factory.deploy_transaction(claims.claim_id.0).await? {
+            let outcome = relayers.send(deploy_tx).await?;
+            info!("Deployed account {:?}: {:?}", contract_addy, outcome);
+        }
+        relayers.send_call(contract_addy, calldata, EthersU256::zero()).await
+    }
+    .await;
+    if let Err(err) = sent {
+        return Err(match err.downcast::<ContractError>() {
+            Ok(revert) => RequestError::Revert(revert),
+            Err(err) => RequestError::Other(err),
+        });
+    }
 
-    tx.send((journal, post_state_digest, seal))
-        .expect("failed to send over channel");
+    Ok(bundle)
 }
-fn jwt_authentication_filter(  user_state: UserState) -> impl Filter<Extract = (String,String), Error = warp::Rejection> + Clone {
+
+fn jwt_authentication_filter() -> impl Filter<Extract = (String,String), Error = warp::Rejection> + Clone {
     warp::any()
         .and(warp::header::<String>(HEADER_XAUTH))
-        .and(with_user_state(user_state.clone()))
-        .and_then(|token: String, user_state: UserState| {
-
-        let mut state = user_state.lock().unwrap();
-        let user_state_clone = Arc::clone(&user_state);
-        async move {
-        handle_jwt_authentication(token, user_state_clone).await.map(|(email,token)| (email,token))
-        }}).untuple_one()
+        .and_then(|token: String| async move {
+            handle_jwt_authentication(token).await
+        })
+        .untuple_one()
 }
 
 
-fn auth_filter(user_state: UserState) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+fn auth_filter(user_state: UserState, relayers: WalletPool) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
     let cors = warp::cors()
         .allow_any_origin()
         .allow_methods(vec!["GET", "POST", "DELETE"])
@@ -401,58 +886,131 @@ fn auth_filter(user_state: UserState) -> impl Filter<Extract = impl Reply, Error
     warp::path("deploy" )
         .and(warp::get())
         .and(warp::path::end())
-        .and(jwt_authentication_filter(user_state.clone()))
+        .and(jwt_authentication_filter())
         .and(with_user_state(user_state))
-        .map(|email: String,token:String ,user_state:UserState| {
-            let mut state = user_state.lock().unwrap();
-
-             warp::reply::json(&state.get(&email).unwrap().unwrap().to_string())
-
-            })
+        .and(with_relayers(relayers))
+        .and_then(|email: String, _token: String, user_state: UserState, relayers: WalletPool| async move {
+            handle_deploy(email, user_state, relayers).await
+        })
         .with(cors)
 
    
     
 }
-fn execute_filter(user_state: UserState) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+fn execute_filter(user_state: UserState, prover: SharedProver, relayers: WalletPool) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
     let cors = warp::cors()
         .allow_any_origin()
         .allow_methods(vec!["GET", "POST", "DELETE"])
-        .allow_headers(vec!["content-type", "x-auth-token","x-dest"])
+        .allow_headers(vec!["content-type", "x-auth-token","x-dest", "x-call", "x-token", "x-amount"])
         .max_age(3600);
 
 
     warp::path("execute" )
         .and(warp::get())
         .and(warp::header::<String>(HEADER_DEST))
+        .and(warp::header::optional::<String>(HEADER_CALL))
+        .and(warp::header::optional::<String>(HEADER_TOKEN))
+        .and(warp::header::optional::<String>(HEADER_AMOUNT))
         .and(warp::path::end())
-        .and(jwt_authentication_filter(user_state.clone()))
+        .and(jwt_authentication_filter())
         .and(with_user_state(user_state))
-        .and_then( |dest, email,token ,  user_state: UserState| async move {
-            handle_execute(token, email, user_state, dest).await
+        .and(with_prover(prover))
+        .and(with_relayers(relayers))
+        .and_then( |dest, call, payment_token, payment_amount, email,token ,  user_state: UserState, prover: SharedProver, relayers: WalletPool| async move {
+            handle_execute(token, email, user_state, prover, relayers, dest, call, payment_token, payment_amount).await
         })
         .with(cors)    
     
 }
 
+/// Serves the indexed events of a claim ID, `/events/claim/<id>`, or of an
+/// account, `/events/account/<address>`.
+fn events_filter(store: Option<EventStore>) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    let cors = warp::cors()
+        .allow_any_origin()
+        .allow_methods(vec!["GET"])
+        .max_age(3600);
+
+    let by_claim_id = warp::path!("events" / "claim" / String)
+        .and(warp::get())
+        .and(with_event_store(store.clone()))
+        .map(|claim_id: String, store: EventStore| {
+            events_reply(
+                claim_id
+                    .parse::<ethers::types::H256>()
+                    .context("Invalid claim ID")
+                    .and_then(|claim_id| store.events_by_claim_id(claim_id)),
+            )
+        });
+    let by_account = warp::path!("events" / "account" / String)
+        .and(warp::get())
+        .and(with_event_store(store))
+        .map(|account: String, store: EventStore| {
+            events_reply(
+                account
+                    .parse::<EthersAddress>()
+                    .context("Invalid account")
+                    .and_then(|account| store.events_by_account(account)),
+            )
+        });
+
+    by_claim_id.or(by_account).with(cors)
+}
+
+fn events_reply(events: anyhow::Result<Vec<IndexedEvent>>) -> warp::reply::Response {
+    match events {
+        Ok(events) => warp::reply::json(&events).into_response(),
+        Err(err) => bad_request_reply(err),
+    }
+}
+
+/// Rejects the request, as not found, when indexing is disabled.
+fn with_event_store(
+    store: Option<EventStore>,
+) -> impl Filter<Extract = (EventStore,), Error = Rejection> + Clone {
+    warp::any().and_then(move || {
+        let store = store.clone();
+        async move { store.ok_or_else(warp::reject::not_found) }
+    })
+}
+
 fn with_user_state(
     user_state: UserState,
 ) -> impl Filter<Extract = (UserState,), Error = std::convert::Infallible> + Clone {
     warp::any().map(move || user_state.clone())
 }
 
+fn with_prover(
+    prover: SharedProver,
+) -> impl Filter<Extract = (SharedProver,), Error = std::convert::Infallible> + Clone {
+    warp::any().map(move || prover.clone())
+}
+
+fn with_relayers(
+    relayers: WalletPool,
+) -> impl Filter<Extract = (WalletPool,), Error = std::convert::Infallible> + Clone {
+    warp::any().map(move || relayers.clone())
+}
+
 #[tokio::main]
 async fn main() {
     env_logger::init();
     let user_state: UserState = Arc::new(Mutex::new(HashMap::new()));
 
 
-    let api = auth_filter(user_state.clone());
+    let args = Args::parse();
+    // Each relayer wallet sends its transactions through its own queue, so
+    // its nonces never collide and a stuck transaction only delays its own.
+    let relayers = args.relayers().await;
+
+    let api = auth_filter(user_state.clone(), relayers.clone());
 
-    let execute_route = execute_filter(user_state.clone());
+    let prover: SharedProver = args.prover().into();
+    let execute_route = execute_filter(user_state.clone(), prover, relayers);
+    let events_route = events_filter(args.event_store().await);
 
     // Combine routes
-    let routes = api.or(execute_route);
+    let routes = api.or(execute_route).or(events_route);
     
     warp::serve(routes).run(([127, 0, 0, 1], 8080)).await;
 }
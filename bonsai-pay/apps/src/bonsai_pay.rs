@@ -0,0 +1,171 @@
+// Copyright 2024 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Typed client of the `BonsaiPay` contract.
+//!
+//! The bindings are generated from the contract ABI in `BonsaiPay.json`.
+//! Transactions are returned unsent, so they can go through a
+//! [TxQueue](crate::TxQueue) or a [WalletPool](crate::WalletPool).
+
+use anyhow::{Context, Result};
+use ethers::{prelude::*, types::transaction::eip2718::TypedTransaction};
+use risc0_zkvm::sha::rust_crypto::{Digest as _, Sha256};
+
+use crate::{ChainClient, ProofBundle, SignerClient};
+
+// Kept out of the crate root, where the `AaDemo` bindings define errors and
+// events of the same names.
+abigen!(BonsaiPay, "./src/BonsaiPay.json");
+
+/// Returns the claim ID of `email`, as committed by the guest.
+pub fn claim_id_of(email: &str) -> [u8; 32] {
+    Sha256::digest(email.as_bytes()).into()
+}
+
+/// Client of a deployed `BonsaiPay` contract.
+#[derive(Clone)]
+pub struct BonsaiPayClient {
+    contract: BonsaiPay<SignerClient>,
+}
+
+impl BonsaiPayClient {
+    /// Creates a client of the contract at `address`, sending from the
+    /// wallet of `chain`.
+    pub fn new(chain: &ChainClient, address: Address) -> Self {
+        Self {
+            contract: BonsaiPay::new(address, chain.client()),
+        }
+    }
+
+    pub fn address(&self) -> Address {
+        self.contract.address()
+    }
+
+    /// The generated bindings, for calls not covered by this client.
+    pub fn contract(&self) -> &BonsaiPay<SignerClient> {
+        &self.contract
+    }
+
+    /// Returns the transaction depositing `amount` wei for `claim_id`.
+    pub fn deposit_transaction(&self, claim_id: [u8; 32], amount: U256) -> TypedTransaction {
+        self.contract.deposit(claim_id).value(amount).tx
+    }
+
+    /// Returns the transaction depositing `amount` wei for `email`.
+    pub fn deposit_for_email_transaction(&self, email: &str, amount: U256) -> TypedTransaction {
+        self.deposit_transaction(claim_id_of(email), amount)
+    }
+
+    /// Returns the transaction claiming the deposits of the claim ID proven
+    /// by `bundle`, sent to the address committed in its journal.
+    pub fn claim_transaction(&self, bundle: &ProofBundle) -> TypedTransaction {
+        let claims = &bundle.claims;
+        self.contract
+            .claim(
+                claims.msg_sender.0 .0.into(),
+                claims.claim_id.0,
+                bundle.post_state_digest.0,
+                bundle.seal.clone().into(),
+            )
+            .tx
+    }
+
+    /// Returns the transaction sending `value` wei to `to` once the proof of
+    /// `bundle` is verified.
+    pub fn execute_call_transaction(
+        &self,
+        to: Address,
+        bundle: &ProofBundle,
+        value: U256,
+    ) -> TypedTransaction {
+        self.contract
+            .execute_call(
+                to,
+                bundle.claims.claim_id.0,
+                bundle.post_state_digest.0,
+                bundle.seal.clone().into(),
+            )
+            .value(value)
+            .tx
+    }
+
+    /// Returns the balance pending for `claim_id`, in wei.
+    pub async fn balance_of(&self, claim_id: [u8; 32]) -> Result<U256> {
+        self.contract
+            .balance_of(claim_id)
+            .call()
+            .await
+            .context("Read balance")
+    }
+
+    /// Returns the balance pending for `email`, in wei.
+    pub async fn balance_of_email(&self, email: &str) -> Result<U256> {
+        self.balance_of(claim_id_of(email)).await
+    }
+
+    /// Returns the events emitted by the contract between `from_block` and
+    /// `to_block`, with the block and transaction of each.
+    pub async fn events(
+        &self,
+        from_block: BlockNumber,
+        to_block: BlockNumber,
+    ) -> Result<Vec<(BonsaiPayEvents, LogMeta)>> {
+        self.contract
+            .events()
+            .from_block(from_block)
+            .to_block(to_block)
+            .query_with_meta()
+            .await
+            .context("Read BonsaiPay events")
+    }
+
+    /// Decodes a log emitted by the contract, e.g. from
+    /// [ChainClient::subscribe_logs].
+    pub fn decode_event(log: &Log) -> Result<BonsaiPayEvents> {
+        Ok(BonsaiPayEvents::decode_log(&log.clone().into())?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ethers::{
+        abi::{RawLog, Token},
+        prelude::*,
+    };
+
+    use super::{claim_id_of, BonsaiPayClient, BonsaiPayEvents, DepositedFilter};
+
+    #[test]
+    fn test_decode_deposited() {
+        let claim_id = claim_id_of("bob@email.com");
+        let log = Log {
+            topics: vec![DepositedFilter::signature(), H256(claim_id)],
+            data: ethers::abi::encode(&[Token::Uint(U256::exp10(18))]).into(),
+            ..Default::default()
+        };
+        assert_eq!(
+            BonsaiPayClient::decode_event(&log).unwrap(),
+            BonsaiPayEvents::DepositedFilter(DepositedFilter {
+                claim_id,
+                amount: U256::exp10(18),
+            })
+        );
+
+        let unknown = RawLog {
+            topics: vec![H256::zero()],
+            data: Vec::new(),
+        };
+        assert!(BonsaiPayEvents::decode_log(&unknown).is_err());
+    }
+}
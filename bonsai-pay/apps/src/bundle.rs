@@ -0,0 +1,217 @@
+// Copyright 2024 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    fs,
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use alloy_primitives::FixedBytes;
+use anyhow::{bail, Context, Result};
+use bonsai_pay_types::{ClaimsData, GuestOutput};
+use methods::ZRP_VALIDATOR_ID;
+use serde::{Deserialize, Serialize};
+
+/// Decodes the journal committed by the guest of `image_id`, which tells the
+/// unversioned `zrp_validator` journals apart.
+pub(crate) fn decode_journal(image_id: FixedBytes<32>, journal: &[u8]) -> Result<GuestOutput> {
+    let output = if image_id.as_slice() == bytemuck::cast_slice::<_, u8>(&ZRP_VALIDATOR_ID) {
+        GuestOutput::decode_zrp(journal)
+    } else {
+        GuestOutput::decode(journal)
+    };
+    output.context("Read journal")
+}
+
+/// IDs of the Bonsai sessions that generated a proof.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct BonsaiSession {
+    pub session_id: String,
+    pub snark_id: String,
+}
+
+/// A proof of the `jwt_validator` or `zrp_validator` guest, with everything
+/// needed to submit it on-chain.
+///
+/// Bundles serialize to JSON or bincode, so proofs can be archived,
+/// inspected and submitted again later without proving again.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ProofBundle {
+    /// Image ID of the guest.
+    pub image_id: FixedBytes<32>,
+    /// Journal committed by the guest.
+    pub journal: Vec<u8>,
+    /// Claims decoded from the journal, in the layout of the `jwt_validator`
+    /// journal whichever guest committed it.
+    pub claims: ClaimsData,
+    /// ABI encoded seal, as expected by the verifier contract.
+    pub seal: Vec<u8>,
+    pub post_state_digest: FixedBytes<32>,
+    /// Bonsai sessions, for proofs generated on Bonsai.
+    pub session: Option<BonsaiSession>,
+    /// Unix timestamp, in seconds, of the proof request.
+    pub started_at: u64,
+    /// Unix timestamp, in seconds, of the proof completion.
+    pub proved_at: u64,
+}
+
+impl ProofBundle {
+    /// Creates a bundle completed now, decoding the claims from the journal.
+    pub fn new(
+        image_id: FixedBytes<32>,
+        journal: Vec<u8>,
+        post_state_digest: FixedBytes<32>,
+        seal: Vec<u8>,
+        started_at: u64,
+    ) -> Result<Self> {
+        let claims = match decode_journal(image_id, &journal)? {
+            GuestOutput::Claims(claims) => claims,
+            GuestOutput::ZrpClaims(claims) => claims.to_claims().context("Read journal")?,
+            GuestOutput::Failure(failure) => {
+                bail!("Guest rejected its input with code {}", failure.error_code)
+            }
+        };
+        Ok(Self {
+            image_id,
+            journal,
+            claims,
+            seal,
+            post_state_digest,
+            session: None,
+            started_at,
+            proved_at: unix_now(),
+        })
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    pub fn from_json(json: &str) -> Result<Self> {
+        Ok(serde_json::from_str(json)?)
+    }
+
+    pub fn to_bincode(&self) -> Result<Vec<u8>> {
+        Ok(bincode::serialize(self)?)
+    }
+
+    pub fn from_bincode(bytes: &[u8]) -> Result<Self> {
+        Ok(bincode::deserialize(bytes)?)
+    }
+
+    /// Writes the bundle to `path`, as JSON if its extension is `json` and
+    /// as bincode otherwise.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let bytes = if is_json(path) {
+            self.to_json()?.into_bytes()
+        } else {
+            self.to_bincode()?
+        };
+        fs::write(path, bytes).with_context(|| format!("Write {}", path.display()))
+    }
+
+    /// Reads a bundle written by [ProofBundle::save].
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let bytes = fs::read(path).with_context(|| format!("Read {}", path.display()))?;
+        if is_json(path) {
+            Self::from_json(std::str::from_utf8(&bytes)?)
+        } else {
+            Self::from_bincode(&bytes)
+        }
+    }
+}
+
+fn is_json(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "json")
+}
+
+/// Returns the current Unix timestamp, in seconds.
+pub(crate) fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use alloy_primitives::{Address, FixedBytes};
+    use bonsai_pay_types::{ClaimsData, ZrpClaimsData};
+    use methods::ZRP_VALIDATOR_ID;
+
+    use super::{BonsaiSession, ProofBundle};
+
+    fn test_bundle() -> ProofBundle {
+        let claims = ClaimsData::new(Address::repeat_byte(0x11), FixedBytes::repeat_byte(0x22));
+        let mut bundle = ProofBundle::new(
+            FixedBytes::repeat_byte(0x33),
+            claims.encode(),
+            FixedBytes::repeat_byte(0x44),
+            vec![0x55; 260],
+            1_700_000_000,
+        )
+        .unwrap();
+        bundle.session = Some(BonsaiSession {
+            session_id: "session".to_string(),
+            snark_id: "snark".to_string(),
+        });
+        bundle
+    }
+
+    #[test]
+    fn test_bundle_round_trip() {
+        let bundle = test_bundle();
+
+        assert_eq!(bundle.claims.msg_sender, Address::repeat_byte(0x11));
+        assert_eq!(
+            ProofBundle::from_json(&bundle.to_json().unwrap()).unwrap(),
+            bundle
+        );
+        assert_eq!(
+            ProofBundle::from_bincode(&bundle.to_bincode().unwrap()).unwrap(),
+            bundle
+        );
+    }
+
+    #[test]
+    fn test_bundle_save_and_load() {
+        let bundle = test_bundle();
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["bundle.json", "bundle.bin"] {
+            let path = dir.path().join(name);
+            bundle.save(&path).unwrap();
+            assert_eq!(ProofBundle::load(&path).unwrap(), bundle);
+        }
+    }
+
+    #[test]
+    fn test_zrp_bundle_decoded_by_image_id() {
+        // The beneficiary reads as the version word of the `jwt_validator`
+        // journals.
+        let beneficiary = Address::with_last_byte(1);
+        let zrp = ZrpClaimsData::new(beneficiary, FixedBytes::repeat_byte(0x22));
+        let zrp_image_id = FixedBytes::from_slice(bytemuck::cast_slice(&ZRP_VALIDATOR_ID));
+
+        let bundle =
+            ProofBundle::new(zrp_image_id, zrp.encode(), FixedBytes::ZERO, vec![], 0).unwrap();
+        assert_eq!(bundle.claims, zrp.to_claims().unwrap());
+
+        // Other guests never commit ZRP journals.
+        let image_id = FixedBytes::repeat_byte(0x33);
+        assert!(ProofBundle::new(image_id, zrp.encode(), FixedBytes::ZERO, vec![], 0).is_err());
+    }
+}
@@ -0,0 +1,384 @@
+// Copyright 2024 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use alloy_primitives::FixedBytes;
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use risc0_zkvm::{
+    compute_image_id,
+    sha::rust_crypto::{Digest as _, Sha256},
+};
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    bundle::{unix_now, ProofBundle},
+    Prover, ProverError, VerifyError,
+};
+
+const ENTRY_EXTENSION: &str = "bin";
+
+/// A content-addressed cache of [ProofBundle]s on disk, keyed by
+/// `(kind, image_id, sha256(input))`, where `kind` names the prover, so dev
+/// mode proofs are never returned in place of real ones.
+///
+/// Entries older than the time to live are dropped when read, and the least
+/// recently used entries are evicted once the cache holds more than
+/// `max_entries` proofs.
+pub struct ProofCache {
+    dir: PathBuf,
+    max_entries: Option<usize>,
+    ttl: Option<Duration>,
+}
+
+impl ProofCache {
+    /// Opens the cache stored in `dir`, creating the directory if needed.
+    pub fn new(dir: impl Into<PathBuf>) -> Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir).with_context(|| format!("Create {}", dir.display()))?;
+        Ok(Self {
+            dir,
+            max_entries: None,
+            ttl: None,
+        })
+    }
+
+    /// Bounds the number of cached proofs.
+    pub fn with_max_entries(mut self, max_entries: usize) -> Self {
+        self.max_entries = Some(max_entries);
+        self
+    }
+
+    /// Expires proofs `ttl` after they were generated.
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    fn path(&self, kind: &str, image_id: FixedBytes<32>, input: &[u8]) -> PathBuf {
+        let input_hash = Sha256::digest(input);
+        self.dir.join(format!(
+            "{kind}-{}-{}.{ENTRY_EXTENSION}",
+            hex::encode(image_id),
+            hex::encode(input_hash)
+        ))
+    }
+
+    /// Returns the proof of `input` cached by the `kind` prover, if any.
+    pub fn get(&self, kind: &str, image_id: FixedBytes<32>, input: &[u8]) -> Option<ProofBundle> {
+        let path = self.path(kind, image_id, input);
+        if !path.is_file() {
+            return None;
+        }
+
+        let bundle = match ProofBundle::load(&path) {
+            Ok(bundle) if bundle.image_id == image_id => bundle,
+            Ok(_) | Err(_) => {
+                log::warn!("Dropping invalid cached proof {}", path.display());
+                let _ = fs::remove_file(&path);
+                return None;
+            }
+        };
+        if self.is_expired(&bundle) {
+            log::info!("Dropping expired cached proof {}", path.display());
+            let _ = fs::remove_file(&path);
+            return None;
+        }
+
+        // Mark the entry as recently used.
+        if let Err(err) = touch(&path) {
+            log::warn!("Failed to touch {}: {}", path.display(), err);
+        }
+        Some(bundle)
+    }
+
+    /// Stores the proof of `input` generated by the `kind` prover, then
+    /// evicts the least recently used proofs beyond `max_entries`.
+    pub fn insert(&self, kind: &str, input: &[u8], bundle: &ProofBundle) -> Result<()> {
+        bundle.save(self.path(kind, bundle.image_id, input))?;
+        self.evict()
+    }
+
+    /// Drops the proof of `input` cached by the `kind` prover, if any.
+    pub fn remove(&self, kind: &str, image_id: FixedBytes<32>, input: &[u8]) -> Result<()> {
+        let path = self.path(kind, image_id, input);
+        match fs::remove_file(&path) {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => {
+                Err(err).with_context(|| format!("Remove {}", path.display()))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn is_expired(&self, bundle: &ProofBundle) -> bool {
+        self.ttl
+            .is_some_and(|ttl| unix_now().saturating_sub(bundle.proved_at) > ttl.as_secs())
+    }
+
+    fn evict(&self) -> Result<()> {
+        let Some(max_entries) = self.max_entries else {
+            return Ok(());
+        };
+
+        let mut entries = Vec::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == ENTRY_EXTENSION) {
+                let modified = fs::metadata(&path)?.modified()?;
+                entries.push((modified, path));
+            }
+        }
+        if entries.len() <= max_entries {
+            return Ok(());
+        }
+
+        entries.sort();
+        for (_, path) in &entries[..entries.len() - max_entries] {
+            log::info!("Evicting cached proof {}", path.display());
+            fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+}
+
+fn touch(path: &Path) -> std::io::Result<()> {
+    fs::File::options()
+        .write(true)
+        .open(path)?
+        .set_modified(SystemTime::now())
+}
+
+/// Checks a proof before it is cached or returned from the cache.
+pub type BundleVerifier = Box<dyn Fn(&ProofBundle) -> Result<(), VerifyError> + Send + Sync>;
+
+/// A [Prover] returning the proofs stored in a [ProofCache], and caching the
+/// proofs generated by the wrapped prover.
+///
+/// Identical requests, such as retries, are only proven once. With a
+/// verifier, only proofs it accepts are cached, and a cached proof it rejects
+/// is dropped and proven again.
+pub struct CachedProver {
+    kind: String,
+    inner: Box<dyn Prover>,
+    cache: ProofCache,
+    verifier: Option<BundleVerifier>,
+}
+
+impl CachedProver {
+    /// Caches the proofs of `inner` under `kind`, which must differ between
+    /// provers sharing the cache directory.
+    pub fn new(kind: impl Into<String>, inner: Box<dyn Prover>, cache: ProofCache) -> Self {
+        Self {
+            kind: kind.into(),
+            inner,
+            cache,
+            verifier: None,
+        }
+    }
+
+    /// Checks each proof with `verifier` before it is cached or returned.
+    pub fn with_verifier(
+        mut self,
+        verifier: impl Fn(&ProofBundle) -> Result<(), VerifyError> + Send + Sync + 'static,
+    ) -> Self {
+        self.verifier = Some(Box::new(verifier));
+        self
+    }
+
+    fn verify(&self, bundle: &ProofBundle) -> Result<(), VerifyError> {
+        match &self.verifier {
+            Some(verifier) => verifier(bundle),
+            None => Ok(()),
+        }
+    }
+}
+
+#[async_trait]
+impl Prover for CachedProver {
+    async fn prove(
+        &self,
+        elf: &[u8],
+        input: &[u8],
+        cancel: &CancellationToken,
+    ) -> Result<ProofBundle, ProverError> {
+        let image_id = FixedBytes::from_slice(compute_image_id(elf)?.as_bytes());
+        if let Some(bundle) = self.cache.get(&self.kind, image_id, input) {
+            match self.verify(&bundle) {
+                Ok(()) => {
+                    log::info!("Using cached proof of claim {}", bundle.claims.claim_id);
+                    return Ok(bundle);
+                }
+                Err(err) => {
+                    log::warn!("Dropping rejected cached proof: {}", err);
+                    if let Err(err) = self.cache.remove(&self.kind, image_id, input) {
+                        log::warn!("Failed to drop cached proof: {:?}", err);
+                    }
+                }
+            }
+        }
+
+        let bundle = self.inner.prove(elf, input, cancel).await?;
+        self.verify(&bundle)?;
+        // The proof is still usable if it cannot be cached.
+        if let Err(err) = self.cache.insert(&self.kind, input, &bundle) {
+            log::warn!("Failed to cache proof: {:?}", err);
+        }
+        Ok(bundle)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+
+    use alloy_primitives::{Address, FixedBytes};
+    use async_trait::async_trait;
+    use bonsai_pay_types::ClaimsData;
+    use methods::JWT_VALIDATOR_ELF;
+    use tokio_util::sync::CancellationToken;
+
+    use super::{CachedProver, ProofCache};
+    use crate::{
+        bundle::{unix_now, ProofBundle},
+        Prover, ProverError, VerifyError,
+    };
+
+    /// Returns the same proof to every request, counting them.
+    struct FixedProver {
+        bundle: ProofBundle,
+        calls: Arc<AtomicUsize>,
+    }
+
+    #[async_trait]
+    impl Prover for FixedProver {
+        async fn prove(
+            &self,
+            _elf: &[u8],
+            _input: &[u8],
+            _cancel: &CancellationToken,
+        ) -> Result<ProofBundle, ProverError> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            Ok(self.bundle.clone())
+        }
+    }
+
+    fn test_bundle(image_id: u8, proved_at: u64) -> ProofBundle {
+        let claims = ClaimsData::new(Address::repeat_byte(0x11), FixedBytes::repeat_byte(0x22));
+        let mut bundle = ProofBundle::new(
+            FixedBytes::repeat_byte(image_id),
+            claims.encode(),
+            FixedBytes::repeat_byte(0x44),
+            vec![0x55; 260],
+            proved_at,
+        )
+        .unwrap();
+        bundle.proved_at = proved_at;
+        bundle
+    }
+
+    #[test]
+    fn test_cache_hit_and_miss() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = ProofCache::new(dir.path()).unwrap();
+        let bundle = test_bundle(0x33, unix_now());
+
+        assert!(cache.get("test", bundle.image_id, b"input").is_none());
+        cache.insert("test", b"input", &bundle).unwrap();
+        assert_eq!(
+            cache.get("test", bundle.image_id, b"input"),
+            Some(bundle.clone())
+        );
+        assert!(cache.get("test", bundle.image_id, b"other input").is_none());
+        assert!(cache.get("test", FixedBytes::ZERO, b"input").is_none());
+    }
+
+    #[test]
+    fn test_cache_expiry() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = ProofCache::new(dir.path())
+            .unwrap()
+            .with_ttl(Duration::from_secs(3600));
+        let expired = test_bundle(0x33, unix_now() - 7200);
+
+        cache.insert("test", b"input", &expired).unwrap();
+        assert!(cache.get("test", expired.image_id, b"input").is_none());
+    }
+
+    #[test]
+    fn test_cache_eviction() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = ProofCache::new(dir.path()).unwrap().with_max_entries(2);
+        let bundles: Vec<_> = (1..=3).map(|id| test_bundle(id, unix_now())).collect();
+
+        for bundle in &bundles {
+            cache.insert("test", b"input", bundle).unwrap();
+            // Keep modification times apart.
+            std::thread::sleep(Duration::from_millis(20));
+        }
+
+        assert!(cache.get("test", bundles[0].image_id, b"input").is_none());
+        assert!(cache.get("test", bundles[1].image_id, b"input").is_some());
+        assert!(cache.get("test", bundles[2].image_id, b"input").is_some());
+    }
+
+    #[test]
+    fn test_cache_keyed_by_prover_kind() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = ProofCache::new(dir.path()).unwrap();
+        let bundle = test_bundle(0x33, unix_now());
+
+        cache.insert("dev", b"input", &bundle).unwrap();
+        assert!(cache.get("bonsai", bundle.image_id, b"input").is_none());
+        assert_eq!(cache.get("dev", bundle.image_id, b"input"), Some(bundle));
+    }
+
+    #[tokio::test]
+    async fn test_rejected_proof_not_cached() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path();
+        let calls = Arc::new(AtomicUsize::new(0));
+        let mut bundle = test_bundle(0x33, unix_now());
+        bundle.seal.clear();
+        let inner = FixedProver {
+            bundle,
+            calls: calls.clone(),
+        };
+        let prover = CachedProver::new("bonsai", Box::new(inner), ProofCache::new(path).unwrap())
+            .with_verifier(|bundle| match bundle.seal.is_empty() {
+                true => Err(VerifyError::InvalidSeal("empty seal".to_string())),
+                false => Ok(()),
+            });
+
+        for _ in 0..2 {
+            let err = prover
+                .prove(JWT_VALIDATOR_ELF, b"input", &CancellationToken::new())
+                .await
+                .unwrap_err();
+            assert!(matches!(err, ProverError::InvalidProof(_)));
+        }
+        // The second request was proven again instead of served from the cache.
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+}
@@ -0,0 +1,348 @@
+// Copyright 2024 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Calls made by an AA account through `AADemo.execute`.
+//!
+//! The journal commits to the destination, the value and the calldata hash
+//! of the call, which the nonce of the token must authorize, so the call is
+//! built before signing in and proving, and a proof cannot be used to make a
+//! call other than the one it was made for.
+
+use anyhow::{anyhow, bail, ensure, Context, Result};
+use ethers::{
+    abi::{
+        token::{LenientTokenizer, Tokenizer},
+        HumanReadableParser, ParamType, Token as AbiToken,
+    },
+    prelude::*,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{erc20_approve_calldata, erc20_transfer_calldata};
+
+/// A call, with the arguments of `execute`.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Call {
+    pub to: Address,
+    /// Value sent with the call, in wei.
+    pub value: U256,
+    pub data: Bytes,
+}
+
+impl Call {
+    /// The keccak256 hash of the calldata, committed in the journal with the
+    /// value of the call.
+    pub fn func_hash(&self) -> H256 {
+        H256(ethers::utils::keccak256(&self.data))
+    }
+
+    /// Sends `amount` wei to `to`.
+    pub fn eth_transfer(to: Address, amount: U256) -> Self {
+        Self {
+            to,
+            value: amount,
+            data: Bytes::new(),
+        }
+    }
+
+    /// Transfers `amount` of the ERC-20 `token` to `to`.
+    pub fn erc20_transfer(token: Address, to: Address, amount: U256) -> Self {
+        Self {
+            to: token,
+            value: U256::zero(),
+            data: erc20_transfer_calldata(to, amount).into(),
+        }
+    }
+
+    /// Allows `spender` to transfer `amount` of the ERC-20 `token`.
+    pub fn erc20_approve(token: Address, spender: Address, amount: U256) -> Self {
+        Self {
+            to: token,
+            value: U256::zero(),
+            data: erc20_approve_calldata(spender, amount).into(),
+        }
+    }
+
+    /// Transfers the ERC-721 `token_id` of `collection` from `from` to `to`,
+    /// with `safeTransferFrom`.
+    pub fn erc721_transfer(
+        collection: Address,
+        from: Address,
+        to: Address,
+        token_id: U256,
+    ) -> Self {
+        let data = encode_function_call(
+            "safeTransferFrom(address,address,uint256)",
+            &[
+                AbiToken::Address(from),
+                AbiToken::Address(to),
+                AbiToken::Uint(token_id),
+            ],
+        )
+        .expect("valid ERC-721 signature");
+        Self {
+            to: collection,
+            value: U256::zero(),
+            data: data.into(),
+        }
+    }
+
+    /// Batches `calls` into one call of `executeBatch` on the AA `account`,
+    /// reverting if any of them fails.
+    ///
+    /// The batched calls are made by the account, with its Ether and tokens,
+    /// and the journal commits to all of them through the batch calldata.
+    pub fn batch(account: Address, calls: &[Call]) -> Self {
+        let data = encode_function_call(
+            "executeBatch(address[],uint256[],bytes[])",
+            &[
+                AbiToken::Array(
+                    calls
+                        .iter()
+                        .map(|call| AbiToken::Address(call.to))
+                        .collect(),
+                ),
+                AbiToken::Array(
+                    calls
+                        .iter()
+                        .map(|call| AbiToken::Uint(call.value))
+                        .collect(),
+                ),
+                AbiToken::Array(
+                    calls
+                        .iter()
+                        .map(|call| AbiToken::Bytes(call.data.to_vec()))
+                        .collect(),
+                ),
+            ],
+        )
+        .expect("valid executeBatch signature");
+        Self {
+            to: account,
+            value: U256::zero(),
+            data: data.into(),
+        }
+    }
+
+    /// Calls the function with the human-readable `signature`, e.g.
+    /// `transfer(address to, uint256 amount)`, with its JSON arguments.
+    ///
+    /// Numbers may be JSON numbers or decimal or hex strings, addresses and
+    /// bytes hex strings, and arrays and tuples JSON arrays.
+    pub fn from_signature(
+        to: Address,
+        value: U256,
+        signature: &str,
+        args: &[Value],
+    ) -> Result<Self> {
+        let function = parse_function(signature)?;
+        ensure!(
+            function.inputs.len() == args.len(),
+            "{} expects {} arguments, got {}",
+            function.name,
+            function.inputs.len(),
+            args.len()
+        );
+
+        let tokens = function
+            .inputs
+            .iter()
+            .zip(args)
+            .map(|(param, arg)| {
+                tokenize(&param.kind, arg)
+                    .with_context(|| format!("Invalid argument {}", param.name))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let data = function
+            .encode_input(&tokens)
+            .with_context(|| format!("Encode call of {}", function.name))?;
+        Ok(Self {
+            to,
+            value,
+            data: data.into(),
+        })
+    }
+}
+
+/// Returns the calldata of the function with the human-readable `signature`
+/// called with `args`.
+pub fn encode_function_call(signature: &str, args: &[AbiToken]) -> Result<Vec<u8>> {
+    let function = parse_function(signature)?;
+    Ok(function.encode_input(args)?)
+}
+
+fn parse_function(signature: &str) -> Result<ethers::abi::Function> {
+    let signature = signature.trim();
+    let signature = if signature.starts_with("function ") {
+        signature.to_string()
+    } else {
+        format!("function {signature}")
+    };
+    HumanReadableParser::parse_function(&signature)
+        .with_context(|| format!("Invalid function signature: {signature}"))
+}
+
+fn tokenize(kind: &ParamType, arg: &Value) -> Result<AbiToken> {
+    match kind {
+        ParamType::Array(inner) => Ok(AbiToken::Array(tokenize_all(
+            std::iter::repeat(inner.as_ref()),
+            arg,
+        )?)),
+        ParamType::FixedArray(inner, len) => {
+            let tokens = tokenize_all(std::iter::repeat(inner.as_ref()), arg)?;
+            ensure!(tokens.len() == *len, "Expected {len} elements");
+            Ok(AbiToken::FixedArray(tokens))
+        }
+        ParamType::Tuple(kinds) => {
+            ensure!(
+                arg.as_array().map(Vec::len) == Some(kinds.len()),
+                "Expected {} fields, got {arg}",
+                kinds.len()
+            );
+            Ok(AbiToken::Tuple(tokenize_all(kinds.iter(), arg)?))
+        }
+        _ => {
+            let value = match arg {
+                Value::String(value) => value.clone(),
+                Value::Number(value) => value.to_string(),
+                Value::Bool(value) => value.to_string(),
+                _ => bail!("Expected a {kind}, got {arg}"),
+            };
+            // The tokenizer expects hex without prefix, and numbers in
+            // decimal.
+            match (kind, value.strip_prefix("0x")) {
+                (ParamType::Uint(_), Some(hex)) => {
+                    Ok(AbiToken::Uint(U256::from_str_radix(hex, 16)?))
+                }
+                (ParamType::Address | ParamType::Bytes | ParamType::FixedBytes(_), Some(hex)) => {
+                    Ok(LenientTokenizer::tokenize(kind, hex)?)
+                }
+                _ => Ok(LenientTokenizer::tokenize(kind, &value)?),
+            }
+        }
+    }
+}
+
+fn tokenize_all<'a>(
+    kinds: impl Iterator<Item = &'a ParamType>,
+    arg: &Value,
+) -> Result<Vec<AbiToken>> {
+    let args = arg
+        .as_array()
+        .ok_or_else(|| anyhow!("Expected a JSON array, got {arg}"))?;
+    kinds
+        .zip(args)
+        .map(|(kind, arg)| tokenize(kind, arg))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use ethers::{
+        abi::{ParamType, Token},
+        prelude::*,
+    };
+    use serde_json::json;
+
+    use super::Call;
+
+    #[test]
+    fn test_from_signature() {
+        let token = Address::repeat_byte(0x11);
+        let to = Address::repeat_byte(0x22);
+
+        let call = Call::from_signature(
+            token,
+            U256::zero(),
+            "transfer(address to, uint256 amount)",
+            &[json!(format!("{to:?}")), json!("1000")],
+        )
+        .unwrap();
+        assert_eq!(call, Call::erc20_transfer(token, to, U256::from(1000)));
+
+        assert!(Call::from_signature(token, U256::zero(), "transfer(address)", &[]).is_err());
+        assert!(
+            Call::from_signature(token, U256::zero(), "transfer(address)", &[json!("0x12")])
+                .is_err()
+        );
+
+        // Arrays and tuples are JSON arrays.
+        let call = Call::from_signature(
+            token,
+            U256::zero(),
+            "f(uint256[] ids, (bool, bytes32) pair)",
+            &[
+                json!([1, "0x02"]),
+                json!([true, format!("0x{}", "33".repeat(32))]),
+            ],
+        )
+        .unwrap();
+        assert_eq!(call.data.len(), 4 + 32 * 6);
+    }
+
+    #[test]
+    fn test_func_hash() {
+        let call = Call::eth_transfer(Address::repeat_byte(0x11), U256::from(1));
+        assert_eq!(
+            call.func_hash(),
+            "0xc5d2460186f7233c927e7db2dcc703c0e500b653ca82273b7bfad8045d85a470"
+                .parse::<H256>()
+                .unwrap()
+        );
+
+        let transfer = Call::erc20_transfer(call.to, call.to, U256::from(1));
+        assert_eq!(
+            transfer.func_hash(),
+            H256(ethers::utils::keccak256(&transfer.data))
+        );
+    }
+
+    #[test]
+    fn test_batch() {
+        let account = Address::repeat_byte(0xaa);
+        let calls = [
+            Call::eth_transfer(Address::repeat_byte(0x11), U256::from(1)),
+            Call::erc20_transfer(
+                Address::repeat_byte(0x22),
+                Address::repeat_byte(0x33),
+                U256::from(2),
+            ),
+        ];
+        let batch = Call::batch(account, &calls);
+
+        // The account pays the batched values itself.
+        assert_eq!((batch.to, batch.value), (account, U256::zero()));
+        assert_eq!(
+            batch.data[..4],
+            ethers::utils::id("executeBatch(address[],uint256[],bytes[])")
+        );
+        let tokens = ethers::abi::decode(
+            &[
+                ParamType::Array(Box::new(ParamType::Address)),
+                ParamType::Array(Box::new(ParamType::Uint(256))),
+                ParamType::Array(Box::new(ParamType::Bytes)),
+            ],
+            &batch.data[4..],
+        )
+        .unwrap();
+        assert_eq!(
+            tokens[2],
+            Token::Array(vec![
+                Token::Bytes(Vec::new()),
+                Token::Bytes(calls[1].data.to_vec())
+            ])
+        );
+    }
+}
@@ -0,0 +1,225 @@
+// Copyright 2024 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use ethers::{prelude::*, types::transaction::eip2718::TypedTransaction};
+
+use crate::{AaDemo, ContractError, RelayerSigner, RpcPool};
+
+/// The signing client shared by a [ChainClient].
+pub type SignerClient = SignerMiddleware<Provider<RpcPool>, RelayerSigner>;
+
+// Margin added to the estimated gas, in percent.
+const DEFAULT_GAS_MARGIN: u64 = 20;
+
+/// Client sending the transactions of the relayer wallet.
+///
+/// All transactions share one provider and signer. The provider is an
+/// [RpcPool] of one or more endpoints, connected over HTTP, WebSocket or IPC
+/// depending on their URL. Gas limits are estimated
+/// with a margin, and fees follow EIP-1559 on chains supporting it, with a
+/// fallback to legacy gas prices. Transactions are simulated before they are
+/// sent, so a transaction bound to revert fails with its [ContractError]
+/// without paying gas.
+#[derive(Clone)]
+pub struct ChainClient {
+    chain_id: u64,
+    client: Arc<SignerClient>,
+    gas_margin: u64,
+}
+
+impl ChainClient {
+    /// Creates a new `ChainClient` sending transactions through `pool`,
+    /// signed by `signer`, e.g. as loaded by
+    /// [SignerSource::signer](crate::SignerSource::signer).
+    pub fn new(chain_id: u64, pool: RpcPool, signer: RelayerSigner) -> Self {
+        let signer = signer.with_chain_id(chain_id);
+        let client = Arc::new(SignerMiddleware::new(Provider::new(pool), signer));
+        log::info!("tx Address: {:?}", client.address());
+
+        ChainClient {
+            chain_id,
+            client,
+            gas_margin: DEFAULT_GAS_MARGIN,
+        }
+    }
+
+    /// Returns a client sharing the RPC endpoints of this one, sending
+    /// transactions signed by `signer`.
+    pub fn with_signer(&self, signer: RelayerSigner) -> Self {
+        let signer = signer.with_chain_id(self.chain_id);
+        let provider = self.client.provider().clone();
+        let client = Arc::new(SignerMiddleware::new(provider, signer));
+        log::info!("tx Address: {:?}", client.address());
+
+        ChainClient {
+            chain_id: self.chain_id,
+            client,
+            gas_margin: self.gas_margin,
+        }
+    }
+
+    /// Connects a new `ChainClient` to the single node at `rpc_url`.
+    pub async fn connect(chain_id: u64, rpc_url: &str, signer: RelayerSigner) -> Result<Self> {
+        let pool = RpcPool::connect(&[rpc_url]).await?;
+        Ok(Self::new(chain_id, pool, signer))
+    }
+
+    /// Sets the margin added to the estimated gas, in percent.
+    pub fn with_gas_margin(mut self, gas_margin: u64) -> Self {
+        self.gas_margin = gas_margin;
+        self
+    }
+
+    /// Address of the relayer wallet.
+    pub fn address(&self) -> Address {
+        self.client.address()
+    }
+
+    pub fn chain_id(&self) -> u64 {
+        self.chain_id
+    }
+
+    /// The underlying signing client, e.g. for contract bindings.
+    pub fn client(&self) -> Arc<SignerClient> {
+        self.client.clone()
+    }
+
+    /// Whether the node pushes new blocks and logs, over WebSocket or IPC.
+    pub fn supports_subscriptions(&self) -> bool {
+        self.client.provider().as_ref().supports_subscriptions()
+    }
+
+    /// Subscribes to the new blocks, which requires a WebSocket or IPC
+    /// connection.
+    pub async fn subscribe_blocks(&self) -> Result<SubscriptionStream<'_, RpcPool, Block<TxHash>>> {
+        Ok(self.client.provider().subscribe_blocks().await?)
+    }
+
+    /// Subscribes to the logs matching `filter`, e.g. the events of a
+    /// contract, which requires a WebSocket or IPC connection.
+    pub async fn subscribe_logs(
+        &self,
+        filter: &Filter,
+    ) -> Result<SubscriptionStream<'_, RpcPool, Log>> {
+        Ok(self.client.provider().subscribe_logs(filter).await?)
+    }
+
+    /// Returns the transaction deploying an `AaDemo` account checking proofs
+    /// with `verifier`.
+    pub fn deploy_aa_transaction(&self, verifier: Address) -> Result<TypedTransaction> {
+        Ok(AaDemo::deploy(self.client.clone(), verifier)?.deployer.tx)
+    }
+
+    /// Deploys an `AaDemo` account checking proofs with `verifier`.
+    pub async fn deploy_aa(&self, verifier: Address) -> Result<(Address, TransactionReceipt)> {
+        let deployer = AaDemo::deploy(self.client.clone(), verifier)?;
+        let (contract, receipt) = deployer.send_with_receipt().await?;
+
+        log::info!("Deploy transaction receipt: {:?}", receipt);
+
+        Ok((contract.address(), receipt))
+    }
+
+    /// Calls `to` with the given calldata, without sending a transaction.
+    pub async fn call(&self, to: Address, calldata: Vec<u8>) -> Result<Bytes> {
+        self.simulate(&self.transaction(to, calldata, U256::zero()))
+            .await
+    }
+
+    /// Runs `tx` with `eth_call` against the latest block.
+    ///
+    /// If the call reverts with revert data, the returned error is the
+    /// decoded [ContractError].
+    pub async fn simulate(&self, tx: &TypedTransaction) -> Result<Bytes> {
+        let mut tx = tx.clone();
+        tx.set_from(self.address());
+        match self.client.call(&tx, None).await {
+            Ok(output) => Ok(output),
+            Err(err) => match ContractError::from_middleware_error(&err) {
+                Some(revert) => Err(revert.into()),
+                None => Err(anyhow::Error::new(err).context("Simulate transaction")),
+            },
+        }
+    }
+
+    /// Send a transaction with the given calldata and value.
+    pub async fn send(
+        &self,
+        to: Address,
+        calldata: Vec<u8>,
+        value: U256,
+    ) -> Result<Option<TransactionReceipt>> {
+        let mut tx = self.transaction(to, calldata, value);
+        self.simulate(&tx).await?;
+        self.fill(&mut tx).await?;
+
+        log::info!("Transaction request: {:?}", tx);
+
+        let tx = self.client.send_transaction(tx, None).await?.await?;
+
+        log::info!("Transaction receipt: {:?}", tx);
+
+        Ok(tx)
+    }
+
+    /// Returns a transaction calling `to` with the given calldata and value.
+    pub fn transaction(&self, to: Address, calldata: Vec<u8>, value: U256) -> TypedTransaction {
+        Eip1559TransactionRequest::new()
+            .chain_id(self.chain_id)
+            .from(self.address())
+            .to(to)
+            .data(calldata)
+            .value(value)
+            .into()
+    }
+
+    /// Sets an estimated gas limit and the current fees on `tx`.
+    pub(crate) async fn fill(&self, tx: &mut TypedTransaction) -> Result<()> {
+        tx.set_from(self.address());
+        let gas = self
+            .client
+            .estimate_gas(tx, None)
+            .await
+            .context("Estimate gas")?;
+        tx.set_gas(gas * (100 + self.gas_margin) / 100);
+
+        match self.client.estimate_eip1559_fees(None).await {
+            Ok((max_fee, max_priority_fee)) => {
+                if let TypedTransaction::Eip1559(inner) = tx {
+                    inner.max_fee_per_gas = Some(max_fee);
+                    inner.max_priority_fee_per_gas = Some(max_priority_fee);
+                }
+            }
+            Err(err) => {
+                log::info!("EIP-1559 fees unavailable, using a legacy gas price: {err}");
+                let gas_price = self.client.get_gas_price().await?;
+                *tx = TypedTransaction::Legacy(TransactionRequest {
+                    from: tx.from().copied(),
+                    to: tx.to().cloned(),
+                    gas: tx.gas().copied(),
+                    gas_price: Some(gas_price),
+                    value: tx.value().copied(),
+                    data: tx.data().cloned(),
+                    nonce: tx.nonce().copied(),
+                    chain_id: Some(self.chain_id.into()),
+                });
+            }
+        }
+
+        Ok(())
+    }
+}
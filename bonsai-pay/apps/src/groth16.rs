@@ -0,0 +1,267 @@
+// Copyright 2024 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Local STARK-to-SNARK wrapping of succinct receipts.
+//!
+//! The succinct receipt is first compressed with the `identity_p254`
+//! recursion program, then its seal is handed to a Groth16 prover as
+//! `input.json`, which writes the Groth16 proof to `proof.json`. This is the
+//! same exchange as the one of the RISC Zero Groth16 prover image, so that
+//! image, a pinned copy of it, or a prover binary built from it can be used.
+//!
+//! The succinct receipt itself comes from the local zkVM prover of the
+//! `LocalProver`, so nothing is sent to Bonsai.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use anyhow::{bail, ensure, Context, Result};
+use risc0_zkvm::{ReceiptClaim, SuccinctReceipt};
+
+/// Tool wrapping a succinct receipt into a Groth16 seal.
+#[derive(Clone, Debug, Default)]
+pub enum SnarkBackend {
+    /// The pipeline built into the local zkVM prover, in-process or `r0vm`,
+    /// which runs the RISC Zero Groth16 prover image with Docker. Like the
+    /// other backends, it never proves on Bonsai.
+    #[default]
+    Docker,
+    /// A locally installed prover binary, called as
+    /// `<binary> <input.json> <proof.json>`.
+    Binary(PathBuf),
+    /// A prover image run by a container runtime such as `docker` or
+    /// `podman`, with the working directory mounted on `/mnt`.
+    Container { runtime: String, image: String },
+}
+
+/// Wraps a succinct receipt into a Groth16 seal, encoded as
+/// `risc0_groth16::Seal::to_vec`.
+#[cfg(feature = "prove")]
+pub(crate) fn stark_to_snark(
+    backend: &SnarkBackend,
+    receipt: &SuccinctReceipt<ReceiptClaim>,
+) -> Result<Vec<u8>> {
+    let identity =
+        risc0_zkvm::recursion::identity_p254(receipt).context("Compress succinct receipt")?;
+    let seal_bytes = identity.get_seal_bytes();
+
+    let work_dir = tempfile::tempdir()?;
+    risc0_groth16::to_json(
+        seal_bytes.as_slice(),
+        fs::File::create(work_dir.path().join(INPUT_FILE))?,
+    )
+    .context("Write Groth16 prover input")?;
+
+    run_backend(backend, work_dir.path())
+}
+
+#[cfg(not(feature = "prove"))]
+pub(crate) fn stark_to_snark(
+    _backend: &SnarkBackend,
+    _receipt: &SuccinctReceipt<ReceiptClaim>,
+) -> Result<Vec<u8>> {
+    bail!("Local Groth16 proving requires the `prove` feature of apps")
+}
+
+const INPUT_FILE: &str = "input.json";
+const PROOF_FILE: &str = "proof.json";
+
+/// Returns the command proving the `input.json` of `work_dir` into its
+/// `proof.json`.
+fn backend_command(backend: &SnarkBackend, work_dir: &Path) -> Result<Command> {
+    let command = match backend {
+        SnarkBackend::Docker => bail!("The Docker backend is run by risc0_zkvm"),
+        SnarkBackend::Binary(binary) => {
+            let mut command = Command::new(binary);
+            command
+                .arg(work_dir.join(INPUT_FILE))
+                .arg(work_dir.join(PROOF_FILE));
+            command
+        }
+        SnarkBackend::Container { runtime, image } => {
+            let mut command = Command::new(runtime);
+            command
+                .arg("run")
+                .arg("--rm")
+                .arg("-v")
+                .arg(format!("{}:/mnt", work_dir.display()))
+                .arg(image);
+            command
+        }
+    };
+    Ok(command)
+}
+
+/// Runs `backend` on the `input.json` of `work_dir`, and returns the seal
+/// read from the `proof.json` it writes.
+#[cfg_attr(not(feature = "prove"), allow(dead_code))]
+fn run_backend(backend: &SnarkBackend, work_dir: &Path) -> Result<Vec<u8>> {
+    let mut command = backend_command(backend, work_dir)?;
+    let program = command.get_program().to_string_lossy().into_owned();
+    let status = command.status().with_context(|| format!("Run {program}"))?;
+    ensure!(status.success(), "Groth16 prover exited with {status}");
+
+    let proof = fs::read_to_string(work_dir.join(PROOF_FILE)).context("Read Groth16 proof")?;
+    let proof: risc0_groth16::ProofJson =
+        serde_json::from_str(&proof).context("Parse Groth16 proof")?;
+    let seal: risc0_groth16::Seal = proof.try_into()?;
+
+    Ok(seal.to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        ffi::OsStr,
+        fs,
+        path::{Path, PathBuf},
+    };
+
+    use tempfile::TempDir;
+
+    use super::{backend_command, run_backend, SnarkBackend, INPUT_FILE};
+
+    // A proof in the `proof.json` layout of the Groth16 prover.
+    const PROOF_JSON: &str = r#"{
+        "pi_a": ["1", "2", "1"],
+        "pi_b": [["3", "4"], ["5", "6"], ["1", "0"]],
+        "pi_c": ["7", "8", "1"],
+        "protocol": "groth16",
+        "curve": "bn128"
+    }"#;
+
+    fn test_dir() -> TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join(INPUT_FILE), "{}").unwrap();
+        dir
+    }
+
+    fn expected_seal() -> Vec<u8> {
+        let proof: risc0_groth16::ProofJson = serde_json::from_str(PROOF_JSON).unwrap();
+        risc0_groth16::Seal::try_from(proof).unwrap().to_vec()
+    }
+
+    // Writes an executable shell script standing in for a prover.
+    #[cfg(unix)]
+    fn stub(dir: &Path, name: &str, body: &str) -> PathBuf {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path = dir.join(name);
+        fs::write(&path, format!("#!/bin/sh\n{body}\n")).unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_backend_commands() {
+        let dir = Path::new("/work");
+
+        let binary = SnarkBackend::Binary("/usr/local/bin/prover".into());
+        let command = backend_command(&binary, dir).unwrap();
+        assert_eq!(command.get_program(), "/usr/local/bin/prover");
+        assert_eq!(
+            command.get_args().collect::<Vec<_>>(),
+            [
+                OsStr::new("/work/input.json"),
+                OsStr::new("/work/proof.json")
+            ]
+        );
+
+        let container = SnarkBackend::Container {
+            runtime: "podman".to_string(),
+            image: "risczero/risc0-groth16-prover:v2024-05-17.1".to_string(),
+        };
+        let command = backend_command(&container, dir).unwrap();
+        assert_eq!(command.get_program(), "podman");
+        assert_eq!(
+            command.get_args().collect::<Vec<_>>(),
+            [
+                "run",
+                "--rm",
+                "-v",
+                "/work:/mnt",
+                "risczero/risc0-groth16-prover:v2024-05-17.1"
+            ]
+        );
+
+        assert!(backend_command(&SnarkBackend::Docker, dir).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_binary_backend() {
+        let temp_dir = test_dir();
+        let dir = temp_dir.path();
+        let prover = stub(
+            dir,
+            "prover",
+            &format!("test -f \"$1\" && cat > \"$2\" <<'EOF'\n{PROOF_JSON}\nEOF"),
+        );
+
+        let seal = run_backend(&SnarkBackend::Binary(prover), dir).unwrap();
+        assert_eq!(seal, expected_seal());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_container_backend() {
+        let temp_dir = test_dir();
+        let dir = temp_dir.path();
+        // The runtime is called as `<runtime> run --rm -v <dir>:/mnt <image>`.
+        let runtime = stub(
+            dir,
+            "runtime",
+            &format!(
+                "test \"$1 $2 $5\" = \"run --rm image\" || exit 2\n\
+                 cat > \"${{4%:/mnt}}/proof.json\" <<'EOF'\n{PROOF_JSON}\nEOF"
+            ),
+        );
+        let backend = SnarkBackend::Container {
+            runtime: runtime.display().to_string(),
+            image: "image".to_string(),
+        };
+
+        let seal = run_backend(&backend, dir).unwrap();
+        assert_eq!(seal, expected_seal());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_backend_errors() {
+        let temp_dir = test_dir();
+        let dir = temp_dir.path();
+
+        // The prover fails.
+        let failing = stub(dir, "failing", "exit 3");
+        let err = run_backend(&SnarkBackend::Binary(failing), dir).unwrap_err();
+        assert!(err.to_string().contains("exited"), "{err:#}");
+
+        // The prover succeeds without writing a proof.
+        let silent = stub(dir, "silent", "exit 0");
+        let err = run_backend(&SnarkBackend::Binary(silent), dir).unwrap_err();
+        assert_eq!(err.to_string(), "Read Groth16 proof");
+
+        // The prover writes a malformed proof.
+        let garbled = stub(dir, "garbled", "echo '{}' > \"$2\"");
+        let err = run_backend(&SnarkBackend::Binary(garbled), dir).unwrap_err();
+        assert_eq!(err.to_string(), "Parse Groth16 proof");
+
+        // The prover is missing.
+        let err = run_backend(&SnarkBackend::Binary(dir.join("missing")), dir).unwrap_err();
+        assert!(err.to_string().starts_with("Run "), "{err:#}");
+    }
+}
@@ -0,0 +1,479 @@
+// Copyright 2024 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Indexer of the events of the `BonsaiPay` and `AADemo` contracts.
+//!
+//! The [Indexer] follows the `Deposited`, `Claimed`, `DebugSeal` and
+//! `Debugfunc` logs of the configured contracts into an [EventStore], an
+//! embedded database queried by claim ID and by account. Only blocks with
+//! enough confirmations are indexed. If the chain reorganizes deeper than
+//! that anyway, the blocks no longer on the chain are rolled back and
+//! indexed again.
+
+use std::{path::Path, time::Duration};
+
+use anyhow::{Context, Result};
+use ethers::prelude::*;
+use serde::{Deserialize, Serialize};
+use tokio::task::JoinHandle;
+
+use crate::{bonsai_pay::BonsaiPayEvents, AaDemoEvents, ChainClient};
+
+// Keys of the store, all in one tree so each batch is written atomically.
+const EVENT_PREFIX: u8 = b'e';
+const CLAIM_PREFIX: u8 = b'c';
+const ACCOUNT_PREFIX: u8 = b'a';
+const CHECKPOINT_PREFIX: u8 = b'h';
+
+/// Number of checkpoints kept to detect reorganizations.
+const MAX_CHECKPOINTS: usize = 256;
+
+/// An event emitted by a `BonsaiPay` or `AADemo` contract.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum EventKind {
+    Deposited {
+        claim_id: H256,
+        amount: U256,
+    },
+    Claimed {
+        recipient: Address,
+        claim_id: H256,
+        amount: U256,
+    },
+    DebugSeal {
+        claim_id: H256,
+        post_state_digest: H256,
+        seal: Bytes,
+    },
+    Debugfunc {
+        dest: Address,
+        value: U256,
+        func: Bytes,
+    },
+}
+
+/// An indexed event, with the log it was decoded from.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IndexedEvent {
+    /// Address of the contract that emitted the event.
+    pub contract: Address,
+    pub block_number: u64,
+    pub block_hash: H256,
+    pub transaction_hash: H256,
+    pub log_index: u64,
+    pub kind: EventKind,
+}
+
+impl IndexedEvent {
+    /// Decodes a mined log, returning `None` for logs of other events.
+    pub fn from_log(log: &Log) -> Option<Self> {
+        let raw = RawLog::from(log.clone());
+        let kind = match BonsaiPayEvents::decode_log(&raw) {
+            Ok(BonsaiPayEvents::DepositedFilter(event)) => EventKind::Deposited {
+                claim_id: event.claim_id.into(),
+                amount: event.amount,
+            },
+            Ok(BonsaiPayEvents::ClaimedFilter(event)) => EventKind::Claimed {
+                recipient: event.recipient,
+                claim_id: event.claim_id.into(),
+                amount: event.amount,
+            },
+            Ok(BonsaiPayEvents::DebugSealFilter(event)) => EventKind::DebugSeal {
+                claim_id: event.claim_id.into(),
+                post_state_digest: event.post_state_digest.into(),
+                seal: event.seal,
+            },
+            Err(_) => match AaDemoEvents::decode_log(&raw).ok()? {
+                AaDemoEvents::DebugfuncFilter(event) => EventKind::Debugfunc {
+                    dest: event.dest,
+                    value: event.value,
+                    func: event.func,
+                },
+                AaDemoEvents::DebugSealFilter(_) => return None,
+            },
+        };
+        Some(Self {
+            contract: log.address,
+            block_number: log.block_number?.as_u64(),
+            block_hash: log.block_hash?,
+            transaction_hash: log.transaction_hash?,
+            log_index: log.log_index?.as_u64(),
+            kind,
+        })
+    }
+
+    /// Returns the claim ID the event is about, if any.
+    pub fn claim_id(&self) -> Option<H256> {
+        match &self.kind {
+            EventKind::Deposited { claim_id, .. }
+            | EventKind::Claimed { claim_id, .. }
+            | EventKind::DebugSeal { claim_id, .. } => Some(*claim_id),
+            EventKind::Debugfunc { .. } => None,
+        }
+    }
+
+    /// Returns the accounts the event is about: the emitting contract, e.g.
+    /// an AA account, and the recipient of a claim or the destination of a
+    /// call.
+    pub fn accounts(&self) -> Vec<Address> {
+        let mut accounts = vec![self.contract];
+        match &self.kind {
+            EventKind::Claimed { recipient, .. } => accounts.push(*recipient),
+            EventKind::Debugfunc { dest, .. } => accounts.push(*dest),
+            EventKind::Deposited { .. } | EventKind::DebugSeal { .. } => {}
+        }
+        accounts.dedup();
+        accounts
+    }
+
+    fn key(&self) -> [u8; 16] {
+        let mut key = [0u8; 16];
+        key[..8].copy_from_slice(&self.block_number.to_be_bytes());
+        key[8..].copy_from_slice(&self.log_index.to_be_bytes());
+        key
+    }
+}
+
+fn prefixed(prefix: u8, parts: &[&[u8]]) -> Vec<u8> {
+    let mut key = vec![prefix];
+    for part in parts {
+        key.extend_from_slice(part);
+    }
+    key
+}
+
+/// Embedded database of the indexed events.
+///
+/// Events are stored in block order, with indexes by claim ID and by
+/// account, and a checkpoint with the hash of the last block of each indexed
+/// range.
+#[derive(Clone)]
+pub struct EventStore {
+    db: sled::Db,
+}
+
+impl EventStore {
+    /// Opens the database stored in `path`, creating it if needed.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let db = sled::open(path).with_context(|| format!("Open {}", path.display()))?;
+        Ok(Self { db })
+    }
+
+    /// Returns the last indexed block and its hash.
+    pub fn last_checkpoint(&self) -> Result<Option<(u64, H256)>> {
+        let Some(entry) = self.db.scan_prefix([CHECKPOINT_PREFIX]).next_back() else {
+            return Ok(None);
+        };
+        let (key, hash) = entry?;
+        let number = u64::from_be_bytes(key[1..].try_into().context("Invalid checkpoint")?);
+        Ok(Some((number, H256::from_slice(&hash))))
+    }
+
+    /// Stores the events of a range of blocks ending with block `number`,
+    /// of hash `hash`.
+    pub fn insert(&self, events: &[IndexedEvent], number: u64, hash: H256) -> Result<()> {
+        let mut batch = sled::Batch::default();
+        for event in events {
+            let key = event.key();
+            batch.insert(prefixed(EVENT_PREFIX, &[&key]), serde_json::to_vec(event)?);
+            if let Some(claim_id) = event.claim_id() {
+                batch.insert(
+                    prefixed(CLAIM_PREFIX, &[claim_id.as_bytes(), &key]),
+                    sled::IVec::default(),
+                );
+            }
+            for account in event.accounts() {
+                batch.insert(
+                    prefixed(ACCOUNT_PREFIX, &[account.as_bytes(), &key]),
+                    sled::IVec::default(),
+                );
+            }
+        }
+        batch.insert(
+            prefixed(CHECKPOINT_PREFIX, &[&number.to_be_bytes()]),
+            hash.as_bytes(),
+        );
+
+        let checkpoints: Vec<_> = self
+            .db
+            .scan_prefix([CHECKPOINT_PREFIX])
+            .keys()
+            .collect::<Result<_, _>>()?;
+        let stale = (checkpoints.len() + 1).saturating_sub(MAX_CHECKPOINTS);
+        for key in &checkpoints[..stale] {
+            batch.remove(key.clone());
+        }
+
+        self.db.apply_batch(batch)?;
+        self.db.flush()?;
+        Ok(())
+    }
+
+    /// Removes the last checkpoint, and the events of the blocks after the
+    /// previous one, or all events if there is none. Returns the new last
+    /// checkpoint.
+    pub fn rollback(&self) -> Result<Option<(u64, H256)>> {
+        let Some((last, _)) = self.last_checkpoint()? else {
+            return Ok(None);
+        };
+        let previous = self
+            .db
+            .range(
+                prefixed(CHECKPOINT_PREFIX, &[])
+                    ..prefixed(CHECKPOINT_PREFIX, &[&last.to_be_bytes()]),
+            )
+            .next_back()
+            .transpose()?
+            .map(|(key, hash)| -> Result<_> {
+                let number = u64::from_be_bytes(key[1..].try_into().context("Invalid checkpoint")?);
+                Ok((number, H256::from_slice(&hash)))
+            })
+            .transpose()?;
+
+        let mut batch = sled::Batch::default();
+        batch.remove(prefixed(CHECKPOINT_PREFIX, &[&last.to_be_bytes()]));
+        let first_removed = previous.map_or(0, |(number, _)| number + 1);
+        let start = prefixed(EVENT_PREFIX, &[&first_removed.to_be_bytes()]);
+        for entry in self.db.range(start..prefixed(EVENT_PREFIX + 1, &[])) {
+            let (key, value) = entry?;
+            let event: IndexedEvent = serde_json::from_slice(&value)?;
+            let event_key = event.key();
+            batch.remove(key);
+            if let Some(claim_id) = event.claim_id() {
+                batch.remove(prefixed(CLAIM_PREFIX, &[claim_id.as_bytes(), &event_key]));
+            }
+            for account in event.accounts() {
+                batch.remove(prefixed(ACCOUNT_PREFIX, &[account.as_bytes(), &event_key]));
+            }
+        }
+
+        self.db.apply_batch(batch)?;
+        self.db.flush()?;
+        Ok(previous)
+    }
+
+    /// Returns the events about `claim_id`, in block order.
+    pub fn events_by_claim_id(&self, claim_id: H256) -> Result<Vec<IndexedEvent>> {
+        self.indexed_events(prefixed(CLAIM_PREFIX, &[claim_id.as_bytes()]))
+    }
+
+    /// Returns the events about `account`, in block order.
+    pub fn events_by_account(&self, account: Address) -> Result<Vec<IndexedEvent>> {
+        self.indexed_events(prefixed(ACCOUNT_PREFIX, &[account.as_bytes()]))
+    }
+
+    /// Returns the amount deposited for `claim_id` since its last claim.
+    pub fn pending_balance(&self, claim_id: H256) -> Result<U256> {
+        let balance =
+            self.events_by_claim_id(claim_id)?
+                .iter()
+                .fold(U256::zero(), |balance, event| match event.kind {
+                    EventKind::Deposited { amount, .. } => balance + amount,
+                    EventKind::Claimed { .. } => U256::zero(),
+                    _ => balance,
+                });
+        Ok(balance)
+    }
+
+    fn indexed_events(&self, prefix: Vec<u8>) -> Result<Vec<IndexedEvent>> {
+        let mut events = Vec::new();
+        for key in self.db.scan_prefix(&prefix).keys() {
+            let key = key?;
+            let event_key = prefixed(EVENT_PREFIX, &[&key[prefix.len()..]]);
+            let value = self.db.get(event_key)?.context("Missing indexed event")?;
+            events.push(serde_json::from_slice(&value)?);
+        }
+        Ok(events)
+    }
+}
+
+/// What the [Indexer] follows.
+#[derive(Clone, Debug)]
+pub struct IndexerConfig {
+    /// Contracts whose logs are indexed.
+    pub contracts: Vec<Address>,
+    /// First block indexed.
+    pub start_block: u64,
+    /// Number of blocks, including its own, after which a block is indexed.
+    pub confirmations: u64,
+    /// Maximum number of blocks whose logs are read at once.
+    pub batch_size: u64,
+    /// Delay between two checks of the chain head.
+    pub poll_interval: Duration,
+}
+
+impl Default for IndexerConfig {
+    fn default() -> Self {
+        Self {
+            contracts: Vec::new(),
+            start_block: 0,
+            confirmations: 12,
+            batch_size: 1000,
+            poll_interval: Duration::from_secs(12),
+        }
+    }
+}
+
+/// Follows the logs of the configured contracts into an [EventStore].
+pub struct Indexer {
+    chain: ChainClient,
+    store: EventStore,
+    config: IndexerConfig,
+}
+
+impl Indexer {
+    pub fn new(chain: ChainClient, store: EventStore, config: IndexerConfig) -> Self {
+        Self {
+            chain,
+            store,
+            config,
+        }
+    }
+
+    /// Indexes the blocks confirmed since the last checkpoint, and returns
+    /// the last indexed block.
+    pub async fn sync(&self) -> Result<Option<u64>> {
+        let client = self.chain.client();
+        let head = client.get_block_number().await?.as_u64();
+        let confirmed = (head + 1).checked_sub(self.config.confirmations.max(1));
+
+        loop {
+            let checkpoint = self.store.last_checkpoint()?;
+            let from = checkpoint.map_or(self.config.start_block, |(number, _)| number + 1);
+            let Some(confirmed) = confirmed.filter(|confirmed| *confirmed >= from) else {
+                return Ok(checkpoint.map(|(number, _)| number));
+            };
+
+            // The next block must build on the checkpoint, unless the chain
+            // reorganized deeper than the confirmation depth.
+            if let Some((number, hash)) = checkpoint {
+                let next = client
+                    .get_block(from)
+                    .await?
+                    .with_context(|| format!("Missing block {from}"))?;
+                if next.parent_hash != hash {
+                    log::warn!("Block {} was reorganized, rolling back", number);
+                    self.store.rollback()?;
+                    continue;
+                }
+            }
+
+            let to = confirmed.min(from + self.config.batch_size.max(1) - 1);
+            let filter = Filter::new()
+                .address(self.config.contracts.clone())
+                .from_block(from)
+                .to_block(to);
+            let logs = client.get_logs(&filter).await.context("Read logs")?;
+            let hash = client
+                .get_block(to)
+                .await?
+                .and_then(|block| block.hash)
+                .with_context(|| format!("Missing block {to}"))?;
+
+            let events: Vec<_> = logs.iter().filter_map(IndexedEvent::from_log).collect();
+            log::debug!("Indexed {} events up to block {}", events.len(), to);
+            self.store.insert(&events, to, hash)?;
+        }
+    }
+
+    /// Spawns a task indexing the new blocks until it is aborted.
+    pub fn spawn(self) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            loop {
+                if let Err(err) = self.sync().await {
+                    log::warn!("Failed to index events: {:#}", err);
+                }
+                tokio::time::sleep(self.config.poll_interval).await;
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ethers::prelude::*;
+
+    use super::{EventKind, EventStore, IndexedEvent};
+
+    fn test_event(block_number: u64, kind: EventKind) -> IndexedEvent {
+        IndexedEvent {
+            contract: Address::repeat_byte(0xaa),
+            block_number,
+            block_hash: H256::repeat_byte(block_number as u8),
+            transaction_hash: H256::zero(),
+            log_index: 0,
+            kind,
+        }
+    }
+
+    #[test]
+    fn test_store_queries_and_rollback() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = EventStore::open(dir.path()).unwrap();
+        let claim_id = H256::repeat_byte(0x11);
+        let recipient = Address::repeat_byte(0x22);
+
+        let deposit = test_event(
+            1,
+            EventKind::Deposited {
+                claim_id,
+                amount: U256::from(5),
+            },
+        );
+        let claim = test_event(
+            2,
+            EventKind::Claimed {
+                recipient,
+                claim_id,
+                amount: U256::from(5),
+            },
+        );
+        let late_deposit = test_event(
+            3,
+            EventKind::Deposited {
+                claim_id,
+                amount: U256::from(7),
+            },
+        );
+
+        store
+            .insert(&[deposit.clone()], 1, H256::repeat_byte(1))
+            .unwrap();
+        store
+            .insert(
+                &[claim.clone(), late_deposit.clone()],
+                3,
+                H256::repeat_byte(3),
+            )
+            .unwrap();
+
+        assert_eq!(
+            store.last_checkpoint().unwrap(),
+            Some((3, H256::repeat_byte(3)))
+        );
+        assert_eq!(
+            store.events_by_claim_id(claim_id).unwrap(),
+            vec![deposit.clone(), claim.clone(), late_deposit]
+        );
+        assert_eq!(store.events_by_account(recipient).unwrap(), vec![claim]);
+        assert_eq!(store.pending_balance(claim_id).unwrap(), U256::from(7));
+
+        // Rolling back drops the events after the previous checkpoint.
+        assert_eq!(store.rollback().unwrap(), Some((1, H256::repeat_byte(1))));
+        assert_eq!(store.events_by_claim_id(claim_id).unwrap(), vec![deposit]);
+        assert!(store.events_by_account(recipient).unwrap().is_empty());
+        assert_eq!(store.pending_balance(claim_id).unwrap(), U256::from(5));
+    }
+}
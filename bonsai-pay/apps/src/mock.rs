@@ -0,0 +1,303 @@
+// Copyright 2024 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! In-memory Ethereum node answering the JSON-RPC requests of the tests.
+//!
+//! The node keeps a mempool of the raw transactions it receives and mines
+//! them in nonce order, so tests can drop, delay or reject transactions
+//! without a real node.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use ethers::{
+    prelude::*,
+    providers::JsonRpcError,
+    types::transaction::eip2718::TypedTransaction,
+    utils::{keccak256, rlp::Rlp},
+};
+use serde_json::{json, Value};
+
+use crate::{ChainClient, RelayerSigner, RpcPool, RpcTransport};
+
+/// Chain ID of the node.
+pub(crate) const MOCK_CHAIN_ID: u64 = 1;
+
+/// Private key of the relayer, the first account of the test mnemonic.
+pub(crate) const MOCK_KEY: &str =
+    "ac0974bec39a17e36ba4a6b4d238ff944bacb478cbed5efcae784d7bf4f2ff80";
+
+/// Gas price returned by `eth_gasPrice`.
+pub(crate) const MOCK_GAS_PRICE: u64 = 1_000_000_000;
+
+// JSON-RPC error code of a rejected transaction, as returned by geth.
+const TX_REJECTED: i64 = -32000;
+
+/// Errors returned by a [MockNode].
+#[derive(Debug, thiserror::Error)]
+pub enum MockError {
+    #[error("{0}")]
+    Rpc(JsonRpcError),
+    /// The node is unreachable.
+    #[error("node is down")]
+    Down,
+    #[error(transparent)]
+    Serde(#[from] serde_json::Error),
+}
+
+/// A transaction received by the node.
+#[derive(Clone, Debug)]
+pub(crate) struct MockTx {
+    pub hash: H256,
+    pub nonce: U256,
+    pub gas_price: U256,
+    pub to: Option<Address>,
+    pub value: U256,
+}
+
+#[derive(Debug, Default)]
+struct NodeState {
+    block: u64,
+    // Nonce of the next transaction mined.
+    nonce: U256,
+    mempool: Vec<MockTx>,
+    mined: HashMap<H256, u64>,
+    received: Vec<MockTx>,
+    requests: HashMap<String, usize>,
+    min_gas_price: U256,
+    balance: U256,
+    down: bool,
+    drop: usize,
+    reject: Option<JsonRpcError>,
+}
+
+impl NodeState {
+    fn pending_nonce(&self) -> U256 {
+        let mut next = self.nonce;
+        while self.mempool.iter().any(|tx| tx.nonce == next) {
+            next += U256::one();
+        }
+        next
+    }
+
+    fn mine(&mut self) {
+        loop {
+            let (next, min_gas_price) = (self.nonce, self.min_gas_price);
+            let Some(index) = self
+                .mempool
+                .iter()
+                .position(|tx| tx.nonce == next && tx.gas_price >= min_gas_price)
+            else {
+                return;
+            };
+            let tx = self.mempool.remove(index);
+            self.block += 1;
+            self.mined.insert(tx.hash, self.block);
+            self.nonce += U256::one();
+        }
+    }
+
+    fn send_raw_transaction(&mut self, params: &Value) -> Result<Value, MockError> {
+        if let Some(err) = self.reject.take() {
+            return Err(MockError::Rpc(err));
+        }
+        let raw = params[0].as_str().unwrap_or_default();
+        let raw = hex::decode(raw.trim_start_matches("0x"))
+            .map_err(|_| rpc_error(-32602, "invalid raw transaction"))?;
+        let (tx, _) = TypedTransaction::decode_signed(&Rlp::new(&raw))
+            .map_err(|_| rpc_error(-32602, "invalid raw transaction"))?;
+        let tx = MockTx {
+            hash: H256(keccak256(&raw)),
+            nonce: tx.nonce().copied().unwrap_or_default(),
+            gas_price: tx.gas_price().unwrap_or_default(),
+            to: tx.to().and_then(|to| to.as_address()).copied(),
+            value: tx.value().copied().unwrap_or_default(),
+        };
+
+        if tx.nonce < self.nonce {
+            return Err(rpc_error(TX_REJECTED, "nonce too low"));
+        }
+        if let Some(index) = self
+            .mempool
+            .iter()
+            .position(|pending| pending.nonce == tx.nonce)
+        {
+            if self.mempool[index].hash != tx.hash {
+                if tx.gas_price < self.mempool[index].gas_price * 110 / 100 {
+                    return Err(rpc_error(
+                        TX_REJECTED,
+                        "replacement transaction underpriced",
+                    ));
+                }
+                self.mempool.remove(index);
+            }
+        }
+
+        self.received.push(tx.clone());
+        if self.drop > 0 {
+            self.drop -= 1;
+        } else if !self.mempool.iter().any(|pending| pending.hash == tx.hash) {
+            self.mempool.push(tx.clone());
+        }
+        Ok(json!(tx.hash))
+    }
+
+    fn receipt(&mut self, params: &Value) -> Result<Value, MockError> {
+        self.mine();
+        let hash: H256 = serde_json::from_value(params[0].clone())?;
+        let Some(&block) = self.mined.get(&hash) else {
+            return Ok(Value::Null);
+        };
+        Ok(serde_json::to_value(TransactionReceipt {
+            transaction_hash: hash,
+            block_number: Some(block.into()),
+            status: Some(U64::one()),
+            ..Default::default()
+        })?)
+    }
+
+    fn transaction(&self, params: &Value) -> Result<Value, MockError> {
+        let hash: H256 = serde_json::from_value(params[0].clone())?;
+        let known = self.mined.contains_key(&hash)
+            || self.mempool.iter().any(|pending| pending.hash == hash);
+        if !known {
+            return Ok(Value::Null);
+        }
+        Ok(serde_json::to_value(Transaction {
+            hash,
+            ..Default::default()
+        })?)
+    }
+}
+
+fn rpc_error(code: i64, message: &str) -> MockError {
+    MockError::Rpc(JsonRpcError {
+        code,
+        message: message.to_string(),
+        data: None,
+    })
+}
+
+/// An in-memory node, shared by its clones.
+#[derive(Clone, Debug)]
+pub struct MockNode {
+    state: Arc<Mutex<NodeState>>,
+}
+
+impl MockNode {
+    /// Creates a node mining the pending transactions whenever a receipt is
+    /// requested.
+    pub fn new() -> Self {
+        let state = NodeState {
+            block: 1,
+            ..Default::default()
+        };
+        Self {
+            state: Arc::new(Mutex::new(state)),
+        }
+    }
+
+    /// A pool of this node only.
+    pub fn pool(&self) -> RpcPool {
+        RpcPool::from_endpoints(vec![("mock".to_string(), self.transport())])
+    }
+
+    pub fn transport(&self) -> RpcTransport {
+        RpcTransport::Mock(self.clone())
+    }
+
+    /// A client sending transactions from the [MOCK_KEY] relayer.
+    pub fn chain(&self) -> ChainClient {
+        let wallet: LocalWallet = MOCK_KEY.parse().unwrap();
+        ChainClient::new(MOCK_CHAIN_ID, self.pool(), RelayerSigner::Local(wallet))
+    }
+
+    /// Sets the nonce of the next mined transaction, as if other
+    /// transactions of the relayer were mined.
+    pub fn set_nonce(&self, nonce: u64) {
+        let mut state = self.state.lock().unwrap();
+        state.nonce = nonce.into();
+        let nonce = state.nonce;
+        state.mempool.retain(|tx| tx.nonce >= nonce);
+    }
+
+    /// Only mines transactions paying at least `min_gas_price`.
+    pub fn set_min_gas_price(&self, min_gas_price: u64) {
+        self.state.lock().unwrap().min_gas_price = min_gas_price.into();
+    }
+
+    /// Sets the balance returned for every account.
+    pub fn set_balance(&self, balance: u64) {
+        self.state.lock().unwrap().balance = balance.into();
+    }
+
+    /// Fails every request as unreachable while `down` is set, counting the
+    /// requests still.
+    pub fn set_down(&self, down: bool) {
+        self.state.lock().unwrap().down = down;
+    }
+
+    /// Accepts the next `count` transactions, then forgets them.
+    pub fn drop_next(&self, count: usize) {
+        self.state.lock().unwrap().drop = count;
+    }
+
+    /// Rejects the next transaction with the JSON-RPC error `message`.
+    pub fn reject_next(&self, message: &str) {
+        let MockError::Rpc(err) = rpc_error(TX_REJECTED, message) else {
+            unreachable!()
+        };
+        self.state.lock().unwrap().reject = Some(err);
+    }
+
+    /// The transactions received, including the dropped ones and the
+    /// resubmissions, in order.
+    pub fn received(&self) -> Vec<MockTx> {
+        self.state.lock().unwrap().received.clone()
+    }
+
+    /// Number of requests of `method` received.
+    pub fn requests(&self, method: &str) -> usize {
+        let state = self.state.lock().unwrap();
+        state.requests.get(method).copied().unwrap_or_default()
+    }
+
+    /// Answers a JSON-RPC request.
+    pub fn handle(&self, method: &str, params: Value) -> Result<Value, MockError> {
+        let mut state = self.state.lock().unwrap();
+        *state.requests.entry(method.to_string()).or_default() += 1;
+        if state.down {
+            return Err(MockError::Down);
+        }
+
+        match method {
+            "eth_chainId" => Ok(json!(U64::from(MOCK_CHAIN_ID))),
+            "eth_blockNumber" => Ok(json!(U64::from(state.block))),
+            "eth_getTransactionCount" => match params[1].as_str() {
+                Some("pending") => Ok(json!(state.pending_nonce())),
+                _ => Ok(json!(state.nonce)),
+            },
+            "eth_getBalance" => Ok(json!(state.balance)),
+            "eth_gasPrice" => Ok(json!(U256::from(MOCK_GAS_PRICE))),
+            "eth_estimateGas" => Ok(json!(U256::from(21_000))),
+            "eth_call" => Ok(json!("0x")),
+            "eth_sendRawTransaction" => state.send_raw_transaction(&params),
+            "eth_getTransactionReceipt" => state.receipt(&params),
+            "eth_getTransactionByHash" => state.transaction(&params),
+            _ => Err(rpc_error(-32601, "the method does not exist")),
+        }
+    }
+}
@@ -0,0 +1,579 @@
+// Copyright 2024 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Failover between several RPC endpoints.
+//!
+//! Requests go to the first healthy endpoint, in the configured order. An
+//! endpoint failing to answer, as opposed to answering with a JSON-RPC error
+//! such as a revert, is marked down for a cooldown period and the request is
+//! retried on the next endpoint. Reads of account state, such as balances
+//! and nonces, can additionally require a quorum of agreeing endpoints. The
+//! pending nonce depends on the mempool of each node, so it is read from the
+//! first healthy endpoint, which also receives the sent transactions.
+
+use std::{
+    collections::HashMap,
+    fmt::Debug,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use anyhow::{ensure, Result};
+use async_trait::async_trait;
+use ethers::{
+    providers::{JsonRpcClient, JsonRpcError, ProviderError, PubsubClient, RpcError},
+    types::U256,
+    utils::keccak256,
+};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+use tokio::task::JoinSet;
+
+use crate::{RpcTransport, TransportError};
+
+/// Time an endpoint is skipped after it failed.
+const DEFAULT_COOLDOWN: Duration = Duration::from_secs(30);
+
+/// Methods reading account state, answered by a quorum when one is set.
+const QUORUM_METHODS: &[&str] = &[
+    "eth_chainId",
+    "eth_getBalance",
+    "eth_getCode",
+    "eth_getStorageAt",
+    "eth_getTransactionCount",
+];
+
+// JSON-RPC error code of a request over the rate limit of the node.
+const LIMIT_EXCEEDED: i64 = -32005;
+
+/// Errors returned by an [RpcPool].
+#[derive(Debug, thiserror::Error)]
+pub enum PoolError {
+    /// The error of the last endpoint tried.
+    #[error(transparent)]
+    Transport(Box<TransportError>),
+    #[error("no quorum of {quorum} endpoints for {method}: {answers:?}")]
+    NoQuorum {
+        method: String,
+        quorum: usize,
+        answers: Vec<Value>,
+    },
+    #[error("no endpoint supports subscriptions")]
+    SubscriptionsUnsupported,
+    #[error(transparent)]
+    Serde(#[from] serde_json::Error),
+}
+
+impl RpcError for PoolError {
+    fn as_error_response(&self) -> Option<&JsonRpcError> {
+        match self {
+            Self::Transport(err) => err.as_error_response(),
+            _ => None,
+        }
+    }
+
+    fn as_serde_error(&self) -> Option<&serde_json::Error> {
+        match self {
+            Self::Transport(err) => err.as_serde_error(),
+            Self::Serde(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<TransportError> for PoolError {
+    fn from(err: TransportError) -> Self {
+        Self::Transport(Box::new(err))
+    }
+}
+
+impl From<PoolError> for ProviderError {
+    fn from(err: PoolError) -> Self {
+        ProviderError::JsonRpcClientError(Box::new(err))
+    }
+}
+
+#[derive(Debug, Default)]
+struct Health {
+    failures: u32,
+    down_until: Option<Instant>,
+}
+
+#[derive(Debug)]
+struct Endpoint {
+    url: String,
+    transport: RpcTransport,
+    health: Mutex<Health>,
+}
+
+impl Endpoint {
+    fn is_up(&self, now: Instant) -> bool {
+        let health = self.health.lock().unwrap();
+        health.down_until.is_none_or(|until| until <= now)
+    }
+
+    fn succeeded(&self) {
+        *self.health.lock().unwrap() = Health::default();
+    }
+
+    fn failed(&self, cooldown: Duration, err: &TransportError) {
+        let mut health = self.health.lock().unwrap();
+        health.failures += 1;
+        health.down_until = Some(Instant::now() + cooldown);
+        log::warn!(
+            "RPC endpoint {} failed {} times in a row, skipping it for {:?}: {}",
+            self.url,
+            health.failures,
+            cooldown,
+            err
+        );
+    }
+}
+
+/// A JSON-RPC client spreading requests over several endpoints, with health
+/// tracking, failover and optional quorum reads.
+///
+/// Clones share the connections and the health of the endpoints.
+#[derive(Clone, Debug)]
+pub struct RpcPool {
+    endpoints: Arc<[Endpoint]>,
+    quorum: Option<usize>,
+    cooldown: Duration,
+}
+
+impl RpcPool {
+    /// Connects to the endpoints at `urls`, in order of preference.
+    pub async fn connect(urls: &[impl AsRef<str>]) -> Result<Self> {
+        ensure!(!urls.is_empty(), "No RPC URL");
+        let mut endpoints = Vec::with_capacity(urls.len());
+        for url in urls {
+            let url = url.as_ref();
+            endpoints.push(Endpoint {
+                url: url.to_string(),
+                transport: RpcTransport::connect(url).await?,
+                health: Default::default(),
+            });
+        }
+        Ok(Self {
+            endpoints: endpoints.into(),
+            quorum: None,
+            cooldown: DEFAULT_COOLDOWN,
+        })
+    }
+
+    /// Creates a pool of already connected endpoints, named by their URL.
+    #[cfg(test)]
+    pub(crate) fn from_endpoints(endpoints: Vec<(String, RpcTransport)>) -> Self {
+        let endpoints: Vec<_> = endpoints
+            .into_iter()
+            .map(|(url, transport)| Endpoint {
+                url,
+                transport,
+                health: Default::default(),
+            })
+            .collect();
+        Self {
+            endpoints: endpoints.into(),
+            quorum: None,
+            cooldown: DEFAULT_COOLDOWN,
+        }
+    }
+
+    /// Requires `quorum` endpoints to agree on reads of account state.
+    /// Fails unless the pool has at least `quorum` endpoints.
+    pub fn with_quorum(mut self, quorum: usize) -> Result<Self> {
+        ensure!(quorum > 0, "RPC quorum must be at least 1");
+        ensure!(
+            quorum <= self.endpoints.len(),
+            "RPC quorum of {quorum} exceeds the {} endpoints",
+            self.endpoints.len()
+        );
+        self.quorum = Some(quorum);
+        Ok(self)
+    }
+
+    /// Sets the time an endpoint is skipped after it failed.
+    pub fn with_cooldown(mut self, cooldown: Duration) -> Self {
+        self.cooldown = cooldown;
+        self
+    }
+
+    /// Whether the node can push notifications over one of the endpoints.
+    pub fn supports_subscriptions(&self) -> bool {
+        self.pubsub_endpoint().is_some()
+    }
+
+    /// Returns the endpoints in the order they are tried: the healthy ones in
+    /// order of preference, then the ones marked down as a last resort.
+    fn ordered(&self) -> impl Iterator<Item = &Endpoint> {
+        let now = Instant::now();
+        let (up, down): (Vec<_>, Vec<_>) = self.endpoints.iter().partition(|e| e.is_up(now));
+        up.into_iter().chain(down)
+    }
+
+    /// Subscriptions are bound to the endpoint that created them, so they
+    /// all go to the first endpoint supporting them, without failover.
+    fn pubsub_endpoint(&self) -> Option<&Endpoint> {
+        self.endpoints
+            .iter()
+            .find(|e| e.transport.supports_subscriptions())
+    }
+
+    async fn request_failover(&self, method: &str, params: Value) -> Result<Value, PoolError> {
+        if matches!(method, "eth_subscribe" | "eth_unsubscribe") {
+            let endpoint = self
+                .pubsub_endpoint()
+                .ok_or(PoolError::SubscriptionsUnsupported)?;
+            return Ok(endpoint.transport.request(method, params).await?);
+        }
+
+        let mut last_err = None;
+        for endpoint in self.ordered() {
+            match endpoint.transport.request(method, &params).await {
+                Ok(value) => {
+                    endpoint.succeeded();
+                    return Ok(value);
+                }
+                Err(err) if is_endpoint_failure(&err) => {
+                    endpoint.failed(self.cooldown, &err);
+                    last_err = Some(err);
+                }
+                // A transaction sent before the previous endpoint failed may
+                // have reached the network already.
+                Err(err) if last_err.is_some() && is_already_known(method, &err) => {
+                    endpoint.succeeded();
+                    return Ok(raw_transaction_hash(&params).ok_or(err)?);
+                }
+                Err(err) => {
+                    endpoint.succeeded();
+                    return Err(err.into());
+                }
+            }
+        }
+        Err(last_err.expect("no endpoint").into())
+    }
+
+    async fn request_quorum(
+        &self,
+        method: &str,
+        params: Value,
+        quorum: usize,
+    ) -> Result<Value, PoolError> {
+        // Endpoints marked down still count when the others cannot reach the
+        // quorum.
+        let now = Instant::now();
+        let mut candidates: Vec<_> = (0..self.endpoints.len())
+            .filter(|&index| self.endpoints[index].is_up(now))
+            .collect();
+        if candidates.len() < quorum {
+            candidates = (0..self.endpoints.len()).collect();
+        }
+
+        let mut requests = JoinSet::new();
+        for index in candidates {
+            let (transport, method, params) = (
+                self.endpoints[index].transport.clone(),
+                method.to_string(),
+                params.clone(),
+            );
+            requests.spawn(async move {
+                let res = transport.request::<_, Value>(&method, params).await;
+                (index, res)
+            });
+        }
+
+        let mut answers: Vec<Value> = Vec::new();
+        let mut votes: HashMap<String, usize> = HashMap::new();
+        let mut last_err = None;
+        while let Some(joined) = requests.join_next().await {
+            let Ok((index, res)) = joined else {
+                continue;
+            };
+            let endpoint = &self.endpoints[index];
+            match res {
+                Ok(value) => {
+                    endpoint.succeeded();
+                    let count = votes.entry(value.to_string()).or_default();
+                    *count += 1;
+                    if *count >= quorum {
+                        return Ok(value);
+                    }
+                    answers.push(value);
+                }
+                Err(err) => {
+                    if is_endpoint_failure(&err) {
+                        endpoint.failed(self.cooldown, &err);
+                    }
+                    last_err = Some(err);
+                }
+            }
+        }
+
+        match last_err {
+            // A JSON-RPC error, e.g. for invalid parameters, is likely the
+            // same on every endpoint.
+            Some(err) if answers.is_empty() => Err(err.into()),
+            _ => Err(PoolError::NoQuorum {
+                method: method.to_string(),
+                quorum,
+                answers,
+            }),
+        }
+    }
+}
+
+#[async_trait]
+impl JsonRpcClient for RpcPool {
+    type Error = PoolError;
+
+    async fn request<T, R>(&self, method: &str, params: T) -> Result<R, Self::Error>
+    where
+        T: Debug + Serialize + Send + Sync,
+        R: DeserializeOwned + Send,
+    {
+        let params = serde_json::to_value(params)?;
+        let value = match self.quorum {
+            Some(quorum) if quorum > 1 && needs_quorum(method, &params) => {
+                self.request_quorum(method, params, quorum).await?
+            }
+            _ => self.request_failover(method, params).await?,
+        };
+        Ok(serde_json::from_value(value)?)
+    }
+}
+
+impl PubsubClient for RpcPool {
+    type NotificationStream = <RpcTransport as PubsubClient>::NotificationStream;
+
+    fn subscribe<T: Into<U256>>(&self, id: T) -> Result<Self::NotificationStream, Self::Error> {
+        let endpoint = self
+            .pubsub_endpoint()
+            .ok_or(PoolError::SubscriptionsUnsupported)?;
+        Ok(endpoint.transport.subscribe(id)?)
+    }
+
+    fn unsubscribe<T: Into<U256>>(&self, id: T) -> Result<(), Self::Error> {
+        let endpoint = self
+            .pubsub_endpoint()
+            .ok_or(PoolError::SubscriptionsUnsupported)?;
+        Ok(endpoint.transport.unsubscribe(id)?)
+    }
+}
+
+/// Whether the request reads account state agreed on by every node. The
+/// pending nonce also counts the mempool of the node, which differs between
+/// nodes.
+fn needs_quorum(method: &str, params: &Value) -> bool {
+    QUORUM_METHODS.contains(&method)
+        && !(method == "eth_getTransactionCount"
+            && params.get(1).and_then(Value::as_str) == Some("pending"))
+}
+
+/// Whether the endpoint failed to answer, rather than answered with an
+/// error.
+fn is_endpoint_failure(err: &TransportError) -> bool {
+    match err.as_error_response() {
+        Some(response) => response.code == LIMIT_EXCEEDED,
+        None => !matches!(err, TransportError::SubscriptionsUnsupported),
+    }
+}
+
+fn is_already_known(method: &str, err: &TransportError) -> bool {
+    method == "eth_sendRawTransaction"
+        && err.as_error_response().is_some_and(|response| {
+            let message = response.message.to_lowercase();
+            message.contains("already known") || message.contains("known transaction")
+        })
+}
+
+/// Returns the hash of the transaction sent by `eth_sendRawTransaction`.
+fn raw_transaction_hash(params: &Value) -> Option<Value> {
+    let raw = params.get(0)?.as_str()?;
+    let raw = hex::decode(raw.trim_start_matches("0x")).ok()?;
+    Some(Value::String(format!("0x{}", hex::encode(keccak256(raw)))))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use ethers::{
+        providers::{JsonRpcClient, Middleware, Provider},
+        types::{Address, BlockNumber, U256},
+        utils::keccak256,
+    };
+    use serde_json::json;
+
+    use super::{needs_quorum, raw_transaction_hash, PoolError, RpcPool};
+    use crate::mock::MockNode;
+
+    fn pool(nodes: &[&MockNode]) -> RpcPool {
+        RpcPool::from_endpoints(
+            nodes
+                .iter()
+                .enumerate()
+                .map(|(index, node)| (format!("mock{index}"), node.transport()))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn test_raw_transaction_hash() {
+        let params = json!(["0x02f8"]);
+        let expected = format!("0x{}", hex::encode(keccak256([0x02, 0xf8])));
+        assert_eq!(raw_transaction_hash(&params), Some(json!(expected)));
+        assert_eq!(raw_transaction_hash(&json!([])), None);
+    }
+
+    #[test]
+    fn test_quorum_bounds() {
+        let (a, b) = (MockNode::new(), MockNode::new());
+        assert!(pool(&[&a, &b]).with_quorum(2).is_ok());
+        assert!(pool(&[&a, &b]).with_quorum(1).is_ok());
+        assert!(pool(&[&a, &b]).with_quorum(3).is_err());
+        assert!(pool(&[&a, &b]).with_quorum(0).is_err());
+    }
+
+    #[test]
+    fn test_pending_nonce_not_quorum() {
+        let account = json!(Address::zero());
+        assert!(needs_quorum("eth_getBalance", &json!([account, "latest"])));
+        assert!(needs_quorum(
+            "eth_getTransactionCount",
+            &json!([account, "latest"])
+        ));
+        assert!(!needs_quorum(
+            "eth_getTransactionCount",
+            &json!([account, "pending"])
+        ));
+        assert!(!needs_quorum("eth_blockNumber", &json!([])));
+    }
+
+    #[tokio::test]
+    async fn test_failover() {
+        let (primary, backup) = (MockNode::new(), MockNode::new());
+        let pool = pool(&[&primary, &backup]);
+
+        let _: U256 = pool.request("eth_blockNumber", ()).await.unwrap();
+        assert_eq!(primary.requests("eth_blockNumber"), 1);
+        assert_eq!(backup.requests("eth_blockNumber"), 0);
+
+        primary.set_down(true);
+        let _: U256 = pool.request("eth_blockNumber", ()).await.unwrap();
+        assert_eq!(primary.requests("eth_blockNumber"), 2);
+        assert_eq!(backup.requests("eth_blockNumber"), 1);
+
+        // JSON-RPC errors are answers, returned without failover.
+        let err = pool
+            .request::<_, U256>("eth_unknownMethod", ())
+            .await
+            .unwrap_err();
+        assert!(matches!(err, PoolError::Transport(_)), "{err}");
+        assert_eq!(backup.requests("eth_unknownMethod"), 1);
+
+        // Every endpoint down returns the error of the last one.
+        backup.set_down(true);
+        assert!(pool
+            .request::<_, U256>("eth_blockNumber", ())
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_failed_endpoint_skipped_until_cooldown() {
+        let (primary, backup) = (MockNode::new(), MockNode::new());
+        let pool = pool(&[&primary, &backup]).with_cooldown(Duration::from_millis(200));
+
+        primary.set_down(true);
+        let _: U256 = pool.request("eth_blockNumber", ()).await.unwrap();
+        primary.set_down(false);
+
+        // The primary is skipped while it cools down, even once back up.
+        let _: U256 = pool.request("eth_blockNumber", ()).await.unwrap();
+        assert_eq!(primary.requests("eth_blockNumber"), 1);
+        assert_eq!(backup.requests("eth_blockNumber"), 2);
+
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        let _: U256 = pool.request("eth_blockNumber", ()).await.unwrap();
+        assert_eq!(primary.requests("eth_blockNumber"), 2);
+        assert_eq!(backup.requests("eth_blockNumber"), 2);
+
+        // Endpoints marked down are still tried when no other is up.
+        primary.set_down(true);
+        backup.set_down(true);
+        assert!(pool
+            .request::<_, U256>("eth_blockNumber", ())
+            .await
+            .is_err());
+        primary.set_down(false);
+        let _: U256 = pool.request("eth_blockNumber", ()).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_quorum() {
+        let (a, b, c) = (MockNode::new(), MockNode::new(), MockNode::new());
+        let provider = Provider::new(pool(&[&a, &b, &c]).with_quorum(2).unwrap());
+        let account = Address::repeat_byte(0x11);
+        for node in [&a, &b, &c] {
+            node.set_balance(100);
+        }
+
+        // One faulty endpoint is outvoted.
+        c.set_balance(1_000);
+        assert_eq!(
+            provider.get_balance(account, None).await.unwrap(),
+            U256::from(100)
+        );
+
+        // So is one endpoint down.
+        c.set_down(true);
+        assert_eq!(
+            provider.get_balance(account, None).await.unwrap(),
+            U256::from(100)
+        );
+
+        // Two disagreeing endpoints are not a quorum.
+        b.set_balance(200);
+        let err = provider.get_balance(account, None).await.unwrap_err();
+        assert!(err.to_string().contains("no quorum"), "{err}");
+
+        // Reads outside account state go to a single endpoint.
+        let _ = provider.get_block_number().await.unwrap();
+        let requests = [&a, &b, &c].map(|node| node.requests("eth_blockNumber"));
+        assert_eq!(requests.iter().sum::<usize>(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_pending_nonce_from_primary() {
+        let (primary, backup) = (MockNode::new(), MockNode::new());
+        let provider = Provider::new(pool(&[&primary, &backup]).with_quorum(2).unwrap());
+        let account = Address::repeat_byte(0x11);
+        primary.set_nonce(5);
+        backup.set_nonce(3);
+
+        let nonce = provider
+            .get_transaction_count(account, Some(BlockNumber::Pending.into()))
+            .await
+            .unwrap();
+        assert_eq!(nonce, U256::from(5));
+        assert_eq!(backup.requests("eth_getTransactionCount"), 0);
+
+        // The mined nonce still requires a quorum.
+        assert!(provider
+            .get_transaction_count(account, Some(BlockNumber::Latest.into()))
+            .await
+            .is_err());
+        assert_eq!(backup.requests("eth_getTransactionCount"), 1);
+    }
+}
@@ -0,0 +1,852 @@
+// Copyright 2024 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::PathBuf,
+    rc::Rc,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use alloy_primitives::FixedBytes;
+use anyhow::{anyhow, Context};
+use async_trait::async_trait;
+use bonsai_pay_types::GuestOutput;
+use bonsai_sdk::alpha::{self as bonsai_sdk, responses::Groth16Seal, SdkErr, SessionId, SnarkId};
+use oidc_validator::OidcErr;
+use risc0_ethereum_contracts::groth16::Seal;
+use risc0_zkvm::{
+    compute_image_id, default_executor,
+    sha::{
+        rust_crypto::{Digest as _, Sha256},
+        Digestible,
+    },
+    ExecutorEnv, ExitCode, Prover as ZkvmProver, ProverOpts, Receipt, ReceiptClaim,
+};
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    bundle::{decode_journal, unix_now, BonsaiSession, ProofBundle},
+    groth16::{stark_to_snark, SnarkBackend},
+    verify::VerifyError,
+};
+
+/// Errors returned by a [Prover].
+#[derive(Debug, thiserror::Error)]
+pub enum ProverError {
+    /// The guest rejected the input and committed a failure journal.
+    #[error("guest rejected the input: {0}")]
+    Guest(OidcErr),
+    /// The proving session ended without a receipt.
+    #[error("session {uuid} exited with {status}: {message}")]
+    SessionFailed {
+        uuid: String,
+        status: String,
+        message: String,
+    },
+    /// The snark session ended without a snark.
+    #[error("snark session {uuid} exited with {status}: {message}")]
+    SnarkFailed {
+        uuid: String,
+        status: String,
+        message: String,
+    },
+    /// The proof did not complete before the deadline.
+    #[error("proving did not complete within {0:?}")]
+    Timeout(Duration),
+    /// The proof was cancelled by the caller.
+    #[error("proving was cancelled")]
+    Cancelled,
+    /// The proof would be rejected by the on-chain verifier.
+    #[error("invalid proof: {0}")]
+    InvalidProof(#[from] VerifyError),
+    #[error(transparent)]
+    Other(#[from] anyhow::Error),
+}
+
+/// A prover generating proofs of a guest that can be verified on-chain.
+#[async_trait]
+pub trait Prover: Send + Sync {
+    /// Generates a snark proof of the given elf and input.
+    ///
+    /// Returns [ProverError::Guest] if the guest rejected the input, and
+    /// [ProverError::Cancelled] as soon as `cancel` is cancelled.
+    async fn prove(
+        &self,
+        elf: &[u8],
+        input: &[u8],
+        cancel: &CancellationToken,
+    ) -> Result<ProofBundle, ProverError>;
+}
+
+// Journal, post state digest and seal of a proof.
+type Proof = (Vec<u8>, FixedBytes<32>, Vec<u8>);
+
+/// Returns [ProverError::Guest] if the journal of the guest of `image_id` is
+/// a failure journal.
+fn check_guest_output(image_id: FixedBytes<32>, journal: &[u8]) -> Result<(), ProverError> {
+    if let GuestOutput::Failure(failure) = decode_journal(image_id, journal)? {
+        let err = OidcErr::from_code(failure.error_code)
+            .ok_or_else(|| anyhow!("Unknown guest error code: {}", failure.error_code))?;
+        return Err(ProverError::Guest(err));
+    }
+    Ok(())
+}
+
+/// Polling policy of the [BonsaiProver].
+#[derive(Clone, Debug)]
+pub struct PollConfig {
+    /// Delay between the first status checks of a session.
+    pub interval: Duration,
+    /// Upper bound of the delay between two status checks.
+    pub max_interval: Duration,
+    /// Factor applied to the delay after each status check.
+    pub backoff: u32,
+    /// Deadline of a whole proof, from the upload of the input to the snark.
+    pub timeout: Option<Duration>,
+}
+
+impl Default for PollConfig {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(5),
+            max_interval: Duration::from_secs(30),
+            backoff: 2,
+            timeout: Some(Duration::from_secs(30 * 60)),
+        }
+    }
+}
+
+impl PollConfig {
+    fn next_interval(&self, interval: Duration) -> Duration {
+        (interval * self.backoff).min(self.max_interval)
+    }
+}
+
+/// A Bonsai client whose blocking calls run on the blocking thread pool.
+///
+/// The blocking client must neither be used nor dropped on an async worker
+/// thread, so it is handed over to the blocking pool when dropped.
+struct BonsaiClient(Option<Arc<bonsai_sdk::Client>>);
+
+impl BonsaiClient {
+    async fn from_env() -> anyhow::Result<Self> {
+        let client =
+            tokio::task::spawn_blocking(|| bonsai_sdk::Client::from_env(risc0_zkvm::VERSION))
+                .await??;
+        Ok(Self(Some(Arc::new(client))))
+    }
+
+    async fn run<T, F>(&self, f: F) -> anyhow::Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&bonsai_sdk::Client) -> Result<T, SdkErr> + Send + 'static,
+    {
+        let client = self.0.clone().context("Bonsai client dropped")?;
+        Ok(tokio::task::spawn_blocking(move || f(&client)).await??)
+    }
+}
+
+impl Drop for BonsaiClient {
+    fn drop(&mut self) {
+        if let (Some(client), Ok(handle)) = (self.0.take(), tokio::runtime::Handle::try_current()) {
+            handle.spawn_blocking(move || drop(client));
+        }
+    }
+}
+
+// Number of uploaded input IDs remembered by the [BonsaiProver].
+const MAX_INPUT_IDS: usize = 1024;
+
+/// Returns the identifier of the Bonsai endpoint and account of the
+/// environment, so images registered with one are not assumed to be
+/// registered with another.
+fn bonsai_scope() -> String {
+    let url = std::env::var("BONSAI_API_URL").unwrap_or_default();
+    let key = std::env::var("BONSAI_API_KEY").unwrap_or_default();
+    let digest = Sha256::new()
+        .chain_update(url)
+        .chain_update([0])
+        .chain_update(key)
+        .finalize();
+    hex::encode(&digest[..16])
+}
+
+/// Image IDs known to be registered on Bonsai, per [bonsai_scope].
+///
+/// Image IDs are kept in memory, and optionally as empty marker files in a
+/// directory per scope, so they survive restarts.
+#[derive(Default)]
+struct ImageRegistry {
+    known: Mutex<HashSet<String>>,
+    dir: Option<PathBuf>,
+}
+
+impl ImageRegistry {
+    fn marker(&self, scope: &str, image_id: &str) -> Option<PathBuf> {
+        self.dir.as_ref().map(|dir| dir.join(scope).join(image_id))
+    }
+
+    fn contains(&self, scope: &str, image_id: &str) -> bool {
+        let key = format!("{scope}/{image_id}");
+        if self.known.lock().unwrap().contains(&key) {
+            return true;
+        }
+        let on_disk = self
+            .marker(scope, image_id)
+            .is_some_and(|marker| marker.is_file());
+        if on_disk {
+            self.known.lock().unwrap().insert(key);
+        }
+        on_disk
+    }
+
+    fn insert(&self, scope: &str, image_id: &str) {
+        self.known
+            .lock()
+            .unwrap()
+            .insert(format!("{scope}/{image_id}"));
+        if let Some(marker) = self.marker(scope, image_id) {
+            // The registry is only an optimization, so failing to persist it
+            // costs one upload on the next start.
+            let persisted = marker
+                .parent()
+                .map_or(Ok(()), fs::create_dir_all)
+                .and_then(|_| fs::write(&marker, []));
+            if let Err(err) = persisted {
+                log::warn!("Failed to persist image ID {}: {}", image_id, err);
+            }
+        }
+    }
+
+    /// Forgets an image, e.g. purged from Bonsai.
+    fn remove(&self, scope: &str, image_id: &str) {
+        self.known
+            .lock()
+            .unwrap()
+            .remove(&format!("{scope}/{image_id}"));
+        if let Some(marker) = self.marker(scope, image_id) {
+            if let Err(err) = fs::remove_file(&marker) {
+                log::warn!("Failed to remove image ID {}: {}", image_id, err);
+            }
+        }
+    }
+}
+
+/// Input IDs uploaded to Bonsai, by hash of the input, evicting the least
+/// recently used ones beyond `capacity`.
+struct InputIds {
+    ids: HashMap<[u8; 32], (String, u64)>,
+    clock: u64,
+    capacity: usize,
+}
+
+impl Default for InputIds {
+    fn default() -> Self {
+        Self {
+            ids: HashMap::new(),
+            clock: 0,
+            capacity: MAX_INPUT_IDS,
+        }
+    }
+}
+
+impl InputIds {
+    fn get(&mut self, key: &[u8; 32]) -> Option<String> {
+        self.clock += 1;
+        let clock = self.clock;
+        self.ids.get_mut(key).map(|(input_id, used)| {
+            *used = clock;
+            input_id.clone()
+        })
+    }
+
+    fn insert(&mut self, key: [u8; 32], input_id: String) {
+        if self.ids.len() >= self.capacity && !self.ids.contains_key(&key) {
+            let oldest = self
+                .ids
+                .iter()
+                .min_by_key(|(_, (_, used))| *used)
+                .map(|(key, _)| *key);
+            if let Some(oldest) = oldest {
+                self.ids.remove(&oldest);
+            }
+        }
+        self.clock += 1;
+        self.ids.insert(key, (input_id, self.clock));
+    }
+
+    fn remove(&mut self, key: &[u8; 32]) {
+        self.ids.remove(key);
+    }
+}
+
+/// An implementation of a Prover that runs on Bonsai.
+///
+/// Images are uploaded once per image ID and Bonsai account, and inputs once
+/// per content, so retries of a request reuse the uploaded input. An image or
+/// input that Bonsai no longer knows is uploaded again.
+#[derive(Default)]
+pub struct BonsaiProver {
+    poll: PollConfig,
+    images: ImageRegistry,
+    inputs: Mutex<InputIds>,
+}
+
+impl BonsaiProver {
+    /// Sets the polling policy used while waiting for Bonsai.
+    pub fn with_poll_config(mut self, poll: PollConfig) -> Self {
+        self.poll = poll;
+        self
+    }
+
+    /// Persists the registered image IDs in `dir`, so images are not
+    /// uploaded again after a restart.
+    pub fn with_image_cache_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.images.dir = Some(dir.into());
+        self
+    }
+
+    /// Uploads the image of `elf` unless it is registered, and returns its
+    /// image ID and whether the upload was skipped.
+    async fn upload_image(
+        &self,
+        client: &BonsaiClient,
+        scope: &str,
+        elf: &[u8],
+    ) -> Result<(String, bool), ProverError> {
+        // Compute the image_id, then upload the ELF with the image_id as its key.
+        let image_id_hex = compute_image_id(elf)?.to_string();
+        if self.images.contains(scope, &image_id_hex) {
+            log::info!("Image ID: 0x{} (already uploaded)", image_id_hex);
+            return Ok((image_id_hex, true));
+        }
+
+        let (key, elf) = (image_id_hex.clone(), elf.to_vec());
+        client
+            .run(move |client| client.upload_img(&key, elf))
+            .await
+            .context("Upload image")?;
+        self.images.insert(scope, &image_id_hex);
+        log::info!("Image ID: 0x{}", image_id_hex);
+
+        Ok((image_id_hex, false))
+    }
+
+    async fn upload_input(
+        &self,
+        client: &BonsaiClient,
+        input: &[u8],
+    ) -> Result<(String, bool), ProverError> {
+        let key: [u8; 32] = Sha256::digest(input).into();
+        if let Some(input_id) = self.inputs.lock().unwrap().get(&key) {
+            log::info!("Reusing input: {}", input_id);
+            return Ok((input_id, true));
+        }
+
+        let input = input.to_vec();
+        let input_id = client
+            .run(move |client| client.upload_input(input))
+            .await
+            .context("Upload input")?;
+        self.inputs.lock().unwrap().insert(key, input_id.clone());
+
+        Ok((input_id, false))
+    }
+
+    /// Starts a session proving `input` with `elf`, and returns its ID.
+    async fn create_session(
+        &self,
+        client: &BonsaiClient,
+        scope: &str,
+        elf: &[u8],
+        input: &[u8],
+    ) -> Result<String, ProverError> {
+        let (image_id, image_reused) = self.upload_image(client, scope, elf).await?;
+        let (input_id, input_reused) = self.upload_input(client, input).await?;
+        let id = image_id.clone();
+        match client
+            .run(move |client| client.create_session(id, input_id, vec![]))
+            .await
+        {
+            Ok(session) => Ok(session.uuid),
+            // A registered image may have been purged, or a reused input
+            // expired, on Bonsai: upload them again, once.
+            Err(err) if image_reused || input_reused => {
+                log::info!("Failed to reuse uploads, uploading them again: {:?}", err);
+                if image_reused {
+                    self.images.remove(scope, &image_id);
+                }
+                if input_reused {
+                    self.inputs
+                        .lock()
+                        .unwrap()
+                        .remove(&<[u8; 32]>::from(Sha256::digest(input)));
+                }
+                let (image_id, _) = self.upload_image(client, scope, elf).await?;
+                let (input_id, _) = self.upload_input(client, input).await?;
+                Ok(client
+                    .run(move |client| client.create_session(image_id, input_id, vec![]))
+                    .await
+                    .context("Create session")?
+                    .uuid)
+            }
+            Err(err) => Err(err.context("Create session").into()),
+        }
+    }
+
+    async fn prove_session(&self, elf: &[u8], input: &[u8]) -> Result<ProofBundle, ProverError> {
+        let started_at = unix_now();
+        let client = BonsaiClient::from_env().await.context("Bonsai client")?;
+
+        // Start a session running the prover.
+        let uuid = self
+            .create_session(&client, &bonsai_scope(), elf, input)
+            .await?;
+        log::info!("Created session: {}", uuid);
+        let mut interval = self.poll.interval;
+        let receipt_url = loop {
+            let session = SessionId::new(uuid.clone());
+            let res = client
+                .run(move |client| session.status(client))
+                .await
+                .context("Session status")?;
+            match res.status.as_str() {
+                "RUNNING" => {
+                    log::info!(
+                        "Current status: {} - state: {} - continue polling...",
+                        res.status,
+                        res.state.unwrap_or_default()
+                    );
+                }
+                "SUCCEEDED" => {
+                    break res
+                        .receipt_url
+                        .context("API error, missing receipt on completed session")?;
+                }
+                _ => {
+                    return Err(ProverError::SessionFailed {
+                        uuid,
+                        status: res.status,
+                        message: res.error_msg.unwrap_or_default(),
+                    });
+                }
+            }
+            tokio::time::sleep(interval).await;
+            interval = self.poll.next_interval(interval);
+        };
+
+        // Download the receipt, containing the output.
+        let receipt_buf = client
+            .run(move |client| client.download(&receipt_url))
+            .await
+            .context("Download receipt")?;
+        let receipt: Receipt = bincode::deserialize(&receipt_buf).context("Read receipt")?;
+
+        // Don't pay for a snark of a rejected input.
+        let image_id = FixedBytes::from_slice(compute_image_id(elf)?.as_bytes());
+        check_guest_output(image_id, &receipt.journal.bytes)?;
+
+        // Fetch the snark.
+        let session_id = uuid.clone();
+        let snark_uuid = client
+            .run(move |client| client.create_snark(uuid))
+            .await
+            .context("Create snark")?
+            .uuid;
+        log::info!("Created snark session: {}", snark_uuid);
+        let mut interval = self.poll.interval;
+        let snark_receipt = loop {
+            let snark_session = SnarkId::new(snark_uuid.clone());
+            let res = client
+                .run(move |client| snark_session.status(client))
+                .await
+                .context("Snark status")?;
+            match res.status.as_str() {
+                "RUNNING" => {
+                    log::info!("Current status: {} - continue polling...", res.status,);
+                }
+                "SUCCEEDED" => {
+                    break res.output.context("No snark generated :(")?;
+                }
+                _ => {
+                    return Err(ProverError::SnarkFailed {
+                        uuid: snark_uuid,
+                        status: res.status,
+                        message: res.error_msg.unwrap_or_default(),
+                    });
+                }
+            }
+            tokio::time::sleep(interval).await;
+            interval = self.poll.next_interval(interval);
+        };
+
+        let snark = snark_receipt.snark;
+        log::debug!("Snark proof!: {snark:?}");
+
+        let seal = Seal::abi_encode(snark).context("Read seal")?;
+        let post_state_digest: FixedBytes<32> = snark_receipt
+            .post_state_digest
+            .as_slice()
+            .try_into()
+            .context("Read post_state_digest")?;
+        let journal = snark_receipt.journal;
+
+        let mut bundle = ProofBundle::new(
+            FixedBytes::from_slice(compute_image_id(elf)?.as_bytes()),
+            journal,
+            post_state_digest,
+            seal,
+            started_at,
+        )?;
+        bundle.session = Some(BonsaiSession {
+            session_id,
+            snark_id: snark_uuid,
+        });
+        Ok(bundle)
+    }
+}
+
+#[async_trait]
+impl Prover for BonsaiProver {
+    async fn prove(
+        &self,
+        elf: &[u8],
+        input: &[u8],
+        cancel: &CancellationToken,
+    ) -> Result<ProofBundle, ProverError> {
+        let proof = async {
+            match self.poll.timeout {
+                Some(timeout) => tokio::time::timeout(timeout, self.prove_session(elf, input))
+                    .await
+                    .map_err(|_| ProverError::Timeout(timeout))?,
+                None => self.prove_session(elf, input).await,
+            }
+        };
+
+        // Cancelling stops polling; the sessions already started on Bonsai
+        // run to completion.
+        tokio::select! {
+            _ = cancel.cancelled() => Err(ProverError::Cancelled),
+            res = proof => res,
+        }
+    }
+}
+
+/// Runs a blocking prover on the blocking thread pool, returning early if
+/// `cancel` is cancelled.
+async fn prove_blocking(
+    elf: &[u8],
+    input: &[u8],
+    cancel: &CancellationToken,
+    prove: impl FnOnce(&[u8], &[u8]) -> Result<Proof, ProverError> + Send + 'static,
+) -> Result<ProofBundle, ProverError> {
+    let started_at = unix_now();
+    let image_id = FixedBytes::from_slice(compute_image_id(elf)?.as_bytes());
+    let (elf, input) = (elf.to_vec(), input.to_vec());
+    let task = tokio::task::spawn_blocking(move || prove(&elf, &input));
+
+    let (journal, post_state_digest, seal) = tokio::select! {
+        _ = cancel.cancelled() => return Err(ProverError::Cancelled),
+        res = task => res.context("Prover task")??,
+    };
+    Ok(ProofBundle::new(
+        image_id,
+        journal,
+        post_state_digest,
+        seal,
+        started_at,
+    )?)
+}
+
+/// An implementation of a Prover that proves on this machine, including the
+/// Groth16 compression.
+///
+/// It never uses Bonsai, even when `BONSAI_API_URL` and `BONSAI_API_KEY` are
+/// set: receipts come from the in-process prover with the `prove` feature,
+/// and from `r0vm` otherwise, see [local_prover]. By default, the Groth16
+/// compression runs the RISC Zero prover image with Docker on an x86 host.
+/// Other [SnarkBackend]s wrap the succinct receipt with a local binary or
+/// container instead, and require the `prove` feature.
+#[derive(Default)]
+pub struct LocalProver {
+    backend: SnarkBackend,
+}
+
+impl LocalProver {
+    /// Sets the tool wrapping the succinct receipt into a Groth16 seal.
+    pub fn with_snark_backend(mut self, backend: SnarkBackend) -> Self {
+        self.backend = backend;
+        self
+    }
+}
+
+#[async_trait]
+impl Prover for LocalProver {
+    async fn prove(
+        &self,
+        elf: &[u8],
+        input: &[u8],
+        cancel: &CancellationToken,
+    ) -> Result<ProofBundle, ProverError> {
+        let backend = self.backend.clone();
+        prove_blocking(elf, input, cancel, move |elf, input| {
+            prove_local(elf, input, &backend)
+        })
+        .await
+    }
+}
+
+/// Returns the zkVM prover of the [LocalProver].
+///
+/// [risc0_zkvm::default_prover] picks Bonsai whenever `BONSAI_API_URL` and
+/// `BONSAI_API_KEY` are set, as they are for the [BonsaiProver], so the
+/// prover is built explicitly instead.
+#[cfg(feature = "prove")]
+fn local_prover() -> Rc<dyn ZkvmProver> {
+    Rc::new(risc0_zkvm::LocalProver::new("local"))
+}
+
+#[cfg(not(feature = "prove"))]
+fn local_prover() -> Rc<dyn ZkvmProver> {
+    // Finds `r0vm` the same way the zkVM does.
+    let r0vm = std::env::var("RISC0_SERVER_PATH").unwrap_or_else(|_| "r0vm".to_string());
+    Rc::new(risc0_zkvm::ExternalProver::new("ipc", r0vm))
+}
+
+fn prove_local(elf: &[u8], input: &[u8], backend: &SnarkBackend) -> Result<Proof, ProverError> {
+    // Check the guest output first, so rejected inputs are not proven.
+    execute(elf, input)?;
+
+    let env = ExecutorEnv::builder().write_slice(input).build()?;
+    let (seal, claim, journal) = match backend {
+        SnarkBackend::Docker => {
+            let receipt = local_prover()
+                .prove_with_opts(env, elf, &ProverOpts::groth16())?
+                .receipt;
+            let groth16 = receipt
+                .inner
+                .groth16()
+                .context("Expected a Groth16 receipt")?;
+            (
+                groth16.seal.clone(),
+                groth16.claim.clone(),
+                receipt.journal.bytes,
+            )
+        }
+        _ => {
+            let receipt = local_prover()
+                .prove_with_opts(env, elf, &ProverOpts::succinct())?
+                .receipt;
+            let succinct = receipt
+                .inner
+                .succinct()
+                .context("Expected a succinct receipt")?;
+            let seal = stark_to_snark(backend, succinct)?;
+            (seal, succinct.claim.clone(), receipt.journal.bytes)
+        }
+    };
+
+    let seal = encode_seal(&seal)?;
+    let post_state_digest = FixedBytes::from_slice(
+        claim
+            .as_value()
+            .context("Read receipt claim")?
+            .post
+            .digest()
+            .as_bytes(),
+    );
+
+    Ok((journal, post_state_digest, seal))
+}
+
+/// Encodes a local Groth16 seal the same way [Seal::abi_encode] encodes the
+/// seals returned by Bonsai.
+fn encode_seal(seal: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let seal = risc0_groth16::Seal::from_vec(seal).context("Read seal")?;
+    Seal::abi_encode(Groth16Seal {
+        a: seal.a,
+        b: seal.b,
+        c: seal.c,
+    })
+}
+
+/// An implementation of a Prover that only executes the guest and returns
+/// an empty seal, for use with a mock verifier that does not check seals.
+///
+/// This allows running the whole flow without network access or proving
+/// hardware. Its proofs are rejected by the `RiscZeroGroth16Verifier`.
+pub struct DevModeProver {}
+
+#[async_trait]
+impl Prover for DevModeProver {
+    async fn prove(
+        &self,
+        elf: &[u8],
+        input: &[u8],
+        cancel: &CancellationToken,
+    ) -> Result<ProofBundle, ProverError> {
+        prove_blocking(elf, input, cancel, execute).await
+    }
+}
+
+fn execute(elf: &[u8], input: &[u8]) -> Result<Proof, ProverError> {
+    let env = ExecutorEnv::builder().write_slice(input).build()?;
+    let session = default_executor().execute(env, elf)?;
+    if session.exit_code != ExitCode::Halted(0) {
+        return Err(anyhow!("Guest exited with {:?}", session.exit_code).into());
+    }
+
+    let journal = session.journal.bytes;
+    let image_id = compute_image_id(elf)?;
+    check_guest_output(FixedBytes::from_slice(image_id.as_bytes()), &journal)?;
+
+    // A guest halting normally ends in the same post state as the one
+    // committed to by a real receipt.
+    let claim = ReceiptClaim::ok(image_id, journal.clone());
+    let post_state_digest = FixedBytes::from_slice(
+        claim
+            .post
+            .as_value()
+            .context("Read post state")?
+            .digest()
+            .as_bytes(),
+    );
+
+    Ok((journal, post_state_digest, Vec::new()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use alloy_primitives::FixedBytes;
+    use methods::{JWT_VALIDATOR_ELF, JWT_VALIDATOR_ID, TEST_JWT};
+    use oidc_validator::{input::GuestInput, IdentityProvider};
+    use tokio_util::sync::CancellationToken;
+
+    use super::{DevModeProver, ImageRegistry, InputIds, PollConfig, Prover, ProverError};
+
+    #[tokio::test]
+    async fn test_dev_mode_prover() {
+        let input = GuestInput::new(IdentityProvider::Test, TEST_JWT).encode_compact();
+        let bundle = DevModeProver {}
+            .prove(JWT_VALIDATOR_ELF, &input, &CancellationToken::new())
+            .await
+            .unwrap();
+
+        assert!(bundle.seal.is_empty());
+        assert!(bundle.session.is_none());
+        assert_eq!(
+            bundle.image_id,
+            FixedBytes::from_slice(bytemuck::cast_slice(&JWT_VALIDATOR_ID))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_dev_mode_prover_rejects_invalid_jwt() {
+        let input = GuestInput::new(IdentityProvider::Test, "xxxx.xxxx.xxxx").encode_compact();
+        let err = DevModeProver {}
+            .prove(JWT_VALIDATOR_ELF, &input, &CancellationToken::new())
+            .await
+            .unwrap_err();
+
+        assert!(matches!(err, ProverError::Guest(_)));
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_prover() {
+        let input = GuestInput::new(IdentityProvider::Test, TEST_JWT).encode_compact();
+        let cancel = CancellationToken::new();
+        cancel.cancel();
+        let err = DevModeProver {}
+            .prove(JWT_VALIDATOR_ELF, &input, &cancel)
+            .await
+            .unwrap_err();
+
+        assert!(matches!(err, ProverError::Cancelled));
+    }
+
+    #[test]
+    fn test_local_prover_ignores_bonsai_env() {
+        std::env::set_var("BONSAI_API_URL", "https://api.bonsai.xyz");
+        std::env::set_var("BONSAI_API_KEY", "key");
+
+        let name = super::local_prover().get_name();
+        assert!(name == "local" || name == "ipc", "unexpected prover {name}");
+    }
+
+    #[test]
+    fn test_image_registry_persists() {
+        let dir = tempfile::tempdir().unwrap();
+        let registry = ImageRegistry {
+            dir: Some(dir.path().to_path_buf()),
+            ..Default::default()
+        };
+        assert!(!registry.contains("scope", "image"));
+        registry.insert("scope", "image");
+
+        // A new registry sharing the directory knows the image, but only for
+        // the same Bonsai account.
+        let registry = ImageRegistry {
+            dir: Some(dir.path().to_path_buf()),
+            ..Default::default()
+        };
+        assert!(registry.contains("scope", "image"));
+        assert!(!registry.contains("other", "image"));
+
+        // A removed image is forgotten on disk too.
+        registry.remove("scope", "image");
+        let registry = ImageRegistry {
+            dir: Some(dir.path().to_path_buf()),
+            ..Default::default()
+        };
+        assert!(!registry.contains("scope", "image"));
+    }
+
+    #[test]
+    fn test_input_ids_evict_least_recently_used() {
+        let mut inputs = InputIds {
+            capacity: 2,
+            ..Default::default()
+        };
+        inputs.insert([1; 32], "one".to_string());
+        inputs.insert([2; 32], "two".to_string());
+        assert_eq!(inputs.get(&[1; 32]).as_deref(), Some("one"));
+
+        // The input that was not used since is evicted.
+        inputs.insert([3; 32], "three".to_string());
+        assert_eq!(inputs.get(&[2; 32]), None);
+        assert_eq!(inputs.get(&[1; 32]).as_deref(), Some("one"));
+        assert_eq!(inputs.get(&[3; 32]).as_deref(), Some("three"));
+    }
+
+    #[test]
+    fn test_poll_backoff() {
+        let poll = PollConfig {
+            interval: Duration::from_secs(5),
+            max_interval: Duration::from_secs(30),
+            backoff: 2,
+            timeout: None,
+        };
+
+        assert_eq!(poll.next_interval(poll.interval), Duration::from_secs(10));
+        assert_eq!(
+            poll.next_interval(Duration::from_secs(20)),
+            Duration::from_secs(30)
+        );
+    }
+}
@@ -0,0 +1,782 @@
+// Copyright 2024 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Nonce-managed submission of the relayer transactions.
+//!
+//! Concurrent requests share one relayer wallet, so they cannot each read
+//! the pending nonce from the chain: two of them would pick the same nonce
+//! and one transaction would be dropped or replace the other. Instead, a
+//! single worker task submits the queued transactions one at a time with
+//! locally assigned nonces, and only waits for their receipts concurrently.
+//!
+//! While waiting, a transaction left pending for too long is replaced with
+//! higher fees, and a transaction dropped from the mempool is resubmitted
+//! with the same nonce, as set by the [ConfirmationPolicy]. The nonce of a
+//! transaction given up on is then used by a zero-value transfer of the
+//! relayer to itself, so the later transactions are not stuck behind a gap.
+
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, bail, Context, Result};
+use ethers::{prelude::*, types::transaction::eip2718::TypedTransaction, utils::keccak256};
+use tokio::sync::{mpsc, oneshot};
+
+use crate::{ChainClient, RpcPool};
+
+// Number of consecutive checks a transaction must be unknown to the node
+// before it is considered dropped, since a load balanced node may not have
+// seen it yet.
+const DROPPED_CHECKS: usize = 3;
+
+// Number of consecutive checks that may fail to read the node before a
+// pending transaction is given up on, since a failed read tells nothing about
+// the transaction.
+const MAX_READ_FAILURES: usize = 60;
+
+/// How the queue waits for a transaction to be confirmed.
+#[derive(Clone, Debug)]
+pub struct ConfirmationPolicy {
+    /// Number of blocks, including the one of the transaction, after which
+    /// the transaction is confirmed.
+    pub confirmations: u64,
+    /// Delay between two checks of a pending transaction. When the node
+    /// pushes new blocks, the transaction is checked on every block, and at
+    /// least this often.
+    pub poll_interval: Duration,
+    /// Time after which a pending transaction is replaced with higher fees.
+    pub replace_after: Duration,
+    /// Fee increase of a replacement, in percent. Nodes usually require at
+    /// least 10.
+    pub fee_bump: u64,
+    /// Maximum number of replacements of a transaction.
+    pub max_replacements: usize,
+    /// Maximum number of resubmissions of a dropped transaction.
+    pub max_resubmissions: usize,
+}
+
+impl Default for ConfirmationPolicy {
+    fn default() -> Self {
+        Self {
+            confirmations: 1,
+            poll_interval: Duration::from_secs(5),
+            replace_after: Duration::from_secs(180),
+            fee_bump: 15,
+            max_replacements: 3,
+            max_resubmissions: 3,
+        }
+    }
+}
+
+/// A confirmed transaction, and how it was confirmed.
+#[derive(Clone, Debug)]
+pub struct TxOutcome {
+    /// Receipt of the confirmed transaction, which is the last replacement
+    /// if the transaction was replaced.
+    pub receipt: TransactionReceipt,
+    /// Number of times the transaction was replaced with higher fees.
+    pub replacements: usize,
+    /// Number of times the transaction was resubmitted after being dropped.
+    pub resubmissions: usize,
+}
+
+impl TxOutcome {
+    /// Whether the transaction was confirmed as first submitted.
+    pub fn is_first_submission(&self) -> bool {
+        self.replacements == 0 && self.resubmissions == 0
+    }
+}
+
+struct Submission {
+    tx: TypedTransaction,
+    policy: ConfirmationPolicy,
+    reply: oneshot::Sender<Result<TxOutcome>>,
+}
+
+/// A submitted transaction given up on, reported to the worker.
+enum Abandoned {
+    /// A transaction whose nonce must be used by a cancellation.
+    Transaction {
+        tx: TypedTransaction,
+        policy: ConfirmationPolicy,
+    },
+    /// A cancellation that was not confirmed either, after which the nonce
+    /// is read from the chain again.
+    Cancellation,
+}
+
+/// Queue of the transactions sent from the relayer wallet of a
+/// [ChainClient].
+///
+/// The queue can be cloned and used from any thread or runtime, while its
+/// worker runs on the runtime it was spawned on.
+#[derive(Clone)]
+pub struct TxQueue {
+    chain: ChainClient,
+    policy: ConfirmationPolicy,
+    submissions: mpsc::UnboundedSender<Submission>,
+}
+
+impl TxQueue {
+    /// Spawns the worker submitting the queued transactions on the current
+    /// runtime.
+    pub fn spawn(chain: ChainClient) -> Self {
+        let (submissions, rx) = mpsc::unbounded_channel();
+        tokio::spawn(run(chain.clone(), rx));
+        Self {
+            chain,
+            policy: ConfirmationPolicy::default(),
+            submissions,
+        }
+    }
+
+    /// Sets how the transactions sent through this handle are confirmed.
+    pub fn with_confirmation_policy(mut self, policy: ConfirmationPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// The client the transactions are sent with.
+    pub fn chain(&self) -> &ChainClient {
+        &self.chain
+    }
+
+    /// Queues `tx` and waits until it is confirmed.
+    ///
+    /// Nonce, gas limit and fees are set by the queue. The transaction is
+    /// simulated first, and not sent if it would revert, in which case the
+    /// error is the decoded [ContractError](crate::ContractError). A
+    /// transaction reverting once mined is confirmed, with a failed receipt
+    /// status.
+    pub async fn send(&self, tx: TypedTransaction) -> Result<TxOutcome> {
+        let (reply, outcome) = oneshot::channel();
+        let policy = self.policy.clone();
+        self.submissions
+            .send(Submission { tx, policy, reply })
+            .map_err(|_| anyhow!("Transaction queue closed"))?;
+        outcome.await.context("Transaction queue closed")?
+    }
+
+    /// Queues a transaction calling `to` with the given calldata and value,
+    /// and waits until it is confirmed.
+    pub async fn send_call(
+        &self,
+        to: Address,
+        calldata: Vec<u8>,
+        value: U256,
+    ) -> Result<TxOutcome> {
+        self.send(self.chain.transaction(to, calldata, value)).await
+    }
+}
+
+async fn run(chain: ChainClient, mut submissions: mpsc::UnboundedReceiver<Submission>) {
+    // Nonce of the next transaction, `None` when it must be read from the
+    // chain. Only this task reads and advances it, so the tasks waiting for
+    // the transactions report the ones they give up on instead.
+    let mut nonce = None;
+    let (abandon, mut abandoned) = mpsc::unbounded_channel();
+    loop {
+        tokio::select! {
+            // Gaps are filled before new transactions take later nonces.
+            biased;
+            Some(abandoned) = abandoned.recv() => match abandoned {
+                Abandoned::Transaction { tx, policy } => {
+                    cancel(&chain, &mut nonce, &tx, policy, &abandon).await
+                }
+                Abandoned::Cancellation => {
+                    log::warn!("Cancellation failed, resyncing the nonce from the chain");
+                    nonce = None;
+                }
+            },
+            submission = submissions.recv() => {
+                let Some(Submission { tx, policy, reply }) = submission else {
+                    break;
+                };
+                // A transaction bound to revert is rejected before it takes a
+                // nonce.
+                let submitted = match chain.simulate(&tx).await {
+                    Ok(_) => submit(&chain, &mut nonce, tx).await,
+                    Err(err) => Err(err),
+                };
+                match submitted {
+                    Ok((tx, tx_hash)) => {
+                        let (chain, abandon) = (chain.clone(), abandon.clone());
+                        tokio::spawn(async move {
+                            let mut tx = tx;
+                            let outcome = wait(&chain, &policy, &mut tx, tx_hash).await;
+                            if let Err(err) = &outcome {
+                                log::warn!("Transaction {:?} failed: {:#}", tx_hash, err);
+                                let _ = abandon.send(Abandoned::Transaction { tx, policy });
+                            }
+                            let _ = reply.send(outcome);
+                        });
+                    }
+                    Err(err) => {
+                        let _ = reply.send(Err(err));
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Submits `tx` with the next nonce.
+///
+/// If the node rejects it and the pending nonce of the relayer differs from
+/// the local one, e.g. after transactions were sent from the same wallet
+/// elsewhere, the nonce is resynced and the submission retried once. Nonces
+/// are compared rather than error messages, which differ between clients.
+async fn submit(
+    chain: &ChainClient,
+    nonce: &mut Option<U256>,
+    tx: TypedTransaction,
+) -> Result<(TypedTransaction, TxHash)> {
+    let next = match *nonce {
+        Some(next) => next,
+        None => pending_nonce(chain).await?,
+    };
+    let err = match submit_with_nonce(chain, tx.clone(), next).await {
+        Ok(sent) => {
+            *nonce = Some(next + 1);
+            return Ok(sent);
+        }
+        Err(err) => err,
+    };
+
+    *nonce = None;
+    let synced = pending_nonce(chain).await?;
+    if synced == next {
+        *nonce = Some(next);
+        return Err(err);
+    }
+    log::info!("Nonce {} rejected, resynced to {}: {:#}", next, synced, err);
+    let sent = submit_with_nonce(chain, tx, synced).await?;
+    *nonce = Some(synced + 1);
+    Ok(sent)
+}
+
+async fn submit_with_nonce(
+    chain: &ChainClient,
+    mut tx: TypedTransaction,
+    nonce: U256,
+) -> Result<(TypedTransaction, TxHash)> {
+    tx.set_nonce(nonce);
+    chain.fill(&mut tx).await?;
+    log::info!("Transaction request: {:?}", tx);
+    let tx_hash = resend(chain, &tx).await?;
+    Ok((tx, tx_hash))
+}
+
+async fn pending_nonce(chain: &ChainClient) -> Result<U256> {
+    let next = chain
+        .client()
+        .get_transaction_count(chain.address(), Some(BlockNumber::Pending.into()))
+        .await
+        .context("Read nonce")?;
+    log::info!("Synced relayer nonce: {}", next);
+    Ok(next)
+}
+
+/// Signs `tx` and sends it as is, e.g. again with the nonce it already has.
+///
+/// A transaction the node fails to acknowledge is still sent if the node
+/// knows it afterwards, as after a timeout.
+async fn resend(chain: &ChainClient, tx: &TypedTransaction) -> Result<TxHash> {
+    let client = chain.client();
+    let signature = client.signer().sign_transaction(tx).await?;
+    let raw = tx.rlp_signed(&signature);
+    let tx_hash = H256(keccak256(&raw));
+    match client.provider().send_raw_transaction(raw).await {
+        Ok(pending) => Ok(pending.tx_hash()),
+        Err(err) => match client.provider().get_transaction(tx_hash).await {
+            Ok(Some(_)) => Ok(tx_hash),
+            _ => Err(err.into()),
+        },
+    }
+}
+
+/// Replaces the abandoned `tx` with a zero-value transfer of the relayer to
+/// itself, outbidding it in case it is still pending, so its nonce is used.
+/// The cancellation is then awaited like any other transaction.
+async fn cancel(
+    chain: &ChainClient,
+    nonce: &mut Option<U256>,
+    tx: &TypedTransaction,
+    policy: ConfirmationPolicy,
+    abandon: &mpsc::UnboundedSender<Abandoned>,
+) {
+    let Some(&tx_nonce) = tx.nonce() else {
+        return;
+    };
+    let mut cancellation = chain.transaction(chain.address(), Vec::new(), U256::zero());
+    cancellation.set_nonce(tx_nonce);
+    let sent = async {
+        chain.fill(&mut cancellation).await?;
+        outbid(&mut cancellation, tx, policy.fee_bump);
+        resend(chain, &cancellation).await
+    }
+    .await;
+
+    match sent {
+        Ok(tx_hash) => {
+            log::info!("Nonce {} cancelled by {:?}", tx_nonce, tx_hash);
+            let (chain, abandon) = (chain.clone(), abandon.clone());
+            tokio::spawn(async move {
+                if let Err(err) = wait(&chain, &policy, &mut cancellation, tx_hash).await {
+                    log::warn!("Cancellation {:?} failed: {:#}", tx_hash, err);
+                    let _ = abandon.send(Abandoned::Cancellation);
+                }
+            });
+        }
+        // The abandoned transaction may have been mined meanwhile.
+        Err(err) => {
+            log::warn!("Failed to cancel nonce {}, resyncing: {:#}", tx_nonce, err);
+            *nonce = None;
+        }
+    }
+}
+
+/// Waits until one of the submissions of `tx` is confirmed, replacing it
+/// when it stays pending and resubmitting it when it is dropped.
+///
+/// On return, `tx` is the last submission.
+async fn wait(
+    chain: &ChainClient,
+    policy: &ConfirmationPolicy,
+    tx: &mut TypedTransaction,
+    tx_hash: TxHash,
+) -> Result<TxOutcome> {
+    let client = chain.client();
+    let provider = client.provider();
+    // Any submission sharing the nonce may be the one mined.
+    let mut sent = vec![tx_hash];
+    let mut last_sent = Instant::now();
+    let (mut replacements, mut resubmissions, mut missing, mut failures) = (0, 0, 0, 0);
+    let mut blocks = match chain.supports_subscriptions() {
+        true => chain
+            .subscribe_blocks()
+            .await
+            .map_err(|err| log::warn!("Failed to subscribe to blocks, polling: {:#}", err))
+            .ok(),
+        false => None,
+    };
+
+    loop {
+        next_check(&mut blocks, policy.poll_interval).await;
+
+        let receipt = match find_receipt(provider, &sent).await {
+            Ok(receipt) => receipt,
+            Err(err) => {
+                read_failed(&mut failures, err)?;
+                continue;
+            }
+        };
+        if let Some(receipt) = receipt {
+            missing = 0;
+            // The receipt is fetched again on every check, so a transaction
+            // reorged out before its confirmation goes back to pending.
+            let Some(block) = receipt.block_number else {
+                continue;
+            };
+            let head = match provider.get_block_number().await {
+                Ok(head) => head,
+                Err(err) => {
+                    read_failed(&mut failures, err.into())?;
+                    continue;
+                }
+            };
+            failures = 0;
+            if head + 1 >= block + policy.confirmations {
+                log::info!("Transaction receipt: {:?}", receipt);
+                return Ok(TxOutcome {
+                    receipt,
+                    replacements,
+                    resubmissions,
+                });
+            }
+            continue;
+        }
+
+        let unknown = match is_unknown(provider, &sent).await {
+            Ok(unknown) => unknown,
+            Err(err) => {
+                read_failed(&mut failures, err)?;
+                continue;
+            }
+        };
+        failures = 0;
+        if unknown {
+            missing += 1;
+            if missing < DROPPED_CHECKS {
+                continue;
+            }
+            if resubmissions == policy.max_resubmissions {
+                bail!("Transaction {:?} dropped from the mempool", tx_hash);
+            }
+            log::info!("Transaction {:?} dropped, resubmitting it", tx_hash);
+            sent.push(resend(chain, tx).await.context("Resubmit transaction")?);
+            resubmissions += 1;
+            missing = 0;
+            last_sent = Instant::now();
+            continue;
+        }
+        missing = 0;
+
+        if last_sent.elapsed() >= policy.replace_after && replacements < policy.max_replacements {
+            let mut replacement = tx.clone();
+            bump_fees(&mut replacement, policy.fee_bump);
+            match resend(chain, &replacement).await {
+                Ok(hash) => {
+                    log::info!("Transaction {:?} pending, replaced by {:?}", tx_hash, hash);
+                    sent.push(hash);
+                    *tx = replacement;
+                    replacements += 1;
+                }
+                // The node may reject the replacement, e.g. when the pending
+                // transaction was mined meanwhile, so keep waiting for it.
+                Err(err) => log::warn!("Failed to replace transaction: {:#}", err),
+            }
+            last_sent = Instant::now();
+        }
+    }
+}
+
+/// Counts a failed read of the node, and fails once [MAX_READ_FAILURES]
+/// reads failed in a row.
+fn read_failed(failures: &mut usize, err: anyhow::Error) -> Result<()> {
+    *failures += 1;
+    if *failures >= MAX_READ_FAILURES {
+        return Err(err.context("Read transaction status"));
+    }
+    log::warn!("Failed to read transaction status: {:#}", err);
+    Ok(())
+}
+
+/// Waits for the next block, or for `interval` without a block
+/// subscription.
+async fn next_check(
+    blocks: &mut Option<SubscriptionStream<'_, RpcPool, Block<TxHash>>>,
+    interval: Duration,
+) {
+    let Some(stream) = blocks else {
+        return tokio::time::sleep(interval).await;
+    };
+    if let Ok(None) = tokio::time::timeout(interval, stream.next()).await {
+        log::warn!("Block subscription closed, polling");
+        *blocks = None;
+    }
+}
+
+async fn find_receipt(
+    provider: &Provider<RpcPool>,
+    sent: &[TxHash],
+) -> Result<Option<TransactionReceipt>> {
+    for hash in sent {
+        if let Some(receipt) = provider.get_transaction_receipt(*hash).await? {
+            return Ok(Some(receipt));
+        }
+    }
+    Ok(None)
+}
+
+/// Whether the node knows none of the submissions.
+async fn is_unknown(provider: &Provider<RpcPool>, sent: &[TxHash]) -> Result<bool> {
+    for hash in sent {
+        if provider.get_transaction(*hash).await?.is_some() {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// Raises the fees of `tx` by `percent`, and by at least 1 wei.
+fn bump_fees(tx: &mut TypedTransaction, percent: u64) {
+    let bump = |fee: U256| fee * (100 + percent) / 100 + 1;
+    match tx {
+        TypedTransaction::Eip1559(inner) => {
+            inner.max_fee_per_gas = inner.max_fee_per_gas.map(bump);
+            inner.max_priority_fee_per_gas = inner.max_priority_fee_per_gas.map(bump);
+        }
+        TypedTransaction::Legacy(inner) => inner.gas_price = inner.gas_price.map(bump),
+        TypedTransaction::Eip2930(inner) => inner.tx.gas_price = inner.tx.gas_price.map(bump),
+    }
+}
+
+/// Raises the fees of `tx` to at least the fees of `replaced` raised by
+/// `percent`, so the node accepts `tx` as its replacement.
+fn outbid(tx: &mut TypedTransaction, replaced: &TypedTransaction, percent: u64) {
+    let mut bumped = replaced.clone();
+    bump_fees(&mut bumped, percent);
+    let (max_fee, priority_fee) = match &bumped {
+        TypedTransaction::Eip1559(inner) => (inner.max_fee_per_gas, inner.max_priority_fee_per_gas),
+        other => (other.gas_price(), other.gas_price()),
+    };
+    match tx {
+        TypedTransaction::Eip1559(inner) => {
+            inner.max_fee_per_gas = inner.max_fee_per_gas.max(max_fee);
+            inner.max_priority_fee_per_gas = inner.max_priority_fee_per_gas.max(priority_fee);
+        }
+        other => {
+            if let Some(gas_price) = other.gas_price().max(max_fee) {
+                other.set_gas_price(gas_price);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use ethers::{prelude::*, types::transaction::eip2718::TypedTransaction};
+
+    use super::{bump_fees, ConfirmationPolicy, TxQueue};
+    use crate::mock::{MockNode, MOCK_GAS_PRICE};
+
+    const RECIPIENT: Address = H160([0x11; 20]);
+
+    fn test_policy() -> ConfirmationPolicy {
+        ConfirmationPolicy {
+            poll_interval: Duration::from_millis(10),
+            replace_after: Duration::from_secs(3600),
+            ..Default::default()
+        }
+    }
+
+    fn nonces(node: &MockNode) -> Vec<u64> {
+        node.received().iter().map(|tx| tx.nonce.as_u64()).collect()
+    }
+
+    #[tokio::test]
+    async fn test_nonce_assignment() {
+        let node = MockNode::new();
+        node.set_nonce(5);
+        let queue = TxQueue::spawn(node.chain()).with_confirmation_policy(test_policy());
+
+        let (first, second, third) = tokio::join!(
+            queue.send_call(RECIPIENT, Vec::new(), 1.into()),
+            queue.send_call(RECIPIENT, Vec::new(), 2.into()),
+            queue.send_call(RECIPIENT, Vec::new(), 3.into()),
+        );
+        for outcome in [first, second, third] {
+            assert!(outcome.unwrap().is_first_submission());
+        }
+
+        // Nonces are assigned locally after a single read.
+        assert_eq!(nonces(&node), [5, 6, 7]);
+        assert_eq!(node.requests("eth_getTransactionCount"), 1);
+    }
+
+    #[tokio::test]
+    async fn test_nonce_resync() {
+        let node = MockNode::new();
+        let queue = TxQueue::spawn(node.chain()).with_confirmation_policy(test_policy());
+        queue
+            .send_call(RECIPIENT, Vec::new(), U256::zero())
+            .await
+            .unwrap();
+
+        // Transactions sent from the same wallet elsewhere take nonces 1 to 3.
+        node.set_nonce(4);
+        queue
+            .send_call(RECIPIENT, Vec::new(), U256::zero())
+            .await
+            .unwrap();
+        queue
+            .send_call(RECIPIENT, Vec::new(), U256::zero())
+            .await
+            .unwrap();
+
+        assert_eq!(nonces(&node), [0, 4, 5]);
+        assert_eq!(node.requests("eth_getTransactionCount"), 2);
+    }
+
+    #[tokio::test]
+    async fn test_rejected_transaction_keeps_nonce() {
+        let node = MockNode::new();
+        let queue = TxQueue::spawn(node.chain()).with_confirmation_policy(test_policy());
+
+        node.reject_next("insufficient funds for gas * price + value");
+        assert!(queue
+            .send_call(RECIPIENT, Vec::new(), U256::zero())
+            .await
+            .is_err());
+        queue
+            .send_call(RECIPIENT, Vec::new(), U256::zero())
+            .await
+            .unwrap();
+
+        // The pending nonce did not change, so the rejected nonce is reused.
+        assert_eq!(nonces(&node), [0]);
+    }
+
+    #[tokio::test]
+    async fn test_abandoned_nonce_cancelled() {
+        let node = MockNode::new();
+        let chain = node.chain();
+        let policy = ConfirmationPolicy {
+            max_resubmissions: 0,
+            ..test_policy()
+        };
+        let queue = TxQueue::spawn(chain.clone()).with_confirmation_policy(policy);
+
+        node.drop_next(1);
+        let err = queue
+            .send_call(RECIPIENT, Vec::new(), 1.into())
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("dropped"), "{err:#}");
+        queue
+            .send_call(RECIPIENT, Vec::new(), 2.into())
+            .await
+            .unwrap();
+
+        // The dropped nonce is used by a zero-value transfer to the relayer,
+        // then the next transaction is mined behind it.
+        let received = node.received();
+        assert_eq!(nonces(&node), [0, 0, 1]);
+        assert_eq!(received[1].to, Some(chain.address()));
+        assert_eq!(received[1].value, U256::zero());
+        assert_eq!(received[2].value, 2.into());
+    }
+
+    #[tokio::test]
+    async fn test_dropped_transaction_resubmitted() {
+        let node = MockNode::new();
+        let queue = TxQueue::spawn(node.chain()).with_confirmation_policy(test_policy());
+
+        node.drop_next(1);
+        let outcome = queue
+            .send_call(RECIPIENT, Vec::new(), 1.into())
+            .await
+            .unwrap();
+
+        assert_eq!((outcome.replacements, outcome.resubmissions), (0, 1));
+        let received = node.received();
+        assert_eq!(received.len(), 2);
+        assert_eq!(received[0].hash, received[1].hash);
+        assert_eq!(outcome.receipt.transaction_hash, received[0].hash);
+    }
+
+    #[tokio::test]
+    async fn test_dropped_transaction_given_up() {
+        let node = MockNode::new();
+        let policy = ConfirmationPolicy {
+            max_resubmissions: 1,
+            ..test_policy()
+        };
+        let queue = TxQueue::spawn(node.chain()).with_confirmation_policy(policy);
+
+        node.drop_next(2);
+        let err = queue
+            .send_call(RECIPIENT, Vec::new(), 1.into())
+            .await
+            .unwrap_err();
+
+        assert!(err.to_string().contains("dropped"), "{err:#}");
+        // The transaction and its resubmission.
+        let received = node.received();
+        assert_eq!(received[0].hash, received[1].hash);
+    }
+
+    #[tokio::test]
+    async fn test_pending_transaction_replaced() {
+        let node = MockNode::new();
+        let policy = ConfirmationPolicy {
+            replace_after: Duration::ZERO,
+            fee_bump: 150,
+            ..test_policy()
+        };
+        let queue = TxQueue::spawn(node.chain()).with_confirmation_policy(policy);
+
+        // Only the replacement pays enough to be mined.
+        node.set_min_gas_price(2 * MOCK_GAS_PRICE);
+        let outcome = queue
+            .send_call(RECIPIENT, Vec::new(), 1.into())
+            .await
+            .unwrap();
+
+        assert_eq!((outcome.replacements, outcome.resubmissions), (1, 0));
+        let received = node.received();
+        assert_eq!(received.len(), 2);
+        assert_eq!(received[0].nonce, received[1].nonce);
+        assert_eq!(received[0].gas_price, MOCK_GAS_PRICE.into());
+        assert_eq!(received[1].gas_price, (MOCK_GAS_PRICE * 5 / 2 + 1).into());
+        assert_eq!(outcome.receipt.transaction_hash, received[1].hash);
+    }
+
+    #[tokio::test]
+    async fn test_node_outage_keeps_waiting() {
+        let node = MockNode::new();
+        let queue = TxQueue::spawn(node.chain()).with_confirmation_policy(test_policy());
+
+        // The transaction stays pending while the node is unreachable.
+        node.set_min_gas_price(2 * MOCK_GAS_PRICE);
+        let outage = async {
+            while node.received().is_empty() {
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            }
+            node.set_down(true);
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            node.set_down(false);
+            node.set_min_gas_price(0);
+        };
+        let (outcome, _) = tokio::join!(queue.send_call(RECIPIENT, Vec::new(), 1.into()), outage);
+
+        // The transaction was neither given up on nor cancelled.
+        let outcome = outcome.unwrap();
+        assert!(outcome.is_first_submission());
+        let received = node.received();
+        assert_eq!(received.len(), 1);
+        assert_eq!(outcome.receipt.transaction_hash, received[0].hash);
+    }
+
+    #[tokio::test]
+    async fn test_node_outage_given_up() {
+        let node = MockNode::new();
+        let queue = TxQueue::spawn(node.chain()).with_confirmation_policy(test_policy());
+
+        node.set_min_gas_price(2 * MOCK_GAS_PRICE);
+        let outage = async {
+            while node.received().is_empty() {
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            }
+            node.set_down(true);
+        };
+        let (outcome, _) = tokio::join!(queue.send_call(RECIPIENT, Vec::new(), 1.into()), outage);
+
+        let err = outcome.unwrap_err();
+        assert!(
+            err.to_string().contains("Read transaction status"),
+            "{err:#}"
+        );
+    }
+
+    #[test]
+    fn test_bump_fees() {
+        let mut tx: TypedTransaction = Eip1559TransactionRequest::new()
+            .max_fee_per_gas(1_000)
+            .max_priority_fee_per_gas(100)
+            .into();
+        bump_fees(&mut tx, 15);
+        let TypedTransaction::Eip1559(inner) = &tx else {
+            unreachable!()
+        };
+        assert_eq!(inner.max_fee_per_gas, Some(1_151.into()));
+        assert_eq!(inner.max_priority_fee_per_gas, Some(116.into()));
+
+        let mut tx: TypedTransaction = TransactionRequest::new().gas_price(1).into();
+        bump_fees(&mut tx, 15);
+        assert_eq!(tx.gas_price(), Some(2.into()));
+    }
+}
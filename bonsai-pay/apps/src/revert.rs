@@ -0,0 +1,172 @@
+// Copyright 2024 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Decoding of the reverts of the contracts.
+//!
+//! `BonsaiPay` and `AADemo` revert with `InvalidClaim`, `InvalidDeposit` and
+//! `TransferFailed`, and `ZRP` with the errors of its `Errors` library. The
+//! `require` messages and panics of all contracts use the standard
+//! `Error(string)` and `Panic(uint256)` reverts.
+
+use alloy_primitives::{FixedBytes, U256};
+use alloy_sol_types::{Panic, Revert, SolError};
+use ethers::providers::MiddlewareError;
+
+mod abi {
+    alloy_sol_types::sol! {
+        error InvalidClaim(string message);
+        error InvalidDeposit(string message);
+        error TransferFailed();
+
+        /// `Types.Proof` of the ZRP contract.
+        struct Proof {
+            bytes seal;
+            bytes32 postStateDigest;
+            bytes journal;
+        }
+
+        error DepositAlreadyExists(bytes32 depositId);
+        error DepositFailed(bytes32 claimId);
+        error InvalidProof(Proof proof);
+        error ClaimFailed(bytes32 claimId);
+        error WithdrawFailed(bytes32 id);
+        error InvalidDepositState(bytes32 depositId);
+        error InvalidFee();
+    }
+}
+
+/// Reason a contract call reverted.
+#[derive(Clone, Debug, PartialEq, thiserror::Error)]
+pub enum ContractError {
+    #[error("invalid claim: {0}")]
+    InvalidClaim(String),
+    #[error("invalid deposit: {0}")]
+    InvalidDeposit(String),
+    #[error("transfer failed")]
+    TransferFailed,
+    #[error("deposit {0} already exists")]
+    DepositAlreadyExists(FixedBytes<32>),
+    #[error("deposit for claim {0} failed")]
+    DepositFailed(FixedBytes<32>),
+    #[error("invalid proof")]
+    InvalidProof {
+        seal: Vec<u8>,
+        post_state_digest: FixedBytes<32>,
+        journal: Vec<u8>,
+    },
+    #[error("claim {0} failed")]
+    ClaimFailed(FixedBytes<32>),
+    #[error("withdrawal of {0} failed")]
+    WithdrawFailed(FixedBytes<32>),
+    #[error("deposit {0} cannot be withdrawn in its state")]
+    InvalidDepositState(FixedBytes<32>),
+    #[error("invalid fee")]
+    InvalidFee,
+    /// A `require` or `revert` with a message.
+    #[error("reverted: {0}")]
+    Revert(String),
+    /// A failed assertion, overflow or similar, with its panic code.
+    #[error("panicked with code {0}")]
+    Panic(U256),
+    /// Revert data matching none of the known errors, possibly empty.
+    #[error("reverted with data 0x{}", hex::encode(.0))]
+    Unknown(Vec<u8>),
+}
+
+impl ContractError {
+    /// Decodes the revert data returned by a failed call.
+    pub fn decode(data: &[u8]) -> Self {
+        decode::<abi::InvalidClaim>(data)
+            .map(|err| Self::InvalidClaim(err.message))
+            .or_else(|| {
+                decode::<abi::InvalidDeposit>(data).map(|err| Self::InvalidDeposit(err.message))
+            })
+            .or_else(|| decode::<abi::TransferFailed>(data).map(|_| Self::TransferFailed))
+            .or_else(|| {
+                decode::<abi::DepositAlreadyExists>(data)
+                    .map(|err| Self::DepositAlreadyExists(err.depositId))
+            })
+            .or_else(|| {
+                decode::<abi::DepositFailed>(data).map(|err| Self::DepositFailed(err.claimId))
+            })
+            .or_else(|| {
+                decode::<abi::InvalidProof>(data).map(|err| Self::InvalidProof {
+                    seal: err.proof.seal,
+                    post_state_digest: err.proof.postStateDigest,
+                    journal: err.proof.journal,
+                })
+            })
+            .or_else(|| decode::<abi::ClaimFailed>(data).map(|err| Self::ClaimFailed(err.claimId)))
+            .or_else(|| decode::<abi::WithdrawFailed>(data).map(|err| Self::WithdrawFailed(err.id)))
+            .or_else(|| {
+                decode::<abi::InvalidDepositState>(data)
+                    .map(|err| Self::InvalidDepositState(err.depositId))
+            })
+            .or_else(|| decode::<abi::InvalidFee>(data).map(|_| Self::InvalidFee))
+            .or_else(|| decode::<Revert>(data).map(|err| Self::Revert(err.reason)))
+            .or_else(|| decode::<Panic>(data).map(|err| Self::Panic(err.code)))
+            .unwrap_or_else(|| Self::Unknown(data.to_vec()))
+    }
+
+    /// Returns the revert of a failed RPC call, if the node returned revert
+    /// data.
+    pub fn from_middleware_error<E: MiddlewareError>(err: &E) -> Option<Self> {
+        let data = err.as_error_response()?.as_revert_data()?;
+        Some(Self::decode(&data))
+    }
+}
+
+fn decode<E: SolError>(data: &[u8]) -> Option<E> {
+    E::abi_decode(data, true).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use alloy_primitives::FixedBytes;
+    use alloy_sol_types::{Revert, SolError};
+
+    use super::{abi, ContractError};
+
+    #[test]
+    fn test_decode() {
+        let data = abi::InvalidClaim {
+            message: "Invalid proof".into(),
+        }
+        .abi_encode();
+        assert_eq!(
+            ContractError::decode(&data),
+            ContractError::InvalidClaim("Invalid proof".into())
+        );
+
+        let data = abi::TransferFailed {}.abi_encode();
+        assert_eq!(ContractError::decode(&data), ContractError::TransferFailed);
+
+        let data = abi::WithdrawFailed {
+            id: FixedBytes::repeat_byte(1),
+        }
+        .abi_encode();
+        assert_eq!(
+            ContractError::decode(&data),
+            ContractError::WithdrawFailed(FixedBytes::repeat_byte(1))
+        );
+
+        let data = Revert::from("You are not the owner").abi_encode();
+        assert_eq!(
+            ContractError::decode(&data),
+            ContractError::Revert("You are not the owner".into())
+        );
+
+        assert_eq!(ContractError::decode(&[]), ContractError::Unknown(vec![]));
+    }
+}
@@ -0,0 +1,397 @@
+// Copyright 2024 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Signers of the relayer wallet.
+//!
+//! The relayer key is read from a raw private key, an encrypted JSON keystore
+//! or a BIP-39 mnemonic, or stays in a remote signer. A remote signer is a
+//! local HTTP service speaking the following protocol, with hex strings
+//! prefixed by `0x`:
+//!
+//! - `GET /address` returns `{"address": <20 bytes>}`, the signing address.
+//! - `POST /sign` with `{"hash": <32 bytes>}` returns `{"signature": <65
+//!   bytes>}`, the signature `r || s || v` of the hash, with `v` either 0/1
+//!   or 27/28.
+//!
+//! Transactions, messages and typed data are hashed before they are sent, so
+//! the remote signer only signs hashes.
+
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use ethers::{
+    prelude::*,
+    signers::{coins_bip39::English, to_eip155_v, WalletError},
+    types::transaction::{eip2718::TypedTransaction, eip712::Eip712},
+    utils::hash_message,
+};
+use serde::{Deserialize, Serialize};
+
+/// Derivation path of the first account of a mnemonic.
+pub const DEFAULT_DERIVATION_PATH: &str = "m/44'/60'/0'/0/0";
+
+/// Where the relayer key comes from.
+#[derive(Clone)]
+pub enum SignerSource {
+    /// A hex encoded private key.
+    PrivateKey(String),
+    /// An encrypted JSON keystore file and its password.
+    Keystore { path: PathBuf, password: String },
+    /// A BIP-39 mnemonic and the derivation path of the account.
+    Mnemonic {
+        phrase: String,
+        derivation_path: String,
+    },
+    /// The base URL of a remote signer.
+    Remote(String),
+}
+
+impl SignerSource {
+    /// Loads the signer for `chain_id`, asking a remote signer for its
+    /// address.
+    pub async fn signer(&self, chain_id: u64) -> Result<RelayerSigner> {
+        let signer = match self {
+            Self::PrivateKey(key) => {
+                RelayerSigner::Local(key.parse::<LocalWallet>().context("Parse private key")?)
+            }
+            Self::Keystore { path, password } => RelayerSigner::Local(
+                LocalWallet::decrypt_keystore(path, password)
+                    .with_context(|| format!("Decrypt keystore {}", path.display()))?,
+            ),
+            Self::Mnemonic {
+                phrase,
+                derivation_path,
+            } => RelayerSigner::Local(
+                MnemonicBuilder::<English>::default()
+                    .phrase(phrase.as_str())
+                    .derivation_path(derivation_path)?
+                    .build()
+                    .context("Derive key from mnemonic")?,
+            ),
+            Self::Remote(url) => RelayerSigner::Remote(RemoteSigner::connect(url).await?),
+        };
+        Ok(signer.with_chain_id(chain_id))
+    }
+}
+
+/// Errors returned by a [RelayerSigner].
+#[derive(Debug, thiserror::Error)]
+pub enum SignerError {
+    #[error(transparent)]
+    Wallet(#[from] WalletError),
+    #[error("remote signer request failed: {0}")]
+    Remote(#[from] reqwest::Error),
+    #[error("invalid signature from the remote signer: {0}")]
+    InvalidSignature(#[from] SignatureError),
+    #[error("failed to encode typed data: {0}")]
+    Eip712(String),
+}
+
+/// The signer of the relayer transactions, holding the key or delegating to
+/// a remote signer.
+#[derive(Clone, Debug)]
+pub enum RelayerSigner {
+    Local(LocalWallet),
+    Remote(RemoteSigner),
+}
+
+#[async_trait]
+impl Signer for RelayerSigner {
+    type Error = SignerError;
+
+    async fn sign_message<S: Send + Sync + AsRef<[u8]>>(
+        &self,
+        message: S,
+    ) -> Result<Signature, Self::Error> {
+        match self {
+            Self::Local(wallet) => Ok(wallet.sign_message(message).await?),
+            Self::Remote(remote) => remote.sign_hash(hash_message(message)).await,
+        }
+    }
+
+    async fn sign_transaction(&self, tx: &TypedTransaction) -> Result<Signature, Self::Error> {
+        match self {
+            Self::Local(wallet) => Ok(wallet.sign_transaction(tx).await?),
+            Self::Remote(remote) => remote.sign_transaction(tx).await,
+        }
+    }
+
+    async fn sign_typed_data<T: Eip712 + Send + Sync>(
+        &self,
+        payload: &T,
+    ) -> Result<Signature, Self::Error> {
+        match self {
+            Self::Local(wallet) => Ok(wallet.sign_typed_data(payload).await?),
+            Self::Remote(remote) => {
+                let hash = payload
+                    .encode_eip712()
+                    .map_err(|err| SignerError::Eip712(err.to_string()))?;
+                remote.sign_hash(hash.into()).await
+            }
+        }
+    }
+
+    fn address(&self) -> Address {
+        match self {
+            Self::Local(wallet) => wallet.address(),
+            Self::Remote(remote) => remote.address,
+        }
+    }
+
+    fn chain_id(&self) -> u64 {
+        match self {
+            Self::Local(wallet) => wallet.chain_id(),
+            Self::Remote(remote) => remote.chain_id,
+        }
+    }
+
+    fn with_chain_id<T: Into<u64>>(self, chain_id: T) -> Self {
+        match self {
+            Self::Local(wallet) => Self::Local(wallet.with_chain_id(chain_id)),
+            Self::Remote(remote) => Self::Remote(RemoteSigner {
+                chain_id: chain_id.into(),
+                ..remote
+            }),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct AddressResponse {
+    address: Address,
+}
+
+#[derive(Serialize)]
+struct SignRequest {
+    hash: H256,
+}
+
+#[derive(Deserialize)]
+struct SignResponse {
+    signature: Bytes,
+}
+
+/// A signer delegating to a remote signer over HTTP.
+#[derive(Clone, Debug)]
+pub struct RemoteSigner {
+    client: reqwest::Client,
+    url: String,
+    address: Address,
+    chain_id: u64,
+}
+
+impl RemoteSigner {
+    /// Connects to the remote signer at `url` and reads its address.
+    pub async fn connect(url: &str) -> Result<Self> {
+        let client = reqwest::Client::new();
+        let url = url.trim_end_matches('/').to_string();
+        let AddressResponse { address } = client
+            .get(format!("{url}/address"))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await
+            .with_context(|| format!("Read address of the remote signer {url}"))?;
+        log::info!("Remote signer {} signs for {:?}", url, address);
+
+        Ok(Self {
+            client,
+            url,
+            address,
+            chain_id: 1,
+        })
+    }
+
+    async fn sign_transaction(&self, tx: &TypedTransaction) -> Result<Signature, SignerError> {
+        // The sighash must commit to the chain ID used for `v`.
+        let chain_id = tx.chain_id().map(|id| id.as_u64()).unwrap_or(self.chain_id);
+        let mut tx = tx.clone();
+        tx.set_chain_id(chain_id);
+
+        let mut signature = self.sign_hash(tx.sighash()).await?;
+        signature.v = to_eip155_v(signature.v as u8 - 27, chain_id);
+        Ok(signature)
+    }
+
+    /// Signs `hash`, returning a signature with `v` set to 27 or 28.
+    async fn sign_hash(&self, hash: H256) -> Result<Signature, SignerError> {
+        let SignResponse { signature } = self
+            .client
+            .post(format!("{}/sign", self.url))
+            .json(&SignRequest { hash })
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        let mut signature = Signature::try_from(signature.as_ref())?;
+        if signature.v < 27 {
+            signature.v += 27;
+        }
+        signature.verify(hash, self.address)?;
+        Ok(signature)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use ethers::{
+        core::rand::thread_rng, prelude::*, types::transaction::eip2718::TypedTransaction,
+    };
+    use serde_json::{json, Value};
+    use warp::Filter;
+
+    use super::{SignerSource, DEFAULT_DERIVATION_PATH};
+
+    const TEST_MNEMONIC: &str = "test test test test test test test test test test test junk";
+    const TEST_KEY: &str = "ac0974bec39a17e36ba4a6b4d238ff944bacb478cbed5efcae784d7bf4f2ff80";
+
+    /// Serves the remote signer protocol in-process, claiming to sign for
+    /// `address` and signing with `wallet`.
+    async fn serve_remote_signer(address: Address, wallet: LocalWallet) -> SocketAddr {
+        let address = warp::path("address")
+            .and(warp::get())
+            .map(move || warp::reply::json(&json!({ "address": address })));
+        let sign = warp::path("sign")
+            .and(warp::post())
+            .and(warp::body::json())
+            .map(move |request: Value| {
+                let hash: H256 = serde_json::from_value(request["hash"].clone()).unwrap();
+                let signature = wallet.sign_hash(hash).unwrap();
+                warp::reply::json(&json!({ "signature": Bytes::from(signature.to_vec()) }))
+            });
+        let (addr, server) = warp::serve(address.or(sign)).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+        addr
+    }
+
+    #[tokio::test]
+    async fn test_local_sources() {
+        let signer = SignerSource::Mnemonic {
+            phrase: TEST_MNEMONIC.into(),
+            derivation_path: DEFAULT_DERIVATION_PATH.into(),
+        }
+        .signer(11155111)
+        .await
+        .unwrap();
+        assert_eq!(
+            signer.address(),
+            "0xf39Fd6e51aad88F6F4ce6aB8827279cffFb92266"
+                .parse()
+                .unwrap()
+        );
+        assert_eq!(signer.chain_id(), 11155111);
+
+        let key = "0xac0974bec39a17e36ba4a6b4d238ff944bacb478cbed5efcae784d7bf4f2ff80";
+        let signer = SignerSource::PrivateKey(key.into())
+            .signer(1)
+            .await
+            .unwrap();
+        assert_eq!(
+            signer.address(),
+            "0xf39Fd6e51aad88F6F4ce6aB8827279cffFb92266"
+                .parse()
+                .unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_keystore_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let key = hex::decode(TEST_KEY).unwrap();
+        let (wallet, _) = LocalWallet::encrypt_keystore(
+            dir.path(),
+            &mut thread_rng(),
+            key,
+            "password",
+            Some("relayer"),
+        )
+        .unwrap();
+
+        let signer = SignerSource::Keystore {
+            path: dir.path().join("relayer"),
+            password: "password".into(),
+        }
+        .signer(1)
+        .await
+        .unwrap();
+        assert_eq!(signer.address(), wallet.address());
+
+        let wrong_password = SignerSource::Keystore {
+            path: dir.path().join("relayer"),
+            password: "wrong".into(),
+        };
+        assert!(wrong_password.signer(1).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_remote_signer() {
+        let wallet: LocalWallet = TEST_KEY.parse().unwrap();
+        let addr = serve_remote_signer(wallet.address(), wallet.clone()).await;
+
+        let signer = SignerSource::Remote(format!("http://{addr}/"))
+            .signer(11155111)
+            .await
+            .unwrap();
+        assert_eq!(signer.address(), wallet.address());
+        assert_eq!(signer.chain_id(), 11155111);
+
+        // The remote signer signs the same hashes as the local key.
+        assert_eq!(
+            signer.sign_message("hello").await.unwrap(),
+            wallet.sign_message("hello").await.unwrap()
+        );
+        let tx: TypedTransaction = Eip1559TransactionRequest::new()
+            .to(Address::repeat_byte(0x11))
+            .value(1)
+            .nonce(3)
+            .chain_id(11155111)
+            .into();
+        let signature = signer.sign_transaction(&tx).await.unwrap();
+        assert_eq!(
+            signature,
+            wallet
+                .with_chain_id(11155111u64)
+                .sign_transaction(&tx)
+                .await
+                .unwrap()
+        );
+        assert_eq!(signature.recover(tx.sighash()).unwrap(), signer.address());
+    }
+
+    #[tokio::test]
+    async fn test_remote_signer_rejects_foreign_signatures() {
+        // The signer claims the address of the test key, but signs with
+        // another one.
+        let wallet: LocalWallet = TEST_KEY.parse().unwrap();
+        let addr = serve_remote_signer(wallet.address(), LocalWallet::new(&mut thread_rng())).await;
+        let signer = SignerSource::Remote(format!("http://{addr}"))
+            .signer(1)
+            .await
+            .unwrap();
+
+        let Err(err) = signer.sign_message("hello").await else {
+            panic!("foreign signature accepted");
+        };
+        assert!(
+            matches!(err, super::SignerError::InvalidSignature(_)),
+            "{err}"
+        );
+    }
+}
@@ -0,0 +1,206 @@
+// Copyright 2024 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! ERC-20 tokens accepted by the apps.
+//!
+//! The [TokenRegistry] has the layout of `ui/src/assets/tokens.json`, the
+//! list of tokens offered by the UI on each network, and defaults to that
+//! file. As in the contracts, the zero address stands for Ether.
+
+use std::{collections::HashMap, fs, path::Path};
+
+use anyhow::{Context, Result};
+use ethers::{abi::AbiEncode, prelude::*};
+use serde::{Deserialize, Serialize};
+
+use crate::Call;
+
+abigen!(
+    Erc20,
+    r#"[
+        function approve(address spender, uint256 amount) external returns (bool)
+        function allowance(address owner, address spender) external view returns (uint256)
+        function balanceOf(address account) external view returns (uint256)
+        function transfer(address to, uint256 amount) external returns (bool)
+        function transferFrom(address from, address to, uint256 amount) external returns (bool)
+    ]"#
+);
+
+const BUNDLED_TOKENS: &str = include_str!("../../ui/src/assets/tokens.json");
+
+/// Returns the network name of `chain_id` in the token registry.
+pub fn network_name(chain_id: u64) -> Option<&'static str> {
+    match chain_id {
+        1 => Some("mainnet"),
+        5 => Some("goerli"),
+        11155111 => Some("sepolia"),
+        31337 => Some("localhost"),
+        _ => None,
+    }
+}
+
+/// Returns the calldata of `transfer(to, amount)` on an ERC-20 contract.
+pub fn erc20_transfer_calldata(to: Address, amount: U256) -> Vec<u8> {
+    TransferCall { to, amount }.encode()
+}
+
+/// Returns the calldata of `approve(spender, amount)` on an ERC-20 contract.
+pub fn erc20_approve_calldata(spender: Address, amount: U256) -> Vec<u8> {
+    ApproveCall { spender, amount }.encode()
+}
+
+/// A token listed in the [TokenRegistry].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Token {
+    /// Address of the ERC-20 contract, or the zero address for Ether.
+    pub address: Address,
+    pub name: String,
+    /// Icon shown by the UI.
+    pub icon: String,
+    pub decimals: u32,
+}
+
+impl Token {
+    /// Returns Ether, as listed in the registry of the UI.
+    pub fn ether() -> Self {
+        Self {
+            address: Address::zero(),
+            name: "ETH".to_string(),
+            icon: "/eth.svg".to_string(),
+            decimals: 18,
+        }
+    }
+
+    /// Returns whether the token is Ether rather than an ERC-20 token.
+    pub fn is_native(&self) -> bool {
+        self.address.is_zero()
+    }
+
+    /// Parses a decimal amount of the token, e.g. `"1.5"`, into its base
+    /// units.
+    pub fn parse_amount(&self, amount: &str) -> Result<U256> {
+        let units = ethers::utils::parse_units(amount, self.decimals)
+            .with_context(|| format!("Invalid {} amount: {amount}", self.name))?;
+        Ok(units.into())
+    }
+
+    /// Formats an amount in base units as a decimal amount of the token.
+    pub fn format_amount(&self, amount: U256) -> Result<String> {
+        Ok(ethers::utils::format_units(amount, self.decimals)?)
+    }
+
+    /// Returns the call sending `amount` of the token to `to` from the
+    /// caller, e.g. from an AA account.
+    pub fn transfer(&self, to: Address, amount: U256) -> Call {
+        if self.is_native() {
+            Call::eth_transfer(to, amount)
+        } else {
+            Call::erc20_transfer(self.address, to, amount)
+        }
+    }
+}
+
+/// The tokens accepted on each network, keyed by [network_name].
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct TokenRegistry {
+    networks: HashMap<String, Vec<Token>>,
+}
+
+impl TokenRegistry {
+    /// Returns the registry of the UI, `ui/src/assets/tokens.json`.
+    pub fn bundled() -> Self {
+        Self::from_json(BUNDLED_TOKENS).expect("invalid bundled token registry")
+    }
+
+    pub fn from_json(json: &str) -> Result<Self> {
+        serde_json::from_str(json).context("Read token registry")
+    }
+
+    /// Reads a registry in the layout of `ui/src/assets/tokens.json`.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let json = fs::read_to_string(path).with_context(|| format!("Read {}", path.display()))?;
+        Self::from_json(&json)
+    }
+
+    /// Returns the tokens accepted on `chain_id`.
+    pub fn tokens(&self, chain_id: u64) -> &[Token] {
+        network_name(chain_id)
+            .and_then(|network| self.networks.get(network))
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    /// Returns the token of `chain_id` with the given address, or name
+    /// ignoring case.
+    pub fn find(&self, chain_id: u64, token: &str) -> Option<&Token> {
+        let address = token.parse::<Address>().ok();
+        self.tokens(chain_id)
+            .iter()
+            .find(|t| Some(t.address) == address || t.name.eq_ignore_ascii_case(token))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ethers::prelude::*;
+
+    use super::{erc20_transfer_calldata, Token, TokenRegistry};
+    use crate::Call;
+
+    const REGISTRY: &str = r#"{
+        "localhost": [
+            { "address": "0x0000000000000000000000000000000000000000", "name": "ETH", "icon": "/eth.svg", "decimals": 18 },
+            { "address": "0x1111111111111111111111111111111111111111", "name": "USDC", "icon": "/usdc.svg", "decimals": 6 }
+        ]
+    }"#;
+
+    #[test]
+    fn test_find_token() {
+        let registry = TokenRegistry::from_json(REGISTRY).unwrap();
+
+        let usdc = registry.find(31337, "usdc").unwrap();
+        assert_eq!(
+            registry.find(31337, "0x1111111111111111111111111111111111111111"),
+            Some(usdc)
+        );
+        assert!(registry.find(11155111, "USDC").is_none());
+        assert!(registry.find(31337, "DAI").is_none());
+
+        assert_eq!(usdc.parse_amount("1.5").unwrap(), U256::from(1_500_000));
+        assert_eq!(
+            usdc.format_amount(U256::from(1_500_000)).unwrap(),
+            "1.500000"
+        );
+
+        // Ether is sent as value, tokens through the ERC-20 contract.
+        let to = Address::repeat_byte(0x22);
+        let eth = registry.find(31337, "ETH").unwrap();
+        assert_eq!(eth, &Token::ether());
+        assert_eq!(
+            eth.transfer(to, U256::one()),
+            Call::eth_transfer(to, U256::one())
+        );
+        let transfer = usdc.transfer(to, U256::one());
+        assert_eq!((transfer.to, transfer.value), (usdc.address, U256::zero()));
+        assert_eq!(
+            transfer.data.to_vec(),
+            erc20_transfer_calldata(to, U256::one())
+        );
+        assert_eq!(transfer.data[..4], [0xa9, 0x05, 0x9c, 0xbb]);
+
+        assert!(!TokenRegistry::bundled().tokens(11155111).is_empty());
+    }
+}
@@ -0,0 +1,281 @@
+// Copyright 2024 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! RPC transports of the Ethereum node, selected by the scheme of its URL.
+//!
+//! `http://` and `https://` URLs use HTTP, `ws://` and `wss://` URLs use
+//! WebSocket, and `ipc://` URLs or plain paths use the IPC socket of the node.
+//! Schemes are case-insensitive, and any other scheme is rejected.
+//! WebSocket and IPC connections support subscriptions, so new blocks and
+//! logs are pushed by the node instead of polled.
+
+use std::{fmt::Debug, str::FromStr};
+
+use anyhow::{bail, Context, Result};
+use async_trait::async_trait;
+use ethers::{
+    providers::{
+        Http, HttpClientError, Ipc, IpcError, JsonRpcClient, JsonRpcError, ProviderError,
+        PubsubClient, RpcError, Ws, WsClientError,
+    },
+    types::U256,
+};
+use serde::{de::DeserializeOwned, Serialize};
+
+/// Kind of transport used for an RPC URL.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TransportKind {
+    Http,
+    Ws,
+    Ipc,
+}
+
+impl TransportKind {
+    /// Returns the transport used for `url`, or an error for an unsupported
+    /// scheme.
+    pub fn of(url: &str) -> Result<Self> {
+        Ok(Self::parse(url)?.0)
+    }
+
+    /// Returns the transport used for `url`, and the path of the IPC socket
+    /// for IPC URLs.
+    fn parse(url: &str) -> Result<(Self, &str)> {
+        let Some((scheme, rest)) = url.split_once("://") else {
+            return Ok((Self::Ipc, url));
+        };
+        let kind = match scheme.to_ascii_lowercase().as_str() {
+            "http" | "https" => Self::Http,
+            "ws" | "wss" => Self::Ws,
+            "ipc" => return Ok((Self::Ipc, rest)),
+            _ => bail!("Unsupported RPC URL scheme {scheme}://, expected http(s), ws(s) or ipc"),
+        };
+        Ok((kind, url))
+    }
+}
+
+/// Errors returned by an [RpcTransport].
+#[derive(Debug, thiserror::Error)]
+pub enum TransportError {
+    #[error(transparent)]
+    Http(#[from] HttpClientError),
+    #[error(transparent)]
+    Ws(#[from] WsClientError),
+    #[error(transparent)]
+    Ipc(#[from] IpcError),
+    #[error("HTTP transports do not support subscriptions")]
+    SubscriptionsUnsupported,
+    #[cfg(test)]
+    #[error(transparent)]
+    Mock(#[from] crate::mock::MockError),
+}
+
+impl RpcError for TransportError {
+    fn as_error_response(&self) -> Option<&JsonRpcError> {
+        match self {
+            Self::Http(err) => err.as_error_response(),
+            Self::Ws(err) => err.as_error_response(),
+            Self::Ipc(err) => err.as_error_response(),
+            Self::SubscriptionsUnsupported => None,
+            #[cfg(test)]
+            Self::Mock(crate::mock::MockError::Rpc(err)) => Some(err),
+            #[cfg(test)]
+            Self::Mock(_) => None,
+        }
+    }
+
+    fn as_serde_error(&self) -> Option<&serde_json::Error> {
+        match self {
+            Self::Http(err) => err.as_serde_error(),
+            Self::Ws(err) => err.as_serde_error(),
+            Self::Ipc(err) => err.as_serde_error(),
+            Self::SubscriptionsUnsupported => None,
+            #[cfg(test)]
+            Self::Mock(crate::mock::MockError::Serde(err)) => Some(err),
+            #[cfg(test)]
+            Self::Mock(_) => None,
+        }
+    }
+}
+
+impl From<TransportError> for ProviderError {
+    fn from(err: TransportError) -> Self {
+        ProviderError::JsonRpcClientError(Box::new(err))
+    }
+}
+
+/// Connection to the Ethereum node over HTTP, WebSocket or IPC.
+#[derive(Clone, Debug)]
+pub enum RpcTransport {
+    Http(Http),
+    Ws(Ws),
+    Ipc(Ipc),
+    #[cfg(test)]
+    Mock(crate::mock::MockNode),
+}
+
+impl RpcTransport {
+    /// Connects to the node at `url`, with the transport matching its scheme.
+    pub async fn connect(url: &str) -> Result<Self> {
+        let transport = match TransportKind::parse(url)? {
+            (TransportKind::Http, _) => Self::Http(Http::from_str(url)?),
+            (TransportKind::Ws, _) => Self::Ws(
+                Ws::connect(url)
+                    .await
+                    .with_context(|| format!("Connect to {url}"))?,
+            ),
+            (TransportKind::Ipc, path) => Self::Ipc(
+                Ipc::connect(path)
+                    .await
+                    .with_context(|| format!("Connect to {path}"))?,
+            ),
+        };
+        Ok(transport)
+    }
+
+    /// Whether the node can push notifications over this transport.
+    pub fn supports_subscriptions(&self) -> bool {
+        matches!(self, Self::Ws(_) | Self::Ipc(_))
+    }
+}
+
+#[async_trait]
+impl JsonRpcClient for RpcTransport {
+    type Error = TransportError;
+
+    async fn request<T, R>(&self, method: &str, params: T) -> Result<R, Self::Error>
+    where
+        T: Debug + Serialize + Send + Sync,
+        R: DeserializeOwned + Send,
+    {
+        Ok(match self {
+            Self::Http(http) => http.request(method, params).await?,
+            Self::Ws(ws) => ws.request(method, params).await?,
+            Self::Ipc(ipc) => ipc.request(method, params).await?,
+            #[cfg(test)]
+            Self::Mock(node) => {
+                let params = serde_json::to_value(params).map_err(crate::mock::MockError::from)?;
+                let value = node.handle(method, params)?;
+                serde_json::from_value(value).map_err(crate::mock::MockError::from)?
+            }
+        })
+    }
+}
+
+impl PubsubClient for RpcTransport {
+    type NotificationStream = <Ipc as PubsubClient>::NotificationStream;
+
+    fn subscribe<T: Into<U256>>(&self, id: T) -> Result<Self::NotificationStream, Self::Error> {
+        match self {
+            Self::Http(_) => Err(TransportError::SubscriptionsUnsupported),
+            #[cfg(test)]
+            Self::Mock(_) => Err(TransportError::SubscriptionsUnsupported),
+            Self::Ws(ws) => Ok(ws.subscribe(id)?),
+            Self::Ipc(ipc) => Ok(ipc.subscribe(id)?),
+        }
+    }
+
+    fn unsubscribe<T: Into<U256>>(&self, id: T) -> Result<(), Self::Error> {
+        match self {
+            Self::Http(_) => Err(TransportError::SubscriptionsUnsupported),
+            #[cfg(test)]
+            Self::Mock(_) => Err(TransportError::SubscriptionsUnsupported),
+            Self::Ws(ws) => Ok(ws.unsubscribe(id)?),
+            Self::Ipc(ipc) => Ok(ipc.unsubscribe(id)?),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{process::Command, time::Duration};
+
+    use ethers::{providers::JsonRpcClient, types::U64, utils::Anvil};
+
+    use super::{RpcTransport, TransportKind};
+
+    #[test]
+    fn test_transport_kind() {
+        let kind = |url| TransportKind::of(url).unwrap();
+        assert_eq!(kind("https://rpc.sepolia.org"), TransportKind::Http);
+        assert_eq!(kind("http://localhost:8545"), TransportKind::Http);
+        assert_eq!(kind("HTTPS://rpc.sepolia.org"), TransportKind::Http);
+        assert_eq!(kind("ws://localhost:8545"), TransportKind::Ws);
+        assert_eq!(kind("wss://node.example"), TransportKind::Ws);
+        assert_eq!(kind("WSS://node.example"), TransportKind::Ws);
+        assert_eq!(kind("ipc:///tmp/anvil.ipc"), TransportKind::Ipc);
+        assert_eq!(kind("IPC:///tmp/anvil.ipc"), TransportKind::Ipc);
+        assert_eq!(kind("/tmp/anvil.ipc"), TransportKind::Ipc);
+
+        assert!(TransportKind::of("ftp://node.example").is_err());
+        assert!(TransportKind::of("htp://localhost:8545").is_err());
+    }
+
+    #[test]
+    fn test_ipc_path() {
+        let path = |url| TransportKind::parse(url).unwrap().1;
+        assert_eq!(path("ipc:///tmp/anvil.ipc"), "/tmp/anvil.ipc");
+        assert_eq!(path("Ipc:///tmp/anvil.ipc"), "/tmp/anvil.ipc");
+        assert_eq!(path("/tmp/anvil.ipc"), "/tmp/anvil.ipc");
+    }
+
+    #[tokio::test]
+    async fn test_unsupported_scheme_not_connected() {
+        let err = RpcTransport::connect("ftp://localhost:8545")
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("ftp://"), "{err:#}");
+    }
+
+    /// Connects over every transport to a local Anvil node, when Anvil is
+    /// installed.
+    #[tokio::test]
+    async fn test_connect_to_anvil() {
+        if Command::new("anvil").arg("--version").output().is_err() {
+            eprintln!("Skipping test_connect_to_anvil: anvil is not installed");
+            return;
+        }
+        let dir = tempfile::tempdir().unwrap();
+        let ipc_path = dir.path().join("anvil.ipc");
+        let anvil = Anvil::new()
+            .arg("--ipc")
+            .arg(ipc_path.display().to_string())
+            .spawn();
+
+        let urls = [
+            anvil.endpoint(),
+            anvil.ws_endpoint(),
+            format!("ipc://{}", ipc_path.display()),
+            ipc_path.display().to_string(),
+        ];
+        for url in urls {
+            let mut transport = RpcTransport::connect(&url).await;
+            // The IPC socket may be created after Anvil starts listening.
+            for _ in 0..50 {
+                if transport.is_ok() {
+                    break;
+                }
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                transport = RpcTransport::connect(&url).await;
+            }
+            let transport = transport.unwrap();
+            let chain_id: U64 = transport.request("eth_chainId", ()).await.unwrap();
+            assert_eq!(chain_id, U64::from(anvil.chain_id()), "{url}");
+            assert_eq!(
+                transport.supports_subscriptions(),
+                !url.starts_with("http"),
+                "{url}"
+            );
+        }
+    }
+}
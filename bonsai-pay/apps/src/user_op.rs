@@ -0,0 +1,576 @@
+// Copyright 2024 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! ERC-4337 user operations of `AADemo` accounts.
+//!
+//! Instead of the relayer calling `execute`, a [UserOperation] calling
+//! `executeUserOp` is sent to a bundler, which submits it to the EntryPoint
+//! v0.6. The proof rides in the signature of the operation, checked by
+//! `AADemo.validateUserOp`, and the account pays for its own gas. An account
+//! not deployed yet is deployed by the EntryPoint through the `initCode` of
+//! its first operation.
+//!
+//! The journal commits to the hash of the operation, which does not cover
+//! its signature, so the operation is built and its gas estimated with a
+//! [placeholder_signature] first, then proven and signed with
+//! [Bundler::sign].
+
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, ensure, Context, Result};
+use ethers::{
+    abi::{self, Token as AbiToken},
+    prelude::*,
+};
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::{encode_function_call, AccountFactory, Call, ChainClient, ProofBundle};
+
+abigen!(
+    EntryPoint,
+    r#"[
+        function getNonce(address sender, uint192 key) external view returns (uint256)
+        function balanceOf(address account) external view returns (uint256)
+        function depositTo(address account) external payable
+    ]"#
+);
+
+/// Address of the ERC-4337 EntryPoint v0.6, the same on every chain.
+pub const ENTRY_POINT_V06: Address = H160([
+    0x5f, 0xf1, 0x37, 0xd4, 0xb0, 0xfd, 0xcd, 0x49, 0xdc, 0xa3, 0x0c, 0x7c, 0xf5, 0x7e, 0x57, 0x8a,
+    0x02, 0x6d, 0x27, 0x89,
+]);
+
+// Margin added to the gas estimated by the bundler, in percent.
+const DEFAULT_GAS_MARGIN: u64 = 20;
+
+// Size of an ABI encoded Groth16 seal, `(uint256[2] a, uint256[2][2] b,
+// uint256[2] c)`.
+const SEAL_LEN: usize = 8 * 32;
+
+// Delay between two checks of a pending user operation.
+const RECEIPT_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// A user operation of the EntryPoint v0.6, serialized as expected by
+/// `eth_sendUserOperation`.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UserOperation {
+    pub sender: Address,
+    pub nonce: U256,
+    /// Factory address followed by the calldata deploying the sender, when
+    /// it is not deployed yet.
+    pub init_code: Bytes,
+    pub call_data: Bytes,
+    pub call_gas_limit: U256,
+    pub verification_gas_limit: U256,
+    pub pre_verification_gas: U256,
+    pub max_fee_per_gas: U256,
+    pub max_priority_fee_per_gas: U256,
+    pub paymaster_and_data: Bytes,
+    pub signature: Bytes,
+}
+
+impl UserOperation {
+    /// Returns the hash of the operation, as computed by
+    /// `EntryPoint.getUserOpHash`.
+    pub fn hash(&self, entry_point: Address, chain_id: u64) -> H256 {
+        let keccak = |bytes: &Bytes| AbiToken::FixedBytes(ethers::utils::keccak256(bytes).to_vec());
+        let packed = abi::encode(&[
+            AbiToken::Address(self.sender),
+            AbiToken::Uint(self.nonce),
+            keccak(&self.init_code),
+            keccak(&self.call_data),
+            AbiToken::Uint(self.call_gas_limit),
+            AbiToken::Uint(self.verification_gas_limit),
+            AbiToken::Uint(self.pre_verification_gas),
+            AbiToken::Uint(self.max_fee_per_gas),
+            AbiToken::Uint(self.max_priority_fee_per_gas),
+            keccak(&self.paymaster_and_data),
+        ]);
+        let encoded = abi::encode(&[
+            AbiToken::FixedBytes(ethers::utils::keccak256(packed).to_vec()),
+            AbiToken::Address(entry_point),
+            AbiToken::Uint(chain_id.into()),
+        ]);
+        ethers::utils::keccak256(encoded).into()
+    }
+}
+
+/// Returns the signature of a user operation carrying the proof of `bundle`,
+/// `abi.encode(claimId, postStateDigest, seal)`.
+pub fn proof_signature(bundle: &ProofBundle) -> Bytes {
+    abi::encode(&[
+        AbiToken::FixedBytes(bundle.claims.claim_id.to_vec()),
+        AbiToken::FixedBytes(bundle.post_state_digest.to_vec()),
+        AbiToken::Bytes(bundle.seal.clone()),
+    ])
+    .into()
+}
+
+/// Returns the signature of a user operation of the account of `claim_id`
+/// before it is proven, with a seal of zeros the size of a Groth16 seal, so
+/// its validation costs about as much as with the proof.
+pub fn placeholder_signature(claim_id: [u8; 32]) -> Bytes {
+    abi::encode(&[
+        AbiToken::FixedBytes(claim_id.to_vec()),
+        AbiToken::FixedBytes(vec![0; 32]),
+        AbiToken::Bytes(vec![0; SEAL_LEN]),
+    ])
+    .into()
+}
+
+/// Returns the `callData` of a user operation making `call` from the
+/// account, with `executeUserOp`.
+pub fn execute_user_op_calldata(call: &Call) -> Bytes {
+    encode_function_call(
+        "executeUserOp(address,uint256,bytes)",
+        &[
+            AbiToken::Address(call.to),
+            AbiToken::Uint(call.value),
+            AbiToken::Bytes(call.data.to_vec()),
+        ],
+    )
+    .expect("valid executeUserOp signature")
+    .into()
+}
+
+/// Gas limits of a user operation, as estimated by
+/// `eth_estimateUserOperationGas`.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GasEstimate {
+    #[serde(deserialize_with = "quantity")]
+    pub pre_verification_gas: U256,
+    #[serde(deserialize_with = "quantity")]
+    pub verification_gas_limit: U256,
+    #[serde(deserialize_with = "quantity")]
+    pub call_gas_limit: U256,
+}
+
+/// Receipt of an included user operation, from
+/// `eth_getUserOperationReceipt`.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UserOperationReceipt {
+    pub user_op_hash: H256,
+    pub sender: Address,
+    /// Whether the call of the operation succeeded.
+    pub success: bool,
+    #[serde(deserialize_with = "quantity")]
+    pub actual_gas_cost: U256,
+    #[serde(deserialize_with = "quantity")]
+    pub actual_gas_used: U256,
+    /// Receipt of the bundle transaction including the operation.
+    pub receipt: TransactionReceipt,
+}
+
+// Bundlers return quantities as hex strings or as JSON numbers.
+fn quantity<'de, D: Deserializer<'de>>(deserializer: D) -> Result<U256, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Quantity {
+        Number(u64),
+        String(String),
+    }
+
+    match Quantity::deserialize(deserializer)? {
+        Quantity::Number(value) => Ok(value.into()),
+        Quantity::String(value) => match value.strip_prefix("0x") {
+            Some(hex) => U256::from_str_radix(hex, 16).map_err(serde::de::Error::custom),
+            None => U256::from_dec_str(&value).map_err(serde::de::Error::custom),
+        },
+    }
+}
+
+/// Client of an ERC-4337 bundler, sending the user operations of `AADemo`
+/// accounts to an EntryPoint v0.6.
+#[derive(Clone, Debug)]
+pub struct Bundler {
+    provider: Provider<Http>,
+    entry_point: Address,
+    gas_margin: u64,
+}
+
+impl Bundler {
+    /// Creates a client of the bundler at `url`, sending operations to the
+    /// EntryPoint v0.6 at its usual address.
+    pub fn new(url: &str) -> Result<Self> {
+        let provider = Provider::<Http>::try_from(url)
+            .with_context(|| format!("Invalid bundler URL {url}"))?;
+        Ok(Self {
+            provider,
+            entry_point: ENTRY_POINT_V06,
+            gas_margin: DEFAULT_GAS_MARGIN,
+        })
+    }
+
+    /// Sets the EntryPoint the operations are sent to, for accounts
+    /// validating the operations of another one.
+    pub fn with_entry_point(mut self, entry_point: Address) -> Self {
+        self.entry_point = entry_point;
+        self
+    }
+
+    /// Sets the margin added to the estimated gas, in percent.
+    pub fn with_gas_margin(mut self, gas_margin: u64) -> Self {
+        self.gas_margin = gas_margin;
+        self
+    }
+
+    pub fn entry_point(&self) -> Address {
+        self.entry_point
+    }
+
+    /// Returns the EntryPoints supported by the bundler.
+    pub async fn supported_entry_points(&self) -> Result<Vec<Address>> {
+        self.provider
+            .request("eth_supportedEntryPoints", ())
+            .await
+            .context("Read supported entry points")
+    }
+
+    /// Returns the user operation of the account of `claim_id` making `call`,
+    /// with the current fees, the gas limits estimated by the bundler and a
+    /// [placeholder_signature].
+    ///
+    /// The proof of the operation commits to its hash, and is set as its
+    /// signature with [Bundler::sign]. As with `execute`, `call` must go to
+    /// the address committed in the journal. The operation deploys the
+    /// account if needed, and is paid by the account, which must hold enough
+    /// Ether or EntryPoint deposit.
+    pub async fn user_operation(
+        &self,
+        chain: &ChainClient,
+        factory: &AccountFactory,
+        claim_id: [u8; 32],
+        call: &Call,
+    ) -> Result<UserOperation> {
+        let sender = factory.account_address(claim_id);
+        let init_code = if factory.is_deployed(claim_id).await? {
+            Bytes::new()
+        } else {
+            factory.init_code(claim_id)
+        };
+        let nonce = EntryPoint::new(self.entry_point, chain.client())
+            .get_nonce(sender, U256::zero())
+            .call()
+            .await
+            .context("Read account nonce")?;
+
+        let client = chain.client();
+        let (max_fee_per_gas, max_priority_fee_per_gas) =
+            match client.estimate_eip1559_fees(None).await {
+                Ok(fees) => fees,
+                Err(err) => {
+                    log::info!("EIP-1559 fees unavailable, using a legacy gas price: {err}");
+                    let gas_price = client.get_gas_price().await?;
+                    (gas_price, gas_price)
+                }
+            };
+
+        let mut op = UserOperation {
+            sender,
+            nonce,
+            init_code,
+            call_data: execute_user_op_calldata(call),
+            max_fee_per_gas,
+            max_priority_fee_per_gas,
+            signature: placeholder_signature(claim_id),
+            ..Default::default()
+        };
+        let estimate = self.estimate_gas(&op).await?;
+        let margin = |gas: U256| gas * (100 + self.gas_margin) / 100;
+        op.pre_verification_gas = margin(estimate.pre_verification_gas);
+        op.verification_gas_limit = margin(estimate.verification_gas_limit);
+        op.call_gas_limit = margin(estimate.call_gas_limit);
+        Ok(op)
+    }
+
+    /// Returns `op` with the proof of `bundle` as signature, the proof being
+    /// bound to the hash of `op` on `chain_id`.
+    pub fn sign(
+        &self,
+        mut op: UserOperation,
+        bundle: &ProofBundle,
+        chain_id: u64,
+    ) -> Result<UserOperation> {
+        let user_op_hash = op.hash(self.entry_point, chain_id);
+        ensure!(
+            bundle.claims.user_op_hash.0 == user_op_hash.0,
+            "The proof is bound to the user operation {}, not {user_op_hash:?}",
+            bundle.claims.user_op_hash
+        );
+        op.signature = proof_signature(bundle);
+        Ok(op)
+    }
+
+    /// Estimates the gas limits of `op`.
+    pub async fn estimate_gas(&self, op: &UserOperation) -> Result<GasEstimate> {
+        self.provider
+            .request("eth_estimateUserOperationGas", (op, self.entry_point))
+            .await
+            .context("Estimate user operation gas")
+    }
+
+    /// Sends `op` to the bundler, and returns its hash.
+    pub async fn send(&self, op: &UserOperation) -> Result<H256> {
+        log::info!("User operation: {:?}", op);
+        self.provider
+            .request("eth_sendUserOperation", (op, self.entry_point))
+            .await
+            .context("Send user operation")
+    }
+
+    /// Returns the receipt of the operation of hash `user_op_hash`, or
+    /// `None` while it is pending.
+    pub async fn receipt(&self, user_op_hash: H256) -> Result<Option<UserOperationReceipt>> {
+        self.provider
+            .request("eth_getUserOperationReceipt", [user_op_hash])
+            .await
+            .context("Read user operation receipt")
+    }
+
+    /// Waits until the operation of hash `user_op_hash` is included, for at
+    /// most `timeout`.
+    pub async fn wait(
+        &self,
+        user_op_hash: H256,
+        timeout: Duration,
+    ) -> Result<UserOperationReceipt> {
+        let start = Instant::now();
+        loop {
+            if let Some(receipt) = self.receipt(user_op_hash).await? {
+                log::info!("User operation receipt: {:?}", receipt);
+                return Ok(receipt);
+            }
+            if start.elapsed() >= timeout {
+                bail!("User operation {user_op_hash:?} not included after {timeout:?}");
+            }
+            tokio::time::sleep(RECEIPT_POLL_INTERVAL).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use alloy_primitives::{Address as AlloyAddress, FixedBytes, U256 as AlloyU256};
+    use bonsai_pay_types::ClaimsData;
+    use ethers::prelude::*;
+    use methods::JWT_VALIDATOR_ELF;
+    use oidc_validator::{input::GuestInput, IdentityProvider};
+    use tokio_util::sync::CancellationToken;
+
+    use super::{
+        execute_user_op_calldata, placeholder_signature, proof_signature, Bundler, GasEstimate,
+        UserOperation, ENTRY_POINT_V06,
+    };
+    use crate::{
+        claim_id_of, AccountFactory, Call, ChainClient, DevModeProver, ProofBundle, Prover,
+        SignerSource,
+    };
+
+    // Token of test@email.com for the test identity provider, whose nonce is
+    // the zero address.
+    fn test_op() -> UserOperation {
+        UserOperation {
+            sender: Address::repeat_byte(0x11),
+            nonce: U256::from(1),
+            init_code: vec![0xaa; 3].into(),
+            call_data: vec![0xbb; 4].into(),
+            call_gas_limit: U256::from(100_000),
+            verification_gas_limit: U256::from(200_000),
+            pre_verification_gas: U256::from(50_000),
+            max_fee_per_gas: U256::from(2_000_000_000u64),
+            max_priority_fee_per_gas: U256::from(1_000_000_000u64),
+            paymaster_and_data: Bytes::new(),
+            signature: vec![0xcc; 5].into(),
+        }
+    }
+
+    #[test]
+    fn test_user_operation_hash() {
+        let op = test_op();
+        assert_eq!(
+            op.hash(ENTRY_POINT_V06, 31337),
+            "0x704d4828a613631db2b4465a1a8b57b5a5c51a3fe0bc7c4f34f90f055525910d"
+                .parse::<H256>()
+                .unwrap()
+        );
+
+        // The signature is not part of the hash.
+        let mut signed = op.clone();
+        signed.signature = Bytes::new();
+        assert_eq!(
+            signed.hash(ENTRY_POINT_V06, 31337),
+            op.hash(ENTRY_POINT_V06, 31337)
+        );
+        assert_ne!(op.hash(ENTRY_POINT_V06, 1), op.hash(ENTRY_POINT_V06, 31337));
+    }
+
+    #[test]
+    fn test_user_operation_json() {
+        let json = serde_json::to_value(test_op()).unwrap();
+        assert_eq!(json["callGasLimit"], "0x186a0");
+        assert_eq!(json["initCode"], "0xaaaaaa");
+        assert_eq!(json["paymasterAndData"], "0x");
+
+        // Bundlers return quantities as hex strings or numbers.
+        let estimate: GasEstimate = serde_json::from_str(
+            r#"{"preVerificationGas": "0xc350", "verificationGasLimit": 200000, "callGasLimit": "100000"}"#,
+        )
+        .unwrap();
+        assert_eq!(estimate.pre_verification_gas, U256::from(50_000));
+        assert_eq!(estimate.verification_gas_limit, U256::from(200_000));
+        assert_eq!(estimate.call_gas_limit, U256::from(100_000));
+
+        // `executeUserOp(address,uint256,bytes)`.
+        let call_data = execute_user_op_calldata(&Call::eth_transfer(Address::zero(), U256::one()));
+        assert_eq!(
+            call_data[..4],
+            ethers::utils::id("executeUserOp(address,uint256,bytes)")
+        );
+    }
+
+    #[test]
+    fn test_sign() {
+        let bundler = Bundler::new("http://localhost:3000").unwrap();
+        let claim_id = [0x22; 32];
+        let mut op = test_op();
+        op.signature = placeholder_signature(claim_id);
+        let user_op_hash = op.hash(ENTRY_POINT_V06, 31337);
+
+        let claims = ClaimsData::new(AlloyAddress::repeat_byte(0x11), claim_id.into())
+            .with_call(
+                AlloyU256::from(1),
+                FixedBytes::repeat_byte(0x33),
+                AlloyU256::ZERO,
+            )
+            .with_user_op_hash(user_op_hash.0.into());
+        let bundle = ProofBundle::new(
+            FixedBytes::repeat_byte(0x44),
+            claims.encode(),
+            FixedBytes::repeat_byte(0x55),
+            vec![0x66; 256],
+            1_700_000_000,
+        )
+        .unwrap();
+
+        let signed = bundler.sign(op.clone(), &bundle, 31337).unwrap();
+        assert_eq!(signed.signature, proof_signature(&bundle));
+        assert_eq!(signed.hash(ENTRY_POINT_V06, 31337), user_op_hash);
+
+        // The proof of an operation cannot sign another one.
+        op.nonce += U256::one();
+        assert!(bundler.sign(op, &bundle, 31337).is_err());
+        assert!(bundler.sign(signed, &bundle, 1).is_err());
+    }
+
+    // Runs against a local chain, e.g. anvil, with the EntryPoint v0.6, a
+    // bundler sending to it, an AADemoFactory whose verifier accepts dev mode
+    // proofs, and:
+    // - ETH_RPC_URL, BUNDLER_URL and AA_FACTORY, the URLs and the factory;
+    // - ETH_WALLET_PRIVATE_KEY, a funded wallet paying for the account;
+    // - CALL_JWT, a token of the test identity provider for test@email.com,
+    //   whose nonce authorizes the call, as printed when it is missing.
+    // It is skipped unless they are all set.
+    #[tokio::test]
+    async fn test_send_user_operation() {
+        let vars = [
+            "ETH_WALLET_PRIVATE_KEY",
+            "ETH_RPC_URL",
+            "AA_FACTORY",
+            "BUNDLER_URL",
+        ];
+        if let Some(name) = vars.iter().find(|name| std::env::var(name).is_err()) {
+            eprintln!("Skipping test_send_user_operation: {name} not set");
+            return;
+        }
+        let env = |name: &str| std::env::var(name).unwrap();
+        let key = env("ETH_WALLET_PRIVATE_KEY");
+        let rpc_url = env("ETH_RPC_URL");
+
+        let provider = Provider::<Http>::try_from(rpc_url.as_str()).unwrap();
+        let chain_id = provider.get_chainid().await.unwrap().as_u64();
+        let signer = SignerSource::PrivateKey(key)
+            .signer(chain_id)
+            .await
+            .unwrap();
+        let chain = ChainClient::connect(chain_id, &rpc_url, signer)
+            .await
+            .unwrap();
+        let factory = AccountFactory::connect(&chain, env("AA_FACTORY").parse().unwrap())
+            .await
+            .unwrap();
+        let bundler = Bundler::new(&env("BUNDLER_URL")).unwrap();
+        assert!(bundler
+            .supported_entry_points()
+            .await
+            .unwrap()
+            .contains(&bundler.entry_point()));
+
+        let claim_id = claim_id_of("test@email.com");
+        let call = Call::eth_transfer(Address::zero(), U256::exp10(12));
+        let op = bundler
+            .user_operation(&chain, &factory, claim_id, &call)
+            .await
+            .unwrap();
+
+        let nonce = factory.account_nonce(op.sender).await.unwrap();
+        let mut input = GuestInput::new(IdentityProvider::Test, "")
+            .with_call(
+                AlloyAddress::from_slice(call.to.as_bytes()),
+                AlloyU256::from_limbs(call.value.0),
+                call.func_hash().0.into(),
+                AlloyU256::from_limbs(nonce.0),
+            )
+            .with_user_op_hash(op.hash(bundler.entry_point(), chain_id).0.into());
+        let Ok(jwt) = std::env::var("CALL_JWT") else {
+            eprintln!(
+                "Skipping test_send_user_operation: CALL_JWT not set, to a token of nonce {}",
+                input.call_nonce()
+            );
+            return;
+        };
+        input.jwt = jwt;
+        let bundle = DevModeProver {}
+            .prove(
+                JWT_VALIDATOR_ELF,
+                &input.encode_compact(),
+                &CancellationToken::new(),
+            )
+            .await
+            .unwrap();
+        let op = bundler.sign(op, &bundle, chain_id).unwrap();
+
+        // The account pays for its deployment and gas.
+        chain
+            .send(op.sender, Vec::new(), U256::exp10(17))
+            .await
+            .unwrap();
+        let user_op_hash = bundler.send(&op).await.unwrap();
+        assert_eq!(user_op_hash, op.hash(bundler.entry_point(), chain_id));
+
+        let receipt = bundler
+            .wait(user_op_hash, Duration::from_secs(60))
+            .await
+            .unwrap();
+        assert!(receipt.success);
+        assert_eq!(receipt.sender, op.sender);
+        assert!(factory.is_deployed(claim_id).await.unwrap());
+        assert_eq!(factory.account_nonce(op.sender).await.unwrap(), nonce + 1);
+    }
+}
@@ -0,0 +1,207 @@
+// Copyright 2024 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Off-chain verification of the proofs submitted to the contracts.
+//!
+//! The contracts call `IRiscZeroVerifier.verify(seal, imageId,
+//! postStateDigest, journalDigest)`, which checks the Groth16 seal against
+//! the claim of a guest halting normally with that journal. The same checks
+//! run here, so a bad proof is rejected before paying gas for a transaction
+//! bound to revert.
+
+use alloy_primitives::FixedBytes;
+use alloy_sol_types::SolValue;
+use risc0_zkvm::{
+    sha::{Digest, Digestible},
+    Groth16Receipt, Groth16ReceiptVerifierParameters, InnerReceipt, Receipt, ReceiptClaim,
+    SystemState,
+};
+
+use crate::bundle::ProofBundle;
+
+alloy_sol_types::sol! {
+    /// ABI layout of the seal decoded by the `RiscZeroGroth16Verifier`.
+    struct Groth16Seal {
+        uint256[2] a;
+        uint256[2][2] b;
+        uint256[2] c;
+    }
+}
+
+/// Reasons a proof would be rejected by the on-chain verifier.
+#[derive(Debug, thiserror::Error)]
+pub enum VerifyError {
+    #[error("proof is for image {actual}, expected {expected}")]
+    ImageIdMismatch {
+        expected: FixedBytes<32>,
+        actual: FixedBytes<32>,
+    },
+    #[error("post state digest {actual} is not the halted state {expected}")]
+    PostStateDigestMismatch {
+        expected: FixedBytes<32>,
+        actual: FixedBytes<32>,
+    },
+    #[error("invalid seal: {0}")]
+    InvalidSeal(String),
+}
+
+/// Returns the post state digest of a guest that halted normally, as
+/// expected by the verifier contract.
+pub fn halted_post_state_digest() -> FixedBytes<32> {
+    let post = SystemState {
+        pc: 0,
+        merkle_root: Digest::ZERO,
+    };
+    FixedBytes::from_slice(post.digest().as_bytes())
+}
+
+/// Verifies an ABI encoded Groth16 seal, as returned by
+/// `risc0_ethereum_contracts::groth16::Seal::abi_encode`, against the image
+/// ID and journal of the guest.
+pub fn verify_seal(
+    seal: &[u8],
+    image_id: impl Into<Digest>,
+    journal: &[u8],
+) -> Result<(), VerifyError> {
+    let image_id = image_id.into();
+    let seal = Groth16Seal::abi_decode(seal, true)
+        .map_err(|err| VerifyError::InvalidSeal(err.to_string()))?;
+    let seal = risc0_groth16::Seal {
+        a: seal.a.iter().map(|x| x.to_be_bytes_vec()).collect(),
+        b: seal
+            .b
+            .iter()
+            .map(|row| row.iter().map(|x| x.to_be_bytes_vec()).collect())
+            .collect(),
+        c: seal.c.iter().map(|x| x.to_be_bytes_vec()).collect(),
+    };
+
+    let claim = ReceiptClaim::ok(image_id, journal.to_vec());
+    let groth16 = Groth16Receipt::new(
+        seal.to_vec(),
+        claim.into(),
+        Groth16ReceiptVerifierParameters::default().digest(),
+    );
+    Receipt::new(InnerReceipt::Groth16(groth16), journal.to_vec())
+        .verify(image_id)
+        .map_err(|err| VerifyError::InvalidSeal(err.to_string()))
+}
+
+/// Checks that the on-chain verifier accepts the proof of `bundle` for the
+/// guest `image_id`.
+pub fn verify_bundle(bundle: &ProofBundle, image_id: impl Into<Digest>) -> Result<(), VerifyError> {
+    let image_id = image_id.into();
+    let expected = FixedBytes::from_slice(image_id.as_bytes());
+    if bundle.image_id != expected {
+        return Err(VerifyError::ImageIdMismatch {
+            expected,
+            actual: bundle.image_id,
+        });
+    }
+
+    let expected = halted_post_state_digest();
+    if bundle.post_state_digest != expected {
+        return Err(VerifyError::PostStateDigestMismatch {
+            expected,
+            actual: bundle.post_state_digest,
+        });
+    }
+
+    verify_seal(&bundle.seal, image_id, &bundle.journal)
+}
+
+#[cfg(test)]
+mod tests {
+    use alloy_primitives::{Address, FixedBytes};
+    use alloy_sol_types::SolValue;
+    use methods::{JWT_VALIDATOR_ELF, JWT_VALIDATOR_ID, TEST_JWT};
+    use oidc_validator::{input::GuestInput, IdentityProvider};
+    use risc0_zkvm::sha::Digest;
+    use tokio_util::sync::CancellationToken;
+
+    use super::{halted_post_state_digest, verify_bundle, verify_seal, VerifyError};
+    use crate::{DevModeProver, Prover};
+
+    // A Groth16 proof of an earlier `jwt_validator` image, generated on
+    // Bonsai and accepted on-chain (see `tests/AaFork.t.sol`). Its journal is
+    // `abi.encode(msgSender, claimId)` of the unversioned claims.
+    const FIXTURE_IMAGE_ID: &str =
+        "41ddb98acca1bcd876a8a0d6a08c075a520b7855dedea1b808cd45f9c2612448";
+    const FIXTURE_MSG_SENDER: &str = "0x23D4a8d26B777c1FDcBB74afa79CAdA1caF772F8";
+    const FIXTURE_CLAIM_ID: &str =
+        "45387fddf42a08a8d896cebf60ee8dba2bdf80e60215285343fe03c0c853357e";
+    const FIXTURE_POST_STATE_DIGEST: &str =
+        "a3acc27117418996340b84e5a90f3ef4c49d22c79e44aad822ec9c313e1eb8e2";
+    const FIXTURE_SEAL: &str = "2e454bf4b1f037fabbc88665af1b10a413d03859bff26141e299ff24a94b2d50180c0824f309091336fb5d24a753edd094f9bada83185060a4a70e26afd63a0e0be098c6abad5e725423fc7256e53b51a891a619039a070eb09c4679dcb0d68713d8b92cf6fdc7035458cdd5dbceded84028751210a85f11b86d56209505b31f099d8b4843131a5f467c0140092717540c85564c907c0a1168c7a8091b60fe8a2568dcc69b5435a9d9f99ac6c62762df2f470926139de04618ca1bc04398088e07ba2c6861b0a8c3ccee2306d4e8dc02745a95591e10af36fc05b5faac0774fb01e7d34b5a5bb18531e57e088e49d0b7d98cbc8b6e5ad123b946d3f5920421a7";
+
+    fn fixture() -> (Digest, Vec<u8>, Vec<u8>) {
+        let image_id = Digest::try_from(hex::decode(FIXTURE_IMAGE_ID).unwrap().as_slice()).unwrap();
+        let msg_sender: Address = FIXTURE_MSG_SENDER.parse().unwrap();
+        let claim_id: FixedBytes<32> = FIXTURE_CLAIM_ID.parse().unwrap();
+        let journal = (msg_sender, claim_id).abi_encode();
+        (image_id, journal, hex::decode(FIXTURE_SEAL).unwrap())
+    }
+
+    #[test]
+    fn test_verify_groth16_fixture() {
+        let (image_id, journal, seal) = fixture();
+
+        assert_eq!(
+            halted_post_state_digest(),
+            FIXTURE_POST_STATE_DIGEST.parse::<FixedBytes<32>>().unwrap()
+        );
+        verify_seal(&seal, image_id, &journal).unwrap();
+    }
+
+    #[test]
+    fn test_reject_tampered_groth16_fixture() {
+        let (image_id, mut journal, seal) = fixture();
+
+        assert!(matches!(
+            verify_seal(&seal, JWT_VALIDATOR_ID, &journal),
+            Err(VerifyError::InvalidSeal(_))
+        ));
+        journal[31] ^= 1;
+        assert!(matches!(
+            verify_seal(&seal, image_id, &journal),
+            Err(VerifyError::InvalidSeal(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_reject_dev_mode_proof() {
+        let input = GuestInput::new(IdentityProvider::Test, TEST_JWT).encode_compact();
+        let mut bundle = DevModeProver {}
+            .prove(JWT_VALIDATOR_ELF, &input, &CancellationToken::new())
+            .await
+            .unwrap();
+
+        // Dev mode proofs end in the expected state, but have no seal.
+        assert_eq!(bundle.post_state_digest, halted_post_state_digest());
+        assert!(matches!(
+            verify_bundle(&bundle, JWT_VALIDATOR_ID),
+            Err(VerifyError::InvalidSeal(_))
+        ));
+
+        bundle.post_state_digest = Default::default();
+        assert!(matches!(
+            verify_bundle(&bundle, JWT_VALIDATOR_ID),
+            Err(VerifyError::PostStateDigestMismatch { .. })
+        ));
+        assert!(matches!(
+            verify_bundle(&bundle, [0u32; 8]),
+            Err(VerifyError::ImageIdMismatch { .. })
+        ));
+    }
+}
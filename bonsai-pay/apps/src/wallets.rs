@@ -0,0 +1,254 @@
+// Copyright 2024 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A pool of relayer wallets sending transactions in parallel.
+//!
+//! Each relayer has its own [TxQueue], so its nonces are tracked separately
+//! and a stuck transaction only delays the transactions of its relayer. A
+//! treasury wallet tops up the relayers running low on funds, which are
+//! skipped meanwhile.
+
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use anyhow::{ensure, Result};
+use ethers::{prelude::*, types::transaction::eip2718::TypedTransaction};
+use tokio::task::JoinHandle;
+
+use crate::{ChainClient, TxOutcome, TxQueue};
+
+/// How transactions are assigned to the relayers.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Assignment {
+    /// Each relayer in turn.
+    #[default]
+    RoundRobin,
+    /// The relayer with the fewest transactions in flight.
+    LeastLoaded,
+}
+
+/// When the treasury tops up the relayers.
+#[derive(Clone, Debug)]
+pub struct RebalancePolicy {
+    /// Balance under which a relayer is topped up, and skipped until then.
+    pub min_balance: U256,
+    /// Amount sent to a relayer running low.
+    pub top_up: U256,
+    /// Delay between two checks of the relayer balances.
+    pub interval: Duration,
+}
+
+struct Relayer {
+    queue: TxQueue,
+    in_flight: AtomicUsize,
+    low: AtomicBool,
+}
+
+/// Decrements the transactions in flight of a relayer when dropped.
+struct InFlight<'a>(&'a Relayer);
+
+impl Drop for InFlight<'_> {
+    fn drop(&mut self) {
+        self.0.in_flight.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// A pool of relayer wallets, each sending its transactions through its own
+/// [TxQueue].
+#[derive(Clone)]
+pub struct WalletPool {
+    relayers: Arc<[Relayer]>,
+    assignment: Assignment,
+    next: Arc<AtomicUsize>,
+}
+
+impl WalletPool {
+    /// Creates a pool of the relayers sending through `queues`.
+    pub fn new(queues: Vec<TxQueue>) -> Result<Self> {
+        ensure!(!queues.is_empty(), "No relayer");
+        let relayers = queues
+            .into_iter()
+            .map(|queue| Relayer {
+                queue,
+                in_flight: AtomicUsize::new(0),
+                low: AtomicBool::new(false),
+            })
+            .collect();
+        Ok(Self {
+            relayers,
+            assignment: Assignment::default(),
+            next: Default::default(),
+        })
+    }
+
+    /// Sets how transactions are assigned to the relayers.
+    pub fn with_assignment(mut self, assignment: Assignment) -> Self {
+        self.assignment = assignment;
+        self
+    }
+
+    /// The client of the first relayer, e.g. to build transactions.
+    pub fn chain(&self) -> &ChainClient {
+        self.relayers[0].queue.chain()
+    }
+
+    /// Addresses of the relayers.
+    pub fn addresses(&self) -> Vec<Address> {
+        self.relayers
+            .iter()
+            .map(|r| r.queue.chain().address())
+            .collect()
+    }
+
+    /// Sends `tx` from one of the relayers, as [TxQueue::send].
+    pub async fn send(&self, tx: TypedTransaction) -> Result<TxOutcome> {
+        let relayer = self.pick();
+        relayer.in_flight.fetch_add(1, Ordering::SeqCst);
+        let _in_flight = InFlight(relayer);
+        relayer.queue.send(tx).await
+    }
+
+    /// Sends a transaction calling `to` with the given calldata and value from
+    /// one of the relayers.
+    pub async fn send_call(
+        &self,
+        to: Address,
+        calldata: Vec<u8>,
+        value: U256,
+    ) -> Result<TxOutcome> {
+        self.send(self.chain().transaction(to, calldata, value))
+            .await
+    }
+
+    /// Returns the relayer of the next transaction. Relayers running low are
+    /// skipped, unless they all are.
+    fn pick(&self) -> &Relayer {
+        let count = self.relayers.len();
+        let start = self.next.fetch_add(1, Ordering::SeqCst) % count;
+        // Rotate from `start`, so ties are spread over the relayers.
+        let candidates = (0..count).map(|i| &self.relayers[(start + i) % count]);
+        let funded = || candidates.clone().filter(|r| !r.low.load(Ordering::SeqCst));
+        match self.assignment {
+            Assignment::RoundRobin => funded().next(),
+            Assignment::LeastLoaded => funded().min_by_key(|r| r.in_flight.load(Ordering::SeqCst)),
+        }
+        .unwrap_or(&self.relayers[start])
+    }
+
+    /// Spawns a task topping up the relayers from `treasury` when their
+    /// balance falls under the policy minimum.
+    pub fn spawn_rebalancer(&self, treasury: TxQueue, policy: RebalancePolicy) -> JoinHandle<()> {
+        let pool = self.clone();
+        tokio::spawn(async move {
+            loop {
+                for relayer in pool.relayers.iter() {
+                    if let Err(err) = rebalance(relayer, &treasury, &policy).await {
+                        log::warn!(
+                            "Failed to top up relayer {:?}: {:#}",
+                            relayer.queue.chain().address(),
+                            err
+                        );
+                    }
+                }
+                tokio::time::sleep(policy.interval).await;
+            }
+        })
+    }
+}
+
+async fn rebalance(relayer: &Relayer, treasury: &TxQueue, policy: &RebalancePolicy) -> Result<()> {
+    let chain = relayer.queue.chain();
+    let address = chain.address();
+    let balance = chain.client().get_balance(address, None).await?;
+    if balance >= policy.min_balance {
+        relayer.low.store(false, Ordering::SeqCst);
+        return Ok(());
+    }
+
+    log::info!(
+        "Relayer {:?} has {} wei left, topping it up with {} wei",
+        address,
+        balance,
+        policy.top_up
+    );
+    relayer.low.store(true, Ordering::SeqCst);
+    let outcome = treasury
+        .send_call(address, Vec::new(), policy.top_up)
+        .await?;
+    ensure!(
+        outcome.receipt.status == Some(1.into()),
+        "Top up transaction {:?} reverted",
+        outcome.receipt.transaction_hash
+    );
+    relayer.low.store(false, Ordering::SeqCst);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::Ordering;
+
+    use super::{Assignment, WalletPool};
+    use crate::{ChainClient, RpcPool, SignerSource, TxQueue};
+
+    async fn test_pool(assignment: Assignment) -> WalletPool {
+        let pool = RpcPool::connect(&["http://localhost:8545"]).await.unwrap();
+        let mut queues = Vec::new();
+        for key in 1..=3u8 {
+            let signer = SignerSource::PrivateKey(hex::encode([key; 32]))
+                .signer(1)
+                .await
+                .unwrap();
+            queues.push(TxQueue::spawn(ChainClient::new(1, pool.clone(), signer)));
+        }
+        WalletPool::new(queues).unwrap().with_assignment(assignment)
+    }
+
+    #[tokio::test]
+    async fn test_round_robin() {
+        let pool = test_pool(Assignment::RoundRobin).await;
+        let addresses = pool.addresses();
+        let picked: Vec<_> = (0..4)
+            .map(|_| pool.pick().queue.chain().address())
+            .collect();
+        assert_eq!(
+            picked,
+            [addresses[0], addresses[1], addresses[2], addresses[0]]
+        );
+
+        // Relayers running low are skipped, their turn goes to the next one.
+        pool.relayers[1].low.store(true, Ordering::SeqCst);
+        let picked: Vec<_> = (0..3)
+            .map(|_| pool.pick().queue.chain().address())
+            .collect();
+        assert_eq!(picked, [addresses[2], addresses[2], addresses[0]]);
+    }
+
+    #[tokio::test]
+    async fn test_least_loaded() {
+        let pool = test_pool(Assignment::LeastLoaded).await;
+        let addresses = pool.addresses();
+        pool.relayers[0].in_flight.store(2, Ordering::SeqCst);
+        pool.relayers[1].in_flight.store(1, Ordering::SeqCst);
+        assert_eq!(pool.pick().queue.chain().address(), addresses[2]);
+
+        pool.relayers[2].in_flight.store(3, Ordering::SeqCst);
+        assert_eq!(pool.pick().queue.chain().address(), addresses[1]);
+    }
+}
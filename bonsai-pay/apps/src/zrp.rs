@@ -0,0 +1,335 @@
+// Copyright 2024 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Typed client of the `ZRP` contract.
+//!
+//! `ZRP` escrows Ether and ERC-20 tokens under an identifier, here the claim
+//! ID of an email, and releases them to the beneficiary of a proof of the
+//! `zrp_validator` guest. The bindings are generated from the contract ABI in
+//! `ZRP.json`. As with the [BonsaiPayClient](crate::BonsaiPayClient),
+//! transactions are returned unsent.
+
+use alloy_sol_types::SolValue;
+use anyhow::{Context, Result};
+use bonsai_pay_types::ZrpClaimsData;
+use ethers::{prelude::*, types::transaction::eip2718::TypedTransaction};
+
+use crate::{claim_id_of, ChainClient, Erc20, ProofBundle, SignerClient};
+
+abigen!(Zrp, "./src/ZRP.json");
+
+mod abi {
+    alloy_sol_types::sol! {
+        /// `Types.Proof`, ABI encoded as the `data` of `claim` and
+        /// `transfer`.
+        struct Proof {
+            bytes seal;
+            bytes32 postStateDigest;
+            bytes journal;
+        }
+    }
+}
+
+/// Returns the identifier of `email` in the `ZRP` contract, as committed by
+/// the `zrp_validator` guest.
+pub fn zrp_id_of(email: &str) -> Bytes {
+    claim_id_of(email).to_vec().into()
+}
+
+/// Returns the claim ID of `id` and `token`, as computed by
+/// `ZRP.getClaimId`.
+pub fn zrp_claim_id(id: &[u8], token: Address) -> [u8; 32] {
+    zrp_key(Address::zero(), "claim", id, token, U256::zero())
+}
+
+/// Returns the ID of the deposit made by `depositor`, as computed by
+/// `ZRP.getDepositId`.
+pub fn zrp_deposit_id(depositor: Address, id: &[u8], token: Address, salt: U256) -> [u8; 32] {
+    zrp_key(depositor, "deposit", id, token, salt)
+}
+
+// `keccak256(abi.encodePacked(about, key, id, token, salt))`.
+fn zrp_key(about: Address, key: &str, id: &[u8], token: Address, salt: U256) -> [u8; 32] {
+    let mut salt_word = [0u8; 32];
+    salt.to_big_endian(&mut salt_word);
+
+    let mut packed = Vec::with_capacity(20 + key.len() + id.len() + 20 + 32);
+    packed.extend_from_slice(about.as_bytes());
+    packed.extend_from_slice(key.as_bytes());
+    packed.extend_from_slice(id);
+    packed.extend_from_slice(token.as_bytes());
+    packed.extend_from_slice(&salt_word);
+    ethers::utils::keccak256(packed)
+}
+
+/// Returns the `data` argument of `claim` and `transfer` for the proof of
+/// `bundle`, which must come from the `zrp_validator` guest.
+fn proof_data(bundle: &ProofBundle) -> Result<Bytes> {
+    ZrpClaimsData::decode(&bundle.journal)
+        .context("Bundle is not a proof of the zrp_validator guest")?;
+    let proof = abi::Proof {
+        seal: bundle.seal.clone().into(),
+        postStateDigest: bundle.post_state_digest,
+        journal: bundle.journal.clone().into(),
+    };
+    Ok(proof.abi_encode().into())
+}
+
+/// Client of a deployed `ZRP` contract.
+///
+/// `token` is the address of an ERC-20 contract, or the zero address for
+/// Ether.
+#[derive(Clone)]
+pub struct ZrpClient {
+    contract: Zrp<SignerClient>,
+}
+
+impl ZrpClient {
+    /// Creates a client of the contract at `address`, sending from the
+    /// wallet of `chain`.
+    pub fn new(chain: &ChainClient, address: Address) -> Self {
+        Self {
+            contract: Zrp::new(address, chain.client()),
+        }
+    }
+
+    pub fn address(&self) -> Address {
+        self.contract.address()
+    }
+
+    /// The generated bindings, for calls not covered by this client.
+    pub fn contract(&self) -> &Zrp<SignerClient> {
+        &self.contract
+    }
+
+    /// Returns the transaction depositing `amount` of `token` under `id`.
+    ///
+    /// Ether deposits carry `amount` as value. Token deposits are pulled
+    /// with `transferFrom`, so the sender must first approve the contract.
+    pub fn deposit_transaction(
+        &self,
+        id: Bytes,
+        token: Address,
+        amount: U256,
+        salt: U256,
+    ) -> TypedTransaction {
+        let call = self.contract.deposit(id, token, amount, salt);
+        if token.is_zero() {
+            call.value(amount).tx
+        } else {
+            call.tx
+        }
+    }
+
+    /// Returns the transactions depositing `amount` of `token` under `id`
+    /// from `depositor`: an `approve` of the contract when its allowance is
+    /// too low, then the deposit.
+    ///
+    /// They must be sent in order from the depositor's wallet, e.g. through
+    /// its [TxQueue](crate::TxQueue), each once the previous one is
+    /// confirmed, as the deposit would revert before the approval.
+    pub async fn deposit_transactions(
+        &self,
+        depositor: Address,
+        id: Bytes,
+        token: Address,
+        amount: U256,
+        salt: U256,
+    ) -> Result<Vec<TypedTransaction>> {
+        let mut txs = Vec::with_capacity(2);
+        if !token.is_zero() {
+            let erc20 = Erc20::new(token, self.contract.client());
+            let allowance = erc20
+                .allowance(depositor, self.address())
+                .call()
+                .await
+                .context("Read allowance")?;
+            if allowance < amount {
+                txs.push(erc20.approve(self.address(), amount).tx);
+            }
+        }
+        txs.push(self.deposit_transaction(id, token, amount, salt));
+        Ok(txs)
+    }
+
+    /// Returns the transaction depositing `amount` of `token` for `email`.
+    pub fn deposit_for_email_transaction(
+        &self,
+        email: &str,
+        token: Address,
+        amount: U256,
+        salt: U256,
+    ) -> TypedTransaction {
+        self.deposit_transaction(zrp_id_of(email), token, amount, salt)
+    }
+
+    /// Returns the transaction claiming the deposits of `token` proven by
+    /// `bundle`. It must be sent by the beneficiary committed in the journal.
+    pub fn claim_transaction(
+        &self,
+        bundle: &ProofBundle,
+        token: Address,
+    ) -> Result<TypedTransaction> {
+        Ok(self.contract.claim(proof_data(bundle)?, token).tx)
+    }
+
+    /// Returns the transaction sending `value` wei to `to` once the proof of
+    /// `bundle` is verified.
+    pub fn transfer_transaction(
+        &self,
+        bundle: &ProofBundle,
+        to: Address,
+        value: U256,
+    ) -> Result<TypedTransaction> {
+        Ok(self
+            .contract
+            .transfer(proof_data(bundle)?, to)
+            .value(value)
+            .tx)
+    }
+
+    /// Returns the transaction taking back a deposit not claimed yet. It
+    /// must be sent by the depositor, with the salt of the deposit.
+    pub fn withdraw_deposit_transaction(
+        &self,
+        id: Bytes,
+        token: Address,
+        salt: U256,
+    ) -> TypedTransaction {
+        self.contract.withdraw_deposit(id, token, salt).tx
+    }
+
+    /// Returns the balance of `token` pending for `id`.
+    pub async fn claim_balance(&self, id: &[u8], token: Address) -> Result<U256> {
+        self.contract
+            .claim_balance(zrp_claim_id(id, token))
+            .call()
+            .await
+            .context("Read claim balance")
+    }
+
+    /// Returns the balance left in a deposit.
+    pub async fn deposit_balance(&self, deposit_id: [u8; 32]) -> Result<U256> {
+        self.contract
+            .deposit_balance(deposit_id)
+            .call()
+            .await
+            .context("Read deposit balance")
+    }
+
+    /// Returns the fee taken from claims, in percent.
+    pub async fn claim_fee(&self) -> Result<U256> {
+        self.contract
+            .claim_fee()
+            .call()
+            .await
+            .context("Read claim fee")
+    }
+
+    pub async fn paused(&self) -> Result<bool> {
+        self.contract.paused().call().await.context("Read paused")
+    }
+
+    /// Returns the transaction pausing deposits, claims and withdrawals.
+    /// Only the owner can send it.
+    pub fn pause_transaction(&self) -> TypedTransaction {
+        self.contract.pause().tx
+    }
+
+    /// Returns the transaction unpausing the contract. Only the owner can
+    /// send it.
+    pub fn unpause_transaction(&self) -> TypedTransaction {
+        self.contract.unpause().tx
+    }
+
+    /// Returns the transaction setting the claim fee, in percent below 100.
+    /// Only the owner can send it.
+    pub fn set_fee_transaction(&self, fee_percentage: U256) -> TypedTransaction {
+        self.contract.set_fee(fee_percentage).tx
+    }
+
+    /// Returns the transaction replacing the verifier contract. Only the
+    /// owner can send it.
+    pub fn set_verifier_transaction(&self, verifier: Address) -> TypedTransaction {
+        self.contract.set_verifier(verifier).tx
+    }
+
+    /// Returns the transaction replacing the image ID of the guest, e.g.
+    /// after upgrading the `zrp_validator`. Only the owner can send it.
+    pub fn set_image_id_transaction(&self, image_id: [u8; 32]) -> TypedTransaction {
+        self.contract.set_image_id(image_id).tx
+    }
+
+    /// Returns the transaction sending the whole balance of `token` held by
+    /// the contract to `to`. Only the owner can send it.
+    pub fn withdraw_contract_transaction(&self, to: Address, token: Address) -> TypedTransaction {
+        self.contract.withdraw_contract(to, token).tx
+    }
+
+    /// Returns the events emitted by the contract between `from_block` and
+    /// `to_block`, with the block and transaction of each.
+    pub async fn events(
+        &self,
+        from_block: BlockNumber,
+        to_block: BlockNumber,
+    ) -> Result<Vec<(ZrpEvents, LogMeta)>> {
+        self.contract
+            .events()
+            .from_block(from_block)
+            .to_block(to_block)
+            .query_with_meta()
+            .await
+            .context("Read ZRP events")
+    }
+
+    /// Decodes a log emitted by the contract, e.g. from
+    /// [ChainClient::subscribe_logs].
+    pub fn decode_event(log: &Log) -> Result<ZrpEvents> {
+        Ok(ZrpEvents::decode_log(&log.clone().into())?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ethers::prelude::*;
+
+    use super::{zrp_claim_id, zrp_deposit_id, zrp_id_of};
+
+    #[test]
+    fn test_zrp_keys() {
+        let id = zrp_id_of("bob@email.com");
+        let depositor = Address::repeat_byte(0x11);
+        let token = Address::repeat_byte(0x22);
+
+        // `abi.encodePacked(address(0), "claim", id, address(0), uint256(0))`.
+        let mut packed = vec![0u8; 20];
+        packed.extend_from_slice(b"claim");
+        packed.extend_from_slice(&id);
+        packed.extend_from_slice(&[0u8; 52]);
+        assert_eq!(
+            zrp_claim_id(&id, Address::zero()),
+            ethers::utils::keccak256(packed)
+        );
+
+        assert_ne!(zrp_claim_id(&id, Address::zero()), zrp_claim_id(&id, token));
+        assert_ne!(
+            zrp_deposit_id(depositor, &id, token, U256::zero()),
+            zrp_deposit_id(depositor, &id, token, U256::one())
+        );
+        assert_ne!(
+            zrp_deposit_id(depositor, &id, token, U256::zero()),
+            zrp_deposit_id(Address::zero(), &id, token, U256::zero())
+        );
+    }
+}
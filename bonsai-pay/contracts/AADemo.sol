@@ -18,11 +18,19 @@ pragma solidity ^0.8.20;
 
 import {IRiscZeroVerifier} from "risc0/IRiscZeroVerifier.sol";
 import {ImageID} from "./ImageID.sol";
+import {Journal} from "./Journal.sol";
+import {IAccount, UserOperation} from "./UserOperation.sol";
 
-contract AADemo {
+contract AADemo is IAccount {
     IRiscZeroVerifier public immutable verifier;
     bytes32 public constant imageId = ImageID.JWT_VALIDATOR_ID;
+    /// ERC-4337 EntryPoint v0.6, deployed at the same address on every chain.
+    address public constant entryPoint = 0x5FF137D4b0FDCD49DcA30c7CF57E578a026d2789;
     bytes32  public  owner;
+    /// Number of calls made by the account, committed in the journal of the next one.
+    uint256 public nonce;
+
+    uint256 internal constant SIG_VALIDATION_FAILED = 1;
 
     event DebugSeal(bytes32 claimId, bytes32 postStateDigest, bytes seal);
     event Debugfunc(address dest, uint256 value, bytes  func);
@@ -35,13 +43,14 @@ contract AADemo {
 
 
     /**
-     * execute a transaction (called directly from owner, or by entryPoint)
-     * @param _owner email of owner
- 
+     * set the owner of the account, once
+     * @param _owner claim ID of the owner's email
+     * @dev accounts created by AADemoFactory are initialized in their deployment transaction
      */
 
     function setOwner(bytes32 _owner) public {
     if (_owner == bytes32(0)) revert InvalidClaim("Empty owner");
+    if (owner != bytes32(0)) revert InvalidClaim("Owner already set");
 
     owner = _owner;
 
@@ -57,18 +66,89 @@ contract AADemo {
      * @param dest destination address to call
      * @param value the value to pass in this call
      * @param func the calldata to pass in this call
+     * @dev the journal commits to the whole call and to the current nonce, which is then
+     * incremented, so a proof is only good for one call
      */
 
     function execute(address dest, uint256 value, bytes calldata func,  bytes32 claimId, bytes32 postStateDigest, bytes calldata seal) external {
         require(claimId == owner, "You are not the owner");
         emit DebugSeal(claimId, postStateDigest, seal);
-        if (!verifier.verify(seal, imageId, postStateDigest, sha256(abi.encode(dest, claimId)))) {
+        if (!verifier.verify(seal, imageId, postStateDigest, Journal.digest(dest, claimId, value, keccak256(func), nonce))) {
             revert InvalidClaim("Invalid proof");
     }
+        nonce++;
         emit Debugfunc(dest,value,func);
         _call(dest, value, func);
     }
 
+    /**
+     * validate a user operation (called by entryPoint)
+     * @dev the signature is abi.encode(claimId, postStateDigest, seal), the proof that the owner
+     * allowed the call of userOp.callData, which must call executeUserOp, in the operation of hash
+     * userOpHash and with the current nonce, incremented once the operation is validated
+     */
+    function validateUserOp(UserOperation calldata userOp, bytes32 userOpHash, uint256 missingAccountFunds)
+        external
+        returns (uint256 validationData)
+    {
+        require(msg.sender == entryPoint, "Not the entry point");
+        validationData = _validateProof(userOp, userOpHash);
+        if (validationData == 0) {
+            nonce++;
+        }
+        if (missingAccountFunds > 0) {
+            // The entry point checks the deposit, so the result can be ignored.
+            (bool success,) = payable(msg.sender).call{value: missingAccountFunds}("");
+            (success);
+        }
+    }
+
+    /**
+     * execute a call of a validated user operation (called by entryPoint)
+     * @param dest destination address to call
+     * @param value the value to pass in this call
+     * @param func the calldata to pass in this call
+     */
+    function executeUserOp(address dest, uint256 value, bytes calldata func) external {
+        require(msg.sender == entryPoint, "Not the entry point");
+        emit Debugfunc(dest, value, func);
+        _call(dest, value, func);
+    }
+
+    /**
+     * execute a batch of calls, reverting if any of them fails (called by the account itself)
+     * @param dests destination addresses to call
+     * @param values the values to pass in these calls
+     * @param funcs the calldata to pass in these calls
+     * @dev a batch is made with execute or executeUserOp calling the account, so the journal
+     * commits to the whole batch through the hash of its calldata
+     */
+    function executeBatch(address[] calldata dests, uint256[] calldata values, bytes[] calldata funcs) external {
+        require(msg.sender == address(this), "Not the account");
+        require(dests.length == values.length && dests.length == funcs.length, "Invalid batch");
+        for (uint256 i = 0; i < dests.length; i++) {
+            emit Debugfunc(dests[i], values[i], funcs[i]);
+            _call(dests[i], values[i], funcs[i]);
+        }
+    }
+
+    function _validateProof(UserOperation calldata userOp, bytes32 userOpHash) internal view returns (uint256) {
+        if (userOp.callData.length < 4 || bytes4(userOp.callData[:4]) != this.executeUserOp.selector) {
+            return SIG_VALIDATION_FAILED;
+        }
+        (address dest, uint256 value, bytes memory func) = abi.decode(userOp.callData[4:], (address, uint256, bytes));
+        (bytes32 claimId, bytes32 postStateDigest, bytes memory seal) =
+            abi.decode(userOp.signature, (bytes32, bytes32, bytes));
+        if (claimId != owner) {
+            return SIG_VALIDATION_FAILED;
+        }
+        bytes32 journalDigest = Journal.digest(dest, claimId, value, keccak256(func), nonce, userOpHash);
+        if (!verifier.verify(seal, imageId, postStateDigest, journalDigest)) {
+            return SIG_VALIDATION_FAILED;
+        }
+        return 0;
+    }
+
      function _call(address target, uint256 value, bytes memory data) internal {
         (bool success, bytes memory result) = target.call{value: value}(data);
         if (!success) {
@@ -0,0 +1,57 @@
+// Copyright 2024 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+pragma solidity ^0.8.20;
+
+import {Clones} from "openzeppelin/proxy/Clones.sol";
+import {IRiscZeroVerifier} from "risc0/IRiscZeroVerifier.sol";
+import {AADemo} from "./AADemo.sol";
+
+/// @notice Deploys AADemo accounts at addresses derived from the claim ID of
+///         their owner, so they can receive funds before being deployed.
+/// @dev Accounts are ERC-1167 clones of `implementation`, created with
+///      CREATE2 and the claim ID as salt. The owner is set in the same
+///      transaction, so it cannot be front-run, and anyone may create an
+///      account since its owner only depends on its address.
+contract AADemoFactory {
+    AADemo public immutable implementation;
+    /// @notice Owner of `implementation`, which no claim ID can match, so the
+    ///         implementation itself cannot be taken over.
+    bytes32 public constant LOCKED_OWNER = bytes32(uint256(1));
+
+    event AccountCreated(bytes32 indexed claimId, address account);
+
+    constructor(IRiscZeroVerifier verifier) {
+        implementation = new AADemo(verifier);
+        implementation.setOwner(LOCKED_OWNER);
+    }
+
+    /// @notice Returns the address of the account of `claimId`, deployed or not.
+    function getAddress(bytes32 claimId) public view returns (address) {
+        return Clones.predictDeterministicAddress(address(implementation), claimId);
+    }
+
+    /// @notice Deploys the account of `claimId`, or returns it if it is already deployed.
+    function createAccount(bytes32 claimId) external returns (AADemo account) {
+        address predicted = getAddress(claimId);
+        if (predicted.code.length > 0) {
+            return AADemo(payable(predicted));
+        }
+
+        account = AADemo(payable(Clones.cloneDeterministic(address(implementation), claimId)));
+        account.setOwner(claimId);
+        emit AccountCreated(claimId, address(account));
+    }
+}
@@ -17,6 +17,7 @@ pragma solidity ^0.8.20;
 
 import {IRiscZeroVerifier} from "risc0/IRiscZeroVerifier.sol";
 import {ImageID} from "./ImageID.sol";
+import {Journal} from "./Journal.sol";
 
 contract BonsaiPay {
     IRiscZeroVerifier public immutable verifier;
@@ -65,7 +66,7 @@ contract BonsaiPay {
         if (to == address(0)) revert InvalidClaim("Invalid recipient address");
         if (claimId == bytes32(0)) revert InvalidClaim("Empty claimId");
         emit DebugSeal(claimId, postStateDigest, seal);
-        if (!verifier.verify(seal, imageId, postStateDigest, sha256(abi.encode(to, claimId)))) {
+        if (!verifier.verify(seal, imageId, postStateDigest, Journal.digest(to, claimId))) {
             revert InvalidClaim("Invalid proof");
         }
 
@@ -83,7 +84,7 @@ contract BonsaiPay {
     function executeCall(address payable _to, bytes32 claimId, bytes32 postStateDigest, bytes calldata seal) public payable {
 
         emit DebugSeal(claimId, postStateDigest, seal);
-        if (!verifier.verify(seal, imageId, postStateDigest, sha256(abi.encode(_to, claimId)))) {
+        if (!verifier.verify(seal, imageId, postStateDigest, Journal.digest(_to, claimId))) {
             revert InvalidClaim("Invalid proof");
         }
         (bool sent, bytes memory data) = _to.call{value: msg.value}("");
@@ -12,7 +12,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::{collections::HashMap, env};
+use std::{collections::HashMap, env, fs};
 
 use risc0_build::{embed_methods_with_options, DockerOptions, GuestOptions, GuestListEntry};
 use risc0_build_ethereum::generate_solidity_files;
@@ -20,6 +20,7 @@ use risc0_build_ethereum::generate_solidity_files;
 // Paths where the generated Solidity files will be written.
 const SOLIDITY_IMAGE_ID_PATH: &str = "../contracts/ImageID.sol";
 const SOLIDITY_ELF_PATH: &str = "../tests/Elf.sol";
+const SOLIDITY_JOURNAL_PATH: &str = "../contracts/Journal.sol";
 
 fn main() {
     // Builds can be made deterministic, and thereby reproducible, by using Docker to build the
@@ -43,4 +44,12 @@ fn main() {
         .with_elf_sol_path(SOLIDITY_ELF_PATH);
 
     generate_solidity_files(guests.as_slice(), &solidity_opts).unwrap();
+
+    // Generate the Solidity journal library, so the contracts always hash the
+    // same journal layout as the one committed by the guest.
+    fs::write(
+        SOLIDITY_JOURNAL_PATH,
+        bonsai_pay_types::solidity_journal_library(),
+    )
+    .unwrap();
 }
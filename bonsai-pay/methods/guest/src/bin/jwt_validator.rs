@@ -12,42 +12,40 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use alloy_primitives::{Address, FixedBytes};
-use alloy_sol_types::SolValue;
-use oidc_validator::IdentityProvider;
+use alloy_primitives::FixedBytes;
+use bonsai_pay_types::{ClaimsData, FailureData};
+use oidc_validator::{input::GuestInput, OidcErr};
 use risc0_zkvm::guest::env;
 use risc0_zkvm::sha::rust_crypto::{Digest as _, Sha256};
 use std::io::Read;
 
-alloy_sol_types::sol! {
-    struct ClaimsData {
-        address msg_sender;
-        bytes32 claim_id;
-    }
-    struct Input {
-        uint256 identity_provider;
-        string jwt;
-    }
-}
-
 fn main() {
     let mut input_bytes = Vec::<u8>::new();
     env::stdin().read_to_end(&mut input_bytes).unwrap();
 
-    let input: Input = <Input>::abi_decode(&input_bytes, true).unwrap();
+    // Rejected inputs commit a failure journal carrying the error code, so the
+    // host can report why the token was refused instead of an opaque panic.
+    let output = match validate(&input_bytes) {
+        Ok(claims) => claims.encode(),
+        Err(err) => FailureData::new(err.code()).encode(),
+    };
+
+    env::commit_slice(&output);
+}
 
-    let identity_provider: IdentityProvider = input.identity_provider.into();
-    let jwt: String = input.jwt;
+fn validate(input_bytes: &[u8]) -> Result<ClaimsData, OidcErr> {
+    // The input is either compact or ABI encoded, as selected by its first byte.
+    let input = GuestInput::decode(input_bytes)?;
 
-    let (claim_id, msg_sender) = identity_provider.validate(&jwt).unwrap();
-    let msg_sender: Address = Address::parse_checksummed(msg_sender, None).unwrap();
+    let (claim_id, token_nonce) = input.identity_provider.validate(&input.jwt)?;
+    // The nonce of the token is either the hash of the whole call, whose
+    // destination is then the sender, or the address of the sender of a
+    // claim, which makes no call.
+    let msg_sender = input.authorized_sender(&token_nonce)?;
     let claim_id: FixedBytes<32> =
         FixedBytes::from_slice(Sha256::digest(claim_id.as_bytes()).as_slice());
-    let output = ClaimsData {
-        msg_sender,
-        claim_id,
-    };
-    let output = output.abi_encode();
 
-    env::commit_slice(&output);
+    Ok(ClaimsData::new(msg_sender, claim_id)
+        .with_call(input.value, input.func_hash, input.nonce)
+        .with_user_op_hash(input.user_op_hash))
 }
@@ -0,0 +1,46 @@
+// Copyright 2024 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use alloy_primitives::{Address, FixedBytes};
+use bonsai_pay_types::{FailureData, ZrpClaimsData};
+use oidc_validator::{input::GuestInput, OidcErr};
+use risc0_zkvm::guest::env;
+use risc0_zkvm::sha::rust_crypto::{Digest as _, Sha256};
+use std::io::Read;
+
+// Same validation as the `jwt_validator` guest, committing the journal
+// decoded by the `ZRP` contract instead.
+fn main() {
+    let mut input_bytes = Vec::<u8>::new();
+    env::stdin().read_to_end(&mut input_bytes).unwrap();
+
+    let output = match validate(&input_bytes) {
+        Ok(claims) => claims.encode(),
+        Err(err) => FailureData::new(err.code()).encode(),
+    };
+
+    env::commit_slice(&output);
+}
+
+fn validate(input_bytes: &[u8]) -> Result<ZrpClaimsData, OidcErr> {
+    let input = GuestInput::decode(input_bytes)?;
+
+    let (claim_id, beneficiary) = input.identity_provider.validate(&input.jwt)?;
+    let beneficiary: Address =
+        Address::parse_checksummed(beneficiary, None).map_err(|_| OidcErr::NonceParseError)?;
+    let claim_id: FixedBytes<32> =
+        FixedBytes::from_slice(Sha256::digest(claim_id.as_bytes()).as_slice());
+
+    Ok(ZrpClaimsData::new(beneficiary, claim_id))
+}
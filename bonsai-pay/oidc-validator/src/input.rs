@@ -0,0 +1,397 @@
+//! Encoding of the input read by the `jwt_validator` guest.
+//!
+//! The first byte of the input selects its format:
+//!
+//! * `0x00`: ABI encoded `Input { uint256 identity_provider; string jwt;
+//!   address dest; uint256 value; bytes32 func_hash; uint256 nonce;
+//!   bytes32 user_op_hash }`.
+//!   The ABI encoding of a dynamic struct always starts with a zero-padded
+//!   offset word, so requests built on-chain with `abi.encode(input)` keep
+//!   working unchanged. Inputs authorizing no call may also be encoded as
+//!   `ClaimInput { uint256 identity_provider; string jwt }`.
+//! * `0x01`: compact encoding, laid out as
+//!   `[version: u8][identity_provider: u8][jwt_len: u32 LE][jwt: [u8; jwt_len]]`
+//!   followed by the 20-byte `dest`, and the 32-byte big-endian `value`,
+//!   `func_hash`, `nonce` and `user_op_hash`. It avoids the padding of the
+//!   ABI encoding and is cheaper to decode inside the zkVM.
+
+use alloy_primitives::{keccak256, Address, FixedBytes, U256};
+use alloy_sol_types::SolValue;
+
+use crate::{IdentityProvider, OidcErr};
+
+/// Version byte of ABI encoded inputs.
+pub const ABI_INPUT_VERSION: u8 = 0;
+/// Version byte of compact inputs.
+pub const COMPACT_INPUT_VERSION: u8 = 1;
+
+// Size of the compact header: version, identity provider and jwt length.
+const COMPACT_HEADER_LEN: usize = 1 + 1 + 4;
+// Size of the head of an ABI encoded `ClaimInput`, which is the offset of its
+// jwt.
+const CLAIM_INPUT_HEAD_LEN: u64 = 2 * 32;
+// Size of the compact trailer: destination, value, function hash and nonce of
+// the call, and hash of the user operation.
+const COMPACT_CALL_LEN: usize = 20 + 4 * 32;
+
+alloy_sol_types::sol! {
+    /// ABI layout of the guest input, as produced by `abi.encode(input)`.
+    struct Input {
+        uint256 identity_provider;
+        string jwt;
+        address dest;
+        uint256 value;
+        bytes32 func_hash;
+        uint256 nonce;
+        bytes32 user_op_hash;
+    }
+
+    /// ABI layout of an input authorizing no call, as used by `BonsaiPay`
+    /// claims.
+    struct ClaimInput {
+        uint256 identity_provider;
+        string jwt;
+    }
+}
+
+/// Returns the nonce of a token authorizing the call of `dest` sending
+/// `value` with the calldata of hash `func_hash`, as the call number `nonce` of
+/// the account: `keccak256(abi.encode(dest, value, func_hash, nonce))`.
+///
+/// The token carries it as a `0x`-prefixed hex string.
+pub fn call_nonce(
+    dest: Address,
+    value: U256,
+    func_hash: FixedBytes<32>,
+    nonce: U256,
+) -> FixedBytes<32> {
+    keccak256((dest, value, func_hash, nonce).abi_encode())
+}
+
+/// Input of the `jwt_validator` guest.
+///
+/// Besides the token, it holds the call the owner authorizes, committed in
+/// the journal: the destination, the value sent, the keccak256 hash of the
+/// calldata and the nonce of the account, and the hash of the user operation
+/// making it, if any. They are zero when no call is authorized.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GuestInput {
+    pub identity_provider: IdentityProvider,
+    pub jwt: String,
+    pub dest: Address,
+    pub value: U256,
+    pub func_hash: FixedBytes<32>,
+    pub nonce: U256,
+    pub user_op_hash: FixedBytes<32>,
+}
+
+impl GuestInput {
+    pub fn new(identity_provider: IdentityProvider, jwt: impl Into<String>) -> Self {
+        Self {
+            identity_provider,
+            jwt: jwt.into(),
+            dest: Address::ZERO,
+            value: U256::ZERO,
+            func_hash: FixedBytes::ZERO,
+            nonce: U256::ZERO,
+            user_op_hash: FixedBytes::ZERO,
+        }
+    }
+
+    /// Authorizes the call of `dest` sending `value` with the calldata of
+    /// hash `func_hash`, as the call number `nonce` of the account.
+    pub fn with_call(
+        mut self,
+        dest: Address,
+        value: U256,
+        func_hash: FixedBytes<32>,
+        nonce: U256,
+    ) -> Self {
+        self.dest = dest;
+        self.value = value;
+        self.func_hash = func_hash;
+        self.nonce = nonce;
+        self
+    }
+
+    /// Binds the proof to the user operation of hash `user_op_hash`, which
+    /// makes the call instead of `execute`.
+    pub fn with_user_op_hash(mut self, user_op_hash: FixedBytes<32>) -> Self {
+        self.user_op_hash = user_op_hash;
+        self
+    }
+
+    /// The [call_nonce] of the call of the input.
+    pub fn call_nonce(&self) -> FixedBytes<32> {
+        call_nonce(self.dest, self.value, self.func_hash, self.nonce)
+    }
+
+    /// Returns the address authorized by a token of nonce `token_nonce`,
+    /// which the guest commits as the `msg_sender` of the journal.
+    ///
+    /// A token authorizing a call carries the [call_nonce] of the whole call,
+    /// and authorizes its destination. A token carrying a checksummed address
+    /// authorizes that address, and no call, as for `BonsaiPay` claims.
+    pub fn authorized_sender(&self, token_nonce: &str) -> Result<Address, OidcErr> {
+        if let Ok(call_nonce) = token_nonce.parse::<FixedBytes<32>>() {
+            if call_nonce != self.call_nonce() {
+                return Err(OidcErr::CallMismatchError);
+            }
+            return Ok(self.dest);
+        }
+
+        let sender =
+            Address::parse_checksummed(token_nonce, None).map_err(|_| OidcErr::NonceParseError)?;
+        if self.has_call() {
+            return Err(OidcErr::CallMismatchError);
+        }
+        Ok(sender)
+    }
+
+    fn has_call(&self) -> bool {
+        !self.dest.is_zero()
+            || !self.value.is_zero()
+            || !self.func_hash.is_zero()
+            || !self.nonce.is_zero()
+            || !self.user_op_hash.is_zero()
+    }
+
+    /// Encodes the input with the compact, length-prefixed format, the call
+    /// following the jwt.
+    pub fn encode_compact(&self) -> Vec<u8> {
+        let jwt = self.jwt.as_bytes();
+        let mut buf = Vec::with_capacity(COMPACT_HEADER_LEN + jwt.len() + COMPACT_CALL_LEN);
+        buf.push(COMPACT_INPUT_VERSION);
+        buf.push(self.identity_provider.id());
+        buf.extend_from_slice(&(jwt.len() as u32).to_le_bytes());
+        buf.extend_from_slice(jwt);
+        buf.extend_from_slice(self.dest.as_slice());
+        buf.extend_from_slice(&self.value.to_be_bytes::<32>());
+        buf.extend_from_slice(self.func_hash.as_slice());
+        buf.extend_from_slice(&self.nonce.to_be_bytes::<32>());
+        buf.extend_from_slice(self.user_op_hash.as_slice());
+        buf
+    }
+
+    /// Encodes the input as `abi.encode(Input)`.
+    pub fn encode_abi(&self) -> Vec<u8> {
+        Input {
+            identity_provider: U256::from(self.identity_provider.id()),
+            jwt: self.jwt.clone(),
+            dest: self.dest,
+            value: self.value,
+            func_hash: self.func_hash,
+            nonce: self.nonce,
+            user_op_hash: self.user_op_hash,
+        }
+        .abi_encode()
+    }
+
+    /// Decodes an input in either format, based on its version byte.
+    pub fn decode(bytes: &[u8]) -> Result<Self, OidcErr> {
+        match bytes.first() {
+            Some(&ABI_INPUT_VERSION) => Self::decode_abi(bytes),
+            Some(&COMPACT_INPUT_VERSION) => Self::decode_compact(bytes),
+            Some(_) => Err(OidcErr::InputVersionError),
+            None => Err(OidcErr::InputDecodeError),
+        }
+    }
+
+    fn decode_abi(bytes: &[u8]) -> Result<Self, OidcErr> {
+        // The offset of the jwt, after the offset of the struct and the
+        // identity provider, tells the two layouts apart.
+        let jwt_offset = bytes.get(64..96).ok_or(OidcErr::InputDecodeError)?;
+        if U256::from_be_slice(jwt_offset) == U256::from(CLAIM_INPUT_HEAD_LEN) {
+            let input =
+                ClaimInput::abi_decode(bytes, true).map_err(|_| OidcErr::InputDecodeError)?;
+            return Ok(Self::new(input.identity_provider.try_into()?, input.jwt));
+        }
+
+        let input = Input::abi_decode(bytes, true).map_err(|_| OidcErr::InputDecodeError)?;
+        Ok(Self {
+            identity_provider: input.identity_provider.try_into()?,
+            jwt: input.jwt,
+            dest: input.dest,
+            value: input.value,
+            func_hash: input.func_hash,
+            nonce: input.nonce,
+            user_op_hash: input.user_op_hash,
+        })
+    }
+
+    fn decode_compact(bytes: &[u8]) -> Result<Self, OidcErr> {
+        if bytes.len() < COMPACT_HEADER_LEN {
+            return Err(OidcErr::InputDecodeError);
+        }
+        let identity_provider = IdentityProvider::try_from(bytes[1])?;
+        let jwt_len = u32::from_le_bytes(bytes[2..6].try_into().unwrap()) as usize;
+        let rest = &bytes[COMPACT_HEADER_LEN..];
+        if rest.len() != jwt_len + COMPACT_CALL_LEN {
+            return Err(OidcErr::InputDecodeError);
+        }
+        let (jwt, call) = rest.split_at(jwt_len);
+        let jwt = core::str::from_utf8(jwt).map_err(|_| OidcErr::InputDecodeError)?;
+
+        let (dest, call) = call.split_at(20);
+
+        Ok(Self::new(identity_provider, jwt)
+            .with_call(
+                Address::from_slice(dest),
+                U256::from_be_slice(&call[..32]),
+                FixedBytes::from_slice(&call[32..64]),
+                U256::from_be_slice(&call[64..96]),
+            )
+            .with_user_op_hash(FixedBytes::from_slice(&call[96..])))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloy_primitives::{keccak256, Address, FixedBytes, U256};
+
+    use alloy_sol_types::SolValue;
+
+    use super::{ClaimInput, GuestInput, COMPACT_INPUT_VERSION};
+    use crate::{IdentityProvider, OidcErr};
+
+    fn test_input(identity_provider: IdentityProvider) -> GuestInput {
+        GuestInput::new(identity_provider, "header.payload.signature")
+            .with_call(
+                Address::repeat_byte(0x11),
+                U256::from(1000),
+                FixedBytes::repeat_byte(0x22),
+                U256::from(3),
+            )
+            .with_user_op_hash(FixedBytes::repeat_byte(0x44))
+    }
+
+    #[test]
+    fn test_compact_round_trip() {
+        let input = test_input(IdentityProvider::Test);
+        let encoded = input.encode_compact();
+
+        assert_eq!(encoded[0], COMPACT_INPUT_VERSION);
+        assert_eq!(encoded.len(), 6 + input.jwt.len() + 20 + 4 * 32);
+        assert_eq!(GuestInput::decode(&encoded).unwrap(), input);
+    }
+
+    #[test]
+    fn test_abi_round_trip() {
+        let input = test_input(IdentityProvider::Google);
+        let encoded = input.encode_abi();
+
+        assert_eq!(encoded[0], 0);
+        assert_eq!(GuestInput::decode(&encoded).unwrap(), input);
+    }
+
+    #[test]
+    fn test_abi_claim_input() {
+        let encoded = ClaimInput {
+            identity_provider: U256::from(IdentityProvider::Google.id()),
+            jwt: "header.payload.signature".to_string(),
+        }
+        .abi_encode();
+
+        assert_eq!(
+            GuestInput::decode(&encoded).unwrap(),
+            GuestInput::new(IdentityProvider::Google, "header.payload.signature")
+        );
+    }
+
+    #[test]
+    fn test_no_call() {
+        let input = GuestInput::new(IdentityProvider::Test, "header.payload.signature");
+        let decoded = GuestInput::decode(&input.encode_compact()).unwrap();
+
+        assert_eq!(decoded.dest, Address::ZERO);
+        assert_eq!(decoded.value, U256::ZERO);
+        assert_eq!(decoded.func_hash, FixedBytes::ZERO);
+        assert_eq!(decoded.nonce, U256::ZERO);
+        assert_eq!(decoded.user_op_hash, FixedBytes::ZERO);
+    }
+
+    #[test]
+    fn test_reject_truncated_compact_input() {
+        let input = GuestInput::new(IdentityProvider::Test, "header.payload.signature");
+        let encoded = input.encode_compact();
+
+        assert!(GuestInput::decode(&encoded[..encoded.len() - 1]).is_err());
+        assert!(GuestInput::decode(&[0xff]).is_err());
+    }
+
+    #[test]
+    fn test_reject_unknown_identity_provider() {
+        let mut compact =
+            GuestInput::new(IdentityProvider::Test, "header.payload.signature").encode_compact();
+        compact[1] = 2;
+        assert_eq!(GuestInput::decode(&compact), Err(OidcErr::InputDecodeError));
+
+        let mut abi =
+            GuestInput::new(IdentityProvider::Google, "header.payload.signature").encode_abi();
+        // The identity provider is the second word, after the struct offset.
+        abi[63] = 2;
+        assert_eq!(GuestInput::decode(&abi), Err(OidcErr::InputDecodeError));
+    }
+
+    #[test]
+    fn test_call_nonce() {
+        let input = test_input(IdentityProvider::Test);
+        let encoded = (
+            Address::repeat_byte(0x11),
+            U256::from(1000),
+            FixedBytes::<32>::repeat_byte(0x22),
+            U256::from(3),
+        )
+            .abi_encode();
+
+        assert_eq!(encoded.len(), 4 * 32);
+        assert_eq!(input.call_nonce(), keccak256(encoded));
+    }
+
+    #[test]
+    fn test_token_authorizes_call() {
+        let input = test_input(IdentityProvider::Test);
+        let nonce = input.call_nonce().to_string();
+        assert_eq!(nonce.len(), 2 + 64);
+        assert_eq!(
+            input.authorized_sender(&nonce),
+            Ok(Address::repeat_byte(0x11))
+        );
+
+        // Any other destination, value, calldata or account nonce is refused.
+        let (dest, value, func_hash, account_nonce) =
+            (input.dest, input.value, input.func_hash, input.nonce);
+        let others = [
+            (Address::repeat_byte(0x12), value, func_hash, account_nonce),
+            (dest, U256::from(1001), func_hash, account_nonce),
+            (dest, value, FixedBytes::repeat_byte(0x23), account_nonce),
+            (dest, value, func_hash, U256::from(4)),
+        ];
+        for (dest, value, func_hash, account_nonce) in others {
+            let other = input
+                .clone()
+                .with_call(dest, value, func_hash, account_nonce);
+            assert_eq!(
+                other.authorized_sender(&nonce),
+                Err(OidcErr::CallMismatchError)
+            );
+        }
+    }
+
+    #[test]
+    fn test_address_token_authorizes_no_call() {
+        let sender = "0x23D4a8d26B777c1FDcBB74afa79CAdA1caF772F8";
+        let claim = GuestInput::new(IdentityProvider::Test, "header.payload.signature");
+        assert_eq!(claim.authorized_sender(sender), Ok(sender.parse().unwrap()));
+
+        let call = test_input(IdentityProvider::Test);
+        assert_eq!(
+            call.authorized_sender(sender),
+            Err(OidcErr::CallMismatchError)
+        );
+        assert_eq!(
+            claim.authorized_sender("0x23d4a8d26b777c1fdcbb74afa79cada1caf772f8"),
+            Err(OidcErr::NonceParseError)
+        );
+    }
+}
@@ -22,6 +22,7 @@ import {IRiscZeroVerifier} from "risc0/IRiscZeroVerifier.sol";
 import {ControlID, RiscZeroGroth16Verifier} from "risc0/groth16/RiscZeroGroth16Verifier.sol";
 
 import {AADemo} from "../contracts/AADemo.sol";
+import {AADemoFactory} from "../contracts/AADemoFactory.sol";
 
 /// @notice Deployment script for the RISC Zero starter project.
 /// @dev Use the following environment variable to control the deployment:
@@ -42,6 +43,9 @@ contract AADemoDeploy is Script {
         AADemo aaDemo = new AADemo(verifier);
         console2.log("Deployed AADemo to", address(aaDemo));
 
+        AADemoFactory factory = new AADemoFactory(verifier);
+        console2.log("Deployed AADemoFactory to", address(factory));
+
         vm.stopBroadcast();
     }
 }
@@ -16,28 +16,42 @@
 
 pragma solidity ^0.8.20;
 
-import {RiscZeroCheats} from "risc0/RiscZeroCheats.sol";
 import {console2} from "forge-std/console2.sol";
 import {Test} from "forge-std/Test.sol";
 import {IRiscZeroVerifier} from "risc0/IRiscZeroVerifier.sol";
 import {AADemo} from "../contracts/AADemo.sol";
+import {Journal} from "../contracts/Journal.sol"; // auto-generated contract after running `cargo build`.
+import {UserOperation} from "../contracts/UserOperation.sol";
 import "forge-std/console.sol";
-import {Elf} from "./Elf.sol"; // auto-generated contract after running `cargo build`.
 
-contract AADemoTest is RiscZeroCheats, Test {
+/// Verifier accepting the seals of `JournalVerifier.seal`. The guest only proves a call for a
+/// token whose nonce commits to it, and such a token cannot be issued here.
+contract JournalVerifier {
+    function seal(bytes32 imageId, bytes32 journalDigest) public pure returns (bytes memory) {
+        return abi.encode(imageId, journalDigest);
+    }
+
+    function verify(bytes calldata seal_, bytes32 imageId, bytes32, bytes32 journalDigest)
+        external
+        pure
+        returns (bool)
+    {
+        return keccak256(seal_) == keccak256(seal(imageId, journalDigest));
+    }
+}
+
+contract AADemoTest is Test {
     AADemo public aaDemo;
     address public alice = makeAddr("alice");
     address public bob = makeAddr("bob");
     address public charlie = makeAddr("charlie");
 
-    struct Input {
-        uint256 id_provider;
-        string jwt;
-    }
+    address public constant jwtNonce = 0x23D4a8d26B777c1FDcBB74afa79CAdA1caF772F8;
+
+    JournalVerifier public verifier = new JournalVerifier();
 
     function setUp() public {
-        IRiscZeroVerifier verifier = deployRiscZeroVerifier();
-        aaDemo = new AADemo(verifier);
+        aaDemo = new AADemo(IRiscZeroVerifier(address(verifier)));
 
         // fund alice and bob and charlie
         vm.deal(alice, 5 ether);
@@ -54,33 +68,189 @@ contract AADemoTest is RiscZeroCheats, Test {
         assertEq(aaDemo.owner() , claimId);
     }
 
-    function test_Execute() public {
-        // deposit as alice
+    function test_SetOwnerOnce() public {
+        bytes32 claimId = sha256(abi.encodePacked("bob@email.com"));
+        aaDemo.setOwner(claimId);
+
+        vm.prank(alice);
+        vm.expectRevert(abi.encodeWithSelector(AADemo.InvalidClaim.selector, "Owner already set"));
+        aaDemo.setOwner(sha256(abi.encodePacked("alice@email.com")));
+        assertEq(aaDemo.owner(), claimId);
+    }
+
+    function userOp(bytes32 claimId, address dest) internal view returns (UserOperation memory) {
+        return UserOperation({
+            sender: address(aaDemo),
+            nonce: 0,
+            initCode: "",
+            callData: abi.encodeCall(AADemo.executeUserOp, (dest, 1 ether, "")),
+            callGasLimit: 100000,
+            verificationGasLimit: 1000000,
+            preVerificationGas: 50000,
+            maxFeePerGas: 1 gwei,
+            maxPriorityFeePerGas: 1 gwei,
+            paymasterAndData: "",
+            signature: abi.encode(claimId, bytes32(0), bytes(""))
+        });
+    }
+
+    function test_UserOpOnlyFromEntryPoint() public {
+        bytes32 claimId = sha256(abi.encodePacked("bob@email.com"));
+        aaDemo.setOwner(claimId);
+
+        vm.prank(alice);
+        vm.expectRevert("Not the entry point");
+        aaDemo.validateUserOp(userOp(claimId, bob), bytes32(0), 0);
+
+        vm.prank(alice);
+        vm.expectRevert("Not the entry point");
+        aaDemo.executeUserOp(bob, 1 ether, "");
+    }
+
+    function test_ValidateUserOpOfOtherOwner() public {
+        bytes32 claimId = sha256(abi.encodePacked("bob@email.com"));
+        aaDemo.setOwner(claimId);
+        address entryPoint = aaDemo.entryPoint();
+
+        // A proof for another email fails validation, and the prefund is still paid.
+        bytes32 otherClaimId = sha256(abi.encodePacked("alice@email.com"));
+        vm.prank(entryPoint);
+        assertEq(aaDemo.validateUserOp(userOp(otherClaimId, bob), bytes32(0), 1 gwei), 1);
+        assertEq(entryPoint.balance, 1 gwei);
+
+        // So does a user operation not calling executeUserOp.
+        UserOperation memory op = userOp(claimId, bob);
+        op.callData = abi.encodeCall(AADemo.setOwner, (claimId));
+        vm.prank(entryPoint);
+        assertEq(aaDemo.validateUserOp(op, bytes32(0), 0), 1);
+    }
+
+    function test_ExecuteUserOp() public {
+        vm.prank(aaDemo.entryPoint());
+        aaDemo.executeUserOp(bob, 1 ether, "");
+        assertEq(bob.balance, 6 ether);
+        assertEq(address(aaDemo).balance, 4 ether);
+    }
+
+    /// Seals the journal authorizing the owner of `claimId` to call `jwtNonce` sending `value` with
+    /// `func`, as the call number `nonce` of the account, made by `execute`.
+    function sealCall(bytes32 claimId, uint256 value, bytes memory func, uint256 nonce)
+        internal
+        view
+        returns (bytes32 postStateDigest, bytes memory seal)
+    {
+        return sealCall(claimId, value, func, nonce, bytes32(0));
+    }
+
+    /// Seals the same call, made by the user operation of hash `userOpHash`.
+    function sealCall(bytes32 claimId, uint256 value, bytes memory func, uint256 nonce, bytes32 userOpHash)
+        internal
+        view
+        returns (bytes32 postStateDigest, bytes memory seal)
+    {
+        bytes32 journalDigest = Journal.digest(jwtNonce, claimId, value, keccak256(func), nonce, userOpHash);
+        return (bytes32(0), verifier.seal(aaDemo.imageId(), journalDigest));
+    }
+
+    function test_ExecuteBatch() public {
         bytes32 claimId = sha256(abi.encodePacked("johnkenny6799@gmail.com"));
+        aaDemo.setOwner(claimId);
+        address[] memory dests = new address[](2);
+        (dests[0], dests[1]) = (bob, charlie);
+        uint256[] memory values = new uint256[](2);
+        (values[0], values[1]) = (1 ether, 2 ether);
+        bytes[] memory funcs = new bytes[](2);
+        bytes memory batch = abi.encodeCall(AADemo.executeBatch, (dests, values, funcs));
+
+        // The batch is a call of the account, committing to all the batched calls.
+        bytes32 journalDigest = Journal.digest(address(aaDemo), claimId, 0, keccak256(batch), 0);
+        bytes memory seal = verifier.seal(aaDemo.imageId(), journalDigest);
+        values[1] = 3 ether;
+        bytes memory otherBatch = abi.encodeCall(AADemo.executeBatch, (dests, values, funcs));
+        vm.prank(alice);
+        vm.expectRevert(abi.encodeWithSelector(AADemo.InvalidClaim.selector, "Invalid proof"));
+        aaDemo.execute(address(aaDemo), 0, otherBatch, claimId, bytes32(0), seal);
+
         vm.prank(alice);
+        aaDemo.execute(address(aaDemo), 0, batch, claimId, bytes32(0), seal);
+        assertEq(bob.balance, 6 ether);
+        assertEq(charlie.balance, 7 ether);
+        assertEq(address(aaDemo).balance, 2 ether);
+        assertEq(aaDemo.nonce(), 1);
+    }
+
+    function test_ExecuteBatchOnlyFromAccount() public {
+        vm.prank(alice);
+        vm.expectRevert("Not the account");
+        aaDemo.executeBatch(new address[](1), new uint256[](1), new bytes[](1));
+    }
 
-        // claim as bob
+    function test_ValidateUserOp() public {
+        bytes32 claimId = sha256(abi.encodePacked("johnkenny6799@gmail.com"));
+        aaDemo.setOwner(claimId);
+        address entryPoint = aaDemo.entryPoint();
+        bytes32 userOpHash = keccak256("user operation");
+        (bytes32 postStateDigest, bytes memory seal) = sealCall(claimId, 1 ether, "", 0, userOpHash);
 
-        string memory jwt ="eyJhbGciOiJSUzI1NiIsImtpZCI6IjNkNTgwZjBhZjdhY2U2OThhMGNlZTdmMjMwYmNhNTk0ZGM2ZGJiNTUiLCJ0eXAiOiJKV1QifQ.eyJpc3MiOiJodHRwczovL2FjY291bnRzLmdvb2dsZS5jb20iLCJhenAiOiIyODAzNzI3MzkzNjgtcXY0YnJ2YTBlaXEwdjFvbzFqdHNxZGFwaDZtdjdvbW8uYXBwcy5nb29nbGV1c2VyY29udGVudC5jb20iLCJhdWQiOiIyODAzNzI3MzkzNjgtcXY0YnJ2YTBlaXEwdjFvbzFqdHNxZGFwaDZtdjdvbW8uYXBwcy5nb29nbGV1c2VyY29udGVudC5jb20iLCJzdWIiOiIxMTc3MzYzNTE4MjIzNTY1NTc3NDkiLCJlbWFpbCI6ImpvaG5rZW5ueTY3OTlAZ21haWwuY29tIiwiZW1haWxfdmVyaWZpZWQiOnRydWUsIm5vbmNlIjoiMHgyM0Q0YThkMjZCNzc3YzFGRGNCQjc0YWZhNzlDQWRBMWNhRjc3MkY4IiwibmJmIjoxNzE4NjMyMTIwLCJuYW1lIjoiSm9obiBLZW5ueSIsInBpY3R1cmUiOiJodHRwczovL2xoMy5nb29nbGV1c2VyY29udGVudC5jb20vYS9BQ2c4b2NKdHczTGFqNXdUNUN4QjV2ZzJySjJkSnlHWWpTX29MaXliMEkzTDIwTmJFeHBBdXc9czk2LWMiLCJnaXZlbl9uYW1lIjoiSm9obiIsImZhbWlseV9uYW1lIjoiS2VubnkiLCJpYXQiOjE3MTg2MzI0MjAsImV4cCI6MTcxODYzNjAyMCwianRpIjoiZDg5MmRlZjdmMjZlYWMxZGJiZDU4YzI2ZTc1MDI0NTU2YzI1NGVlZSJ9.Rj0phisb3nb-fkXABQaksABIMsyvAAyfHCeyNekAHFlY4HTDNwpiud5qdGR5u3dEZ5Y_-CdU6eFXWVQ-ry8EiDhK8267j8jcIcb60unWQnDR7e2MYJBwgdZh0f8teru4FYIVXW88-PRDdT3WuurQOXyUxrS1gsq0EZH580HAl98b0RdmrXcRolKGv5f9gzk9cZApvZomUydr2SRqAvhU5Sxbg18uHjYU2q1LO8uJSlKyC8xx-J6gZEf3c-izYmeSsSp92XrPMIOeTfWtr-QcVimPnOl3g5U7Jld7UU7FIl-WBvkCH4jzJpFytwTJZh36hV6hXL78sADkKQMjCp7IQA";
-        uint256 id_provider = 1;
+        UserOperation memory op = userOp(claimId, jwtNonce);
+        op.signature = abi.encode(claimId, postStateDigest, seal);
 
-        Input memory input = Input({id_provider: id_provider, jwt: jwt});
+        // The proof is bound to the hash of the operation.
+        vm.prank(entryPoint);
+        assertEq(aaDemo.validateUserOp(op, keccak256("other operation"), 0), 1);
+        assertEq(aaDemo.nonce(), 0);
 
-        (bytes memory journal, bytes32 post_state_digest, bytes memory seal) = prove(Elf.JWT_VALIDATOR_PATH, abi.encode(input));
+        vm.prank(entryPoint);
+        assertEq(aaDemo.validateUserOp(op, userOpHash, 0), 0);
+        assertEq(aaDemo.nonce(), 1);
+
+        // And cannot be used by execute.
+        (postStateDigest, seal) = sealCall(claimId, 1 ether, "", 1, userOpHash);
+        vm.prank(alice);
+        vm.expectRevert(abi.encodeWithSelector(AADemo.InvalidClaim.selector, "Invalid proof"));
+        aaDemo.execute(payable(jwtNonce), 1 ether, "", claimId, postStateDigest, seal);
+    }
 
-        bytes memory fake_seal = hex"2e454bf4b1f037fabbc88665af1b10a413d03859bff26141e299ff24a94b2d50180c0824f309091336fb5d24a753edd094f9bada83185060a4a70e26afd63a0e0be098c6abad5e725423fc7256e53b51a891a619039a070eb09c4679dcb0d68713d8b92cf6fdc7035458cdd5dbceded84028751210a85f11b86d56209505b31f099d8b4843131a5f467c0140092717540c85564c907c0a1168c7a8091b60fe8a2568dcc69b5435a9d9f99ac6c62762df2f470926139de04618ca1bc04398088e07ba2c6861b0a8c3ccee2306d4e8dc02745a95591e10af36fc05b5faac0774fb01e7d34b5a5bb18531e57e088e49d0b7d98cbc8b6e5ad123b946d3f5920421a7";
-        vm.prank(0x23D4a8d26B777c1FDcBB74afa79CAdA1caF772F8);
-        aaDemo.setOwner(claimId);        
-        console.logBytes32(aaDemo.owner());
+    function test_Execute() public {
+        bytes32 claimId = sha256(abi.encodePacked("johnkenny6799@gmail.com"));
+        aaDemo.setOwner(claimId);
+        (bytes32 postStateDigest, bytes memory seal) = sealCall(claimId, 1 ether, "", 0);
 
         vm.prank(alice);
-        aaDemo.execute(payable(0x23D4a8d26B777c1FDcBB74afa79CAdA1caF772F8), 1 ether , "", claimId, 0xa3acc27117418996340b84e5a90f3ef4c49d22c79e44aad822ec9c313e1eb8e2,fake_seal);
+        aaDemo.execute(payable(jwtNonce), 1 ether, "", claimId, postStateDigest, seal);
         assertEq(payable(address(aaDemo)).balance, 4 ether);
-        assertEq(address(0x23D4a8d26B777c1FDcBB74afa79CAdA1caF772F8).balance, 6 ether);        
+        assertEq(jwtNonce.balance, 6 ether);
+        assertEq(aaDemo.nonce(), 1);
     }
-    
 
+    function test_ExecuteReplay() public {
+        bytes32 claimId = sha256(abi.encodePacked("johnkenny6799@gmail.com"));
+        aaDemo.setOwner(claimId);
+        (bytes32 postStateDigest, bytes memory seal) = sealCall(claimId, 1 ether, "", 0);
+
+        vm.prank(alice);
+        aaDemo.execute(payable(jwtNonce), 1 ether, "", claimId, postStateDigest, seal);
+
+        // The proof committed to the previous nonce.
+        vm.prank(alice);
+        vm.expectRevert(abi.encodeWithSelector(AADemo.InvalidClaim.selector, "Invalid proof"));
+        aaDemo.execute(payable(jwtNonce), 1 ether, "", claimId, postStateDigest, seal);
+        assertEq(jwtNonce.balance, 6 ether);
+    }
 
+    function test_ExecuteOtherCall() public {
+        bytes32 claimId = sha256(abi.encodePacked("johnkenny6799@gmail.com"));
+        aaDemo.setOwner(claimId);
+        (bytes32 postStateDigest, bytes memory seal) = sealCall(claimId, 1 ether, "", 0);
 
+        // The proof committed to the value and the calldata of the call.
+        vm.prank(alice);
+        vm.expectRevert(abi.encodeWithSelector(AADemo.InvalidClaim.selector, "Invalid proof"));
+        aaDemo.execute(payable(jwtNonce), 2 ether, "", claimId, postStateDigest, seal);
 
+        vm.prank(alice);
+        vm.expectRevert(abi.encodeWithSelector(AADemo.InvalidClaim.selector, "Invalid proof"));
+        aaDemo.execute(payable(jwtNonce), 1 ether, hex"00", claimId, postStateDigest, seal);
+        assertEq(aaDemo.nonce(), 0);
+    }
 }
@@ -0,0 +1,83 @@
+// Copyright 2024 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+pragma solidity ^0.8.20;
+
+import {RiscZeroCheats} from "risc0/RiscZeroCheats.sol";
+import {Test} from "forge-std/Test.sol";
+import {IRiscZeroVerifier} from "risc0/IRiscZeroVerifier.sol";
+import {AADemo} from "../contracts/AADemo.sol";
+import {AADemoFactory} from "../contracts/AADemoFactory.sol";
+
+contract AADemoFactoryTest is RiscZeroCheats, Test {
+    AADemoFactory public factory;
+    address public alice = makeAddr("alice");
+
+    function setUp() public {
+        IRiscZeroVerifier verifier = deployRiscZeroVerifier();
+        factory = new AADemoFactory(verifier);
+        vm.deal(alice, 5 ether);
+    }
+
+    function test_ReceiveBeforeDeployment() public {
+        bytes32 claimId = sha256(abi.encodePacked("bob@email.com"));
+        address account = factory.getAddress(claimId);
+        assertEq(account.code.length, 0);
+
+        vm.prank(alice);
+        payable(account).transfer(1 ether);
+
+        AADemo created = factory.createAccount(claimId);
+        assertEq(address(created), account);
+        assertEq(created.owner(), claimId);
+        assertEq(address(created.verifier()), address(factory.implementation().verifier()));
+        assertEq(account.balance, 1 ether);
+    }
+
+    function test_CreateAccountTwice() public {
+        bytes32 claimId = sha256(abi.encodePacked("bob@email.com"));
+        AADemo first = factory.createAccount(claimId);
+
+        vm.prank(alice);
+        AADemo second = factory.createAccount(claimId);
+        assertEq(address(second), address(first));
+        assertEq(second.owner(), claimId);
+    }
+
+    function test_OwnerCannotBeReplaced() public {
+        bytes32 claimId = sha256(abi.encodePacked("bob@email.com"));
+        AADemo account = factory.createAccount(claimId);
+
+        vm.prank(alice);
+        vm.expectRevert(abi.encodeWithSelector(AADemo.InvalidClaim.selector, "Owner already set"));
+        account.setOwner(sha256(abi.encodePacked("alice@email.com")));
+    }
+
+    function test_ImplementationLocked() public {
+        AADemo implementation = factory.implementation();
+        assertEq(implementation.owner(), factory.LOCKED_OWNER());
+
+        vm.prank(alice);
+        vm.expectRevert(abi.encodeWithSelector(AADemo.InvalidClaim.selector, "Owner already set"));
+        implementation.setOwner(sha256(abi.encodePacked("alice@email.com")));
+    }
+
+    function test_AccountsDifferByClaimId() public {
+        bytes32 bob = sha256(abi.encodePacked("bob@email.com"));
+        bytes32 carol = sha256(abi.encodePacked("carol@email.com"));
+        assertTrue(factory.getAddress(bob) != factory.getAddress(carol));
+    }
+}
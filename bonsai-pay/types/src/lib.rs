@@ -0,0 +1,467 @@
+// Copyright 2024 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Types shared between the guest, the host applications and the contracts.
+
+use alloy_primitives::{Address, FixedBytes, U256};
+use alloy_sol_types::SolValue;
+use thiserror::Error;
+
+/// Version of the journal layout committed by the guest.
+///
+/// Bump this whenever the fields of [`ClaimsData`] change.
+pub const JOURNAL_VERSION: u32 = 3;
+
+/// Flag set in the version word of failure journals.
+///
+/// Failure journals never hash to the digest expected by the contracts, so a
+/// proof of a rejected input cannot be used on-chain.
+pub const FAILURE_FLAG: u32 = 1 << 31;
+
+alloy_sol_types::sol! {
+    /// Journal committed by the `jwt_validator` guest.
+    ///
+    /// The contracts verify the proof against `sha256(abi.encode(version,
+    /// msg_sender, claim_id, value, func_hash, nonce, user_op_hash))`.
+    /// `value`, `func_hash` and `nonce` commit to the call the owner
+    /// authorizes: its value, the keccak256 hash of its calldata and the
+    /// nonce of the account, so a proof is used for that call only, once.
+    /// They are zero for claims, which make no call. `user_op_hash` is the
+    /// hash of the ERC-4337 user operation carrying the proof, and zero for
+    /// calls of `execute`.
+    #[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+    struct ClaimsData {
+        uint32 version;
+        address msg_sender;
+        bytes32 claim_id;
+        uint256 value;
+        bytes32 func_hash;
+        uint256 nonce;
+        bytes32 user_op_hash;
+    }
+
+    /// Journal committed by the `jwt_validator` and `zrp_validator` guests
+    /// when they reject their input. `error_code` is the code of the matching
+    /// `OidcErr`.
+    #[derive(Debug, PartialEq)]
+    struct FailureData {
+        uint32 version;
+        uint32 error_code;
+    }
+
+    /// Journal committed by the `zrp_validator` guest.
+    ///
+    /// The `ZRP` contract decodes it as `abi.decode(journal, (address,
+    /// bytes))`, so it carries no version word, and is only told apart from
+    /// the other journals by the image ID of the guest. `id` is the claim ID
+    /// of the email, which depositors pass to `ZRP.deposit`.
+    #[derive(Debug, PartialEq)]
+    struct ZrpClaimsData {
+        address beneficiary;
+        bytes id;
+    }
+}
+
+#[derive(Error, Debug, PartialEq)]
+pub enum JournalErr {
+    #[error("Failed to decode journal")]
+    DecodeError,
+    #[error("Unsupported journal version {0}")]
+    VersionError(u32),
+}
+
+impl ClaimsData {
+    /// Creates a journal with the current [`JOURNAL_VERSION`], authorizing
+    /// no call.
+    pub fn new(msg_sender: Address, claim_id: FixedBytes<32>) -> Self {
+        Self {
+            version: JOURNAL_VERSION,
+            msg_sender,
+            claim_id,
+            value: U256::ZERO,
+            func_hash: FixedBytes::ZERO,
+            nonce: U256::ZERO,
+            user_op_hash: FixedBytes::ZERO,
+        }
+    }
+
+    /// Authorizes the call sending `value` with the calldata of hash
+    /// `func_hash`, as the call number `nonce` of the account.
+    pub fn with_call(mut self, value: U256, func_hash: FixedBytes<32>, nonce: U256) -> Self {
+        self.value = value;
+        self.func_hash = func_hash;
+        self.nonce = nonce;
+        self
+    }
+
+    /// Binds the journal to the user operation of hash `user_op_hash`, as
+    /// computed by `EntryPoint.getUserOpHash`.
+    pub fn with_user_op_hash(mut self, user_op_hash: FixedBytes<32>) -> Self {
+        self.user_op_hash = user_op_hash;
+        self
+    }
+
+    /// Encodes the journal as committed by the guest.
+    pub fn encode(&self) -> Vec<u8> {
+        self.abi_encode()
+    }
+
+    /// Decodes a journal, rejecting any version other than
+    /// [`JOURNAL_VERSION`].
+    pub fn decode(journal: &[u8]) -> Result<Self, JournalErr> {
+        let claims = Self::abi_decode(journal, true).map_err(|_| JournalErr::DecodeError)?;
+        if claims.version != JOURNAL_VERSION {
+            return Err(JournalErr::VersionError(claims.version));
+        }
+        Ok(claims)
+    }
+}
+
+impl FailureData {
+    /// Creates a failure journal for the given error code.
+    pub fn new(error_code: u32) -> Self {
+        Self {
+            version: JOURNAL_VERSION | FAILURE_FLAG,
+            error_code,
+        }
+    }
+
+    /// Encodes the journal as committed by the guest.
+    pub fn encode(&self) -> Vec<u8> {
+        self.abi_encode()
+    }
+
+    /// Decodes a failure journal, rejecting any other journal.
+    pub fn decode(journal: &[u8]) -> Result<Self, JournalErr> {
+        let failure = Self::abi_decode(journal, true).map_err(|_| JournalErr::DecodeError)?;
+        if failure.version != JOURNAL_VERSION | FAILURE_FLAG || failure.encode() != journal {
+            return Err(JournalErr::DecodeError);
+        }
+        Ok(failure)
+    }
+}
+
+impl ZrpClaimsData {
+    pub fn new(beneficiary: Address, claim_id: FixedBytes<32>) -> Self {
+        Self {
+            beneficiary,
+            id: claim_id.to_vec(),
+        }
+    }
+
+    /// Encodes the journal as committed by the guest, matching
+    /// `abi.encode(beneficiary, id)`.
+    pub fn encode(&self) -> Vec<u8> {
+        self.abi_encode_params()
+    }
+
+    /// Decodes a journal, rejecting any encoding other than the one of
+    /// [`ZrpClaimsData::encode`].
+    pub fn decode(journal: &[u8]) -> Result<Self, JournalErr> {
+        let claims = Self::abi_decode_params(journal, true).map_err(|_| JournalErr::DecodeError)?;
+        if claims.encode() != journal {
+            return Err(JournalErr::DecodeError);
+        }
+        Ok(claims)
+    }
+
+    /// Returns the same claims in the layout of the `jwt_validator` journal.
+    ///
+    /// Fails if `id` is not a 32 bytes claim ID.
+    pub fn to_claims(&self) -> Result<ClaimsData, JournalErr> {
+        let claim_id = FixedBytes::try_from(&self.id[..]).map_err(|_| JournalErr::DecodeError)?;
+        Ok(ClaimsData::new(self.beneficiary, claim_id))
+    }
+}
+
+/// Output of the guest: either the claims, or the reason the input was
+/// rejected.
+#[derive(Debug, PartialEq)]
+pub enum GuestOutput {
+    Claims(ClaimsData),
+    ZrpClaims(ZrpClaimsData),
+    Failure(FailureData),
+}
+
+impl GuestOutput {
+    /// Decodes a journal of the `jwt_validator` guest, dispatching on its
+    /// version word.
+    pub fn decode(journal: &[u8]) -> Result<Self, JournalErr> {
+        let word = journal.get(..32).ok_or(JournalErr::DecodeError)?;
+        let version = u32::abi_decode(word, true).ok();
+
+        if version == Some(JOURNAL_VERSION | FAILURE_FLAG) {
+            return FailureData::decode(journal).map(Self::Failure);
+        }
+        ClaimsData::decode(journal).map(Self::Claims)
+    }
+
+    /// Decodes a journal of the `zrp_validator` guest. Its claims carry no
+    /// version word, so the two cannot be told apart from the journal alone:
+    /// a beneficiary could read as a version word.
+    pub fn decode_zrp(journal: &[u8]) -> Result<Self, JournalErr> {
+        // Claims hold at least the beneficiary, and the offset and length of
+        // the ID, so they never have the length of a failure journal.
+        if let Ok(failure) = FailureData::decode(journal) {
+            return Ok(Self::Failure(failure));
+        }
+        ZrpClaimsData::decode(journal).map(Self::ZrpClaims)
+    }
+}
+
+/// Returns the Solidity `Journal` library matching the layout of
+/// [`ClaimsData`].
+pub fn solidity_journal_library() -> String {
+    format!(
+        r#"// Copyright 2024 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+// This file is automatically generated by the bonsai-pay-types crate.
+
+pragma solidity ^0.8.20;
+
+library Journal {{
+    uint32 internal constant VERSION = {JOURNAL_VERSION};
+
+    struct ClaimsData {{
+        uint32 version;
+        address msgSender;
+        bytes32 claimId;
+        uint256 value;
+        bytes32 funcHash;
+        uint256 nonce;
+        bytes32 userOpHash;
+    }}
+
+    function encode(
+        address msgSender,
+        bytes32 claimId,
+        uint256 value,
+        bytes32 funcHash,
+        uint256 nonce,
+        bytes32 userOpHash
+    ) internal pure returns (bytes memory) {{
+        return abi.encode(
+            ClaimsData({{
+                version: VERSION,
+                msgSender: msgSender,
+                claimId: claimId,
+                value: value,
+                funcHash: funcHash,
+                nonce: nonce,
+                userOpHash: userOpHash
+            }})
+        );
+    }}
+
+    /// Digest of the journal of a claim, authorizing no call.
+    function digest(address msgSender, bytes32 claimId) internal pure returns (bytes32) {{
+        return digest(msgSender, claimId, 0, bytes32(0), 0, bytes32(0));
+    }}
+
+    /// Digest of the journal authorizing the call of `msgSender` sending `value` with the calldata
+    /// of hash `funcHash`, as the call number `nonce` of the account, made by `execute`.
+    function digest(address msgSender, bytes32 claimId, uint256 value, bytes32 funcHash, uint256 nonce)
+        internal
+        pure
+        returns (bytes32)
+    {{
+        return digest(msgSender, claimId, value, funcHash, nonce, bytes32(0));
+    }}
+
+    /// Digest of the journal authorizing the same call, made by the user operation of hash
+    /// `userOpHash`.
+    function digest(
+        address msgSender,
+        bytes32 claimId,
+        uint256 value,
+        bytes32 funcHash,
+        uint256 nonce,
+        bytes32 userOpHash
+    ) internal pure returns (bytes32) {{
+        return sha256(encode(msgSender, claimId, value, funcHash, nonce, userOpHash));
+    }}
+}}
+"#
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use alloy_primitives::{Address, Bytes, FixedBytes, U256};
+    use alloy_sol_types::SolValue;
+
+    use super::{
+        ClaimsData, FailureData, GuestOutput, JournalErr, ZrpClaimsData, FAILURE_FLAG,
+        JOURNAL_VERSION,
+    };
+
+    #[test]
+    fn test_journal_round_trip() {
+        let claims = ClaimsData::new(Address::repeat_byte(0x11), FixedBytes::repeat_byte(0x22));
+        let journal = claims.encode();
+
+        // Matches `abi.encode(uint32 version, address msgSender, bytes32 claimId,
+        // uint256 value, bytes32 funcHash, uint256 nonce, bytes32 userOpHash)`.
+        assert_eq!(journal.len(), 7 * 32);
+        assert_eq!(journal[28..32], JOURNAL_VERSION.to_be_bytes());
+        assert_eq!(journal[3 * 32..], [0; 4 * 32]);
+
+        let decoded = ClaimsData::decode(&journal).unwrap();
+        assert_eq!(decoded.msg_sender, claims.msg_sender);
+        assert_eq!(decoded.claim_id, claims.claim_id);
+    }
+
+    #[test]
+    fn test_journal_with_call() {
+        let claims = ClaimsData::new(Address::repeat_byte(0x11), FixedBytes::repeat_byte(0x22))
+            .with_call(
+                U256::from(1000),
+                FixedBytes::repeat_byte(0x33),
+                U256::from(7),
+            )
+            .with_user_op_hash(FixedBytes::repeat_byte(0x44));
+        let journal = claims.encode();
+
+        assert_eq!(
+            journal,
+            (
+                JOURNAL_VERSION,
+                Address::repeat_byte(0x11),
+                FixedBytes::<32>::repeat_byte(0x22),
+                U256::from(1000),
+                FixedBytes::<32>::repeat_byte(0x33),
+                U256::from(7),
+                FixedBytes::<32>::repeat_byte(0x44)
+            )
+                .abi_encode()
+        );
+        assert_eq!(ClaimsData::decode(&journal).unwrap(), claims);
+    }
+
+    #[test]
+    fn test_decode_guest_output() {
+        let claims = ClaimsData::new(Address::repeat_byte(0x11), FixedBytes::repeat_byte(0x22));
+        let failure = FailureData::new(5);
+
+        assert_eq!(
+            GuestOutput::decode(&claims.encode()).unwrap(),
+            GuestOutput::Claims(claims)
+        );
+        assert_eq!(
+            GuestOutput::decode(&failure.encode()).unwrap(),
+            GuestOutput::Failure(failure)
+        );
+        assert!(GuestOutput::decode(&[]).is_err());
+    }
+
+    #[test]
+    fn test_zrp_journal_round_trip() {
+        let zrp = ZrpClaimsData::new(Address::repeat_byte(0x11), FixedBytes::repeat_byte(0x22));
+        let journal = zrp.encode();
+
+        // Matches `abi.encode(address beneficiary, bytes id)`.
+        let id = Bytes::from(vec![0x22; 32]);
+        assert_eq!(
+            journal,
+            (Address::repeat_byte(0x11), id).abi_encode_params()
+        );
+        assert_eq!(journal.len(), 4 * 32);
+
+        assert_eq!(
+            GuestOutput::decode_zrp(&journal).unwrap(),
+            GuestOutput::ZrpClaims(zrp.clone())
+        );
+        assert!(GuestOutput::decode(&journal).is_err());
+        assert_eq!(
+            zrp.to_claims().unwrap(),
+            ClaimsData::new(Address::repeat_byte(0x11), FixedBytes::repeat_byte(0x22))
+        );
+
+        // Versioned journals are never taken for ZRP journals.
+        let claims = ClaimsData::new(Address::ZERO, FixedBytes::ZERO);
+        assert_eq!(
+            GuestOutput::decode(&claims.encode()).unwrap(),
+            GuestOutput::Claims(claims)
+        );
+        let failure = FailureData::new(5);
+        assert_eq!(
+            GuestOutput::decode_zrp(&failure.encode()).unwrap(),
+            GuestOutput::Failure(failure)
+        );
+    }
+
+    #[test]
+    fn test_zrp_beneficiary_read_as_version() {
+        // A beneficiary whose word matches a version word is still a ZRP
+        // journal.
+        for beneficiary in [JOURNAL_VERSION, JOURNAL_VERSION | FAILURE_FLAG] {
+            let mut address = [0; 20];
+            address[16..].copy_from_slice(&beneficiary.to_be_bytes());
+            let zrp = ZrpClaimsData::new(Address::from(address), FixedBytes::repeat_byte(0x22));
+
+            assert_eq!(
+                GuestOutput::decode_zrp(&zrp.encode()).unwrap(),
+                GuestOutput::ZrpClaims(zrp)
+            );
+        }
+    }
+
+    #[test]
+    fn test_reject_unknown_version() {
+        let mut claims = ClaimsData::new(Address::ZERO, FixedBytes::ZERO);
+        claims.version = JOURNAL_VERSION + 1;
+
+        assert_eq!(
+            ClaimsData::decode(&claims.abi_encode()).unwrap_err(),
+            JournalErr::VersionError(JOURNAL_VERSION + 1)
+        );
+    }
+
+    #[test]
+    fn test_reject_legacy_journal() {
+        // Unversioned journals only carry `(address msg_sender, bytes32 claim_id)`.
+        let legacy = (Address::ZERO, FixedBytes::<32>::ZERO).abi_encode();
+        assert!(ClaimsData::decode(&legacy).is_err());
+
+        // Version 1 journals carry no call.
+        let v1 = (1u32, Address::ZERO, FixedBytes::<32>::ZERO).abi_encode();
+        assert!(ClaimsData::decode(&v1).is_err());
+
+        // Version 2 journals carry no user operation hash.
+        let v2 = (
+            2u32,
+            Address::ZERO,
+            FixedBytes::<32>::ZERO,
+            U256::ZERO,
+            FixedBytes::<32>::ZERO,
+            U256::ZERO,
+        )
+            .abi_encode();
+        assert!(ClaimsData::decode(&v2).is_err());
+    }
+}
@@ -3,6 +3,9 @@ import { AaContext } from "../AaContext";
 import { toast } from "react-toastify";
 import { SignInWithGoogle } from "./SignInWithGoogle";
 import Send from "./Send";
+import { useContractRead } from "wagmi";
+import { parseAbi } from "viem";
+import { callNonce, PAYMENT_AMOUNT } from "../libs/utils";
 
 interface DepositProps {}
 
@@ -11,6 +14,15 @@ const Deposit: React.FC<DepositProps> = () => {
   const {address, debounceDest,  setAddress, jwtExists, walletExists,email,nonceExists } = useContext(AaContext); 
    const [currentStep, setCurrentStep] = useState<number>(1);
 
+  // An account that is not deployed yet has not made any call.
+  const { data: accountNonce } = useContractRead({
+    address: address,
+    abi: parseAbi(["function nonce() view returns (uint256)"]),
+    functionName: "nonce",
+    enabled: Boolean(address),
+    watch: true,
+  });
+
 
   const { VITE_API_HOST } = import.meta.env;
 
@@ -124,7 +136,10 @@ const Deposit: React.FC<DepositProps> = () => {
         <>
         <h4>Confirm the Destination address by signing in</h4>
         <h5>Destination Address: <br/>  {debounceDest}</h5>
-        <SignInWithGoogle disabled={jwtExists} />
+        <SignInWithGoogle
+          disabled={jwtExists}
+          nonce={callNonce(debounceDest, PAYMENT_AMOUNT, accountNonce ?? 0n)}
+        />
       </>
       )
       case 5: return(
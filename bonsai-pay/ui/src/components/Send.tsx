@@ -4,6 +4,7 @@ import { AaContext } from "../AaContext";
 import { useBalance } from "wagmi";
 import { toast } from "react-toastify";
 import { isAddress } from 'viem'
+import { PAYMENT_AMOUNT } from "../libs/utils";
 
 
 interface SendProps {
@@ -59,12 +60,21 @@ const Send: React.FC<SendProps> = ({currentStep,setCurrentStep}) => {
         headers: {
           "X-Auth-Token": jwt,
           "X-DEST": debounceDest ,
+          "X-AMOUNT": PAYMENT_AMOUNT,
         },
       });
 
       if (response.ok) {
         await response.body;
         toast.success("Your tx has been sent")
+      } else if (response.status === 422) {
+        // The prover rejected the token, e.g. an expired or malformed JWT.
+        const { error } = await response.json();
+        toast.error(`Your sign-in could not be verified: ${error}`);
+      } else if (response.status === 400) {
+        // The sign-in does not authorize this payment, e.g. the destination changed.
+        const { error } = await response.json();
+        toast.error(`Sign in again to confirm this payment: ${error}`);
       } else {
         throw new Error("Response not OK");
       }
@@ -9,7 +9,7 @@ declare global {
   }
 }
 
-export const SignInWithGoogle = ({ disabled }) => {
+export const SignInWithGoogle = ({ disabled, nonce = undefined }) => {
   const { VITE_GOOGLE_CLIENT_ID } = import.meta.env;
   const { debounceDest } = useContext(AaContext); 
 
@@ -27,7 +27,7 @@ export const SignInWithGoogle = ({ disabled }) => {
         window.google.accounts.id.initialize({
           client_id: VITE_GOOGLE_CLIENT_ID,
           callback: handleCredentialResponse,
-          nonce: debounceDest,
+          nonce: nonce ?? debounceDest,
           scope: "email",
           auto_select: false,
           state: "hello",
@@ -70,7 +70,7 @@ export const SignInWithGoogle = ({ disabled }) => {
       }
       initGsi();
     };
-  }, [VITE_GOOGLE_CLIENT_ID, debounceDest]);
+  }, [VITE_GOOGLE_CLIENT_ID, debounceDest, nonce]);
 
   const handleCredentialResponse = async (response) => {
     const { credential} = response;
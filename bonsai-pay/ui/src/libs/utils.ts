@@ -1,3 +1,20 @@
+import { Address, encodeAbiParameters, keccak256, parseAbiParameters, parseEther } from "viem";
+
+// Amount of Ether sent by a payment, passed to the server as `X-AMOUNT`.
+export const PAYMENT_AMOUNT = "0.00001";
+
+// Nonce of a sign-in authorizing the account's call number `accountNonce`,
+// sending `amount` Ether to `dest`. The guest only accepts the token for this call.
+export const callNonce = (dest: Address, amount: string, accountNonce: bigint) =>
+  keccak256(
+    encodeAbiParameters(parseAbiParameters("address, uint256, bytes32, uint256"), [
+      dest,
+      parseEther(amount),
+      keccak256("0x"),
+      accountNonce,
+    ])
+  );
+
 export const clearCookies = () => {
   document.cookie.split(";").map((cookie) => {
     const eqPos = cookie.indexOf("=");